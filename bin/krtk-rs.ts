@@ -27,6 +27,8 @@ const krtkStack = new KrtkRsStack(app, 'KrtkRsStack', {
   certificateArn: certStack.certificate.certificateArn,
   authCertificateArn: certStack.authCertificate.certificateArn,
   googleApiKeySecret: secretsStack.googleApiSecret,
+  linkCookieSecret: secretsStack.linkCookieSecret,
+  cursorSecret: secretsStack.cursorSecret,
   crossRegionReferences: true,
 });
 
@@ -1,18 +1,43 @@
 use aws_sdk_dynamodb::types::AttributeValue;
 use chrono::Utc;
 use sha2::{Digest, Sha256};
+use shared::auth::{Scope, DEFAULT_RATE_LIMIT_PER_MINUTE};
+
+/// Characters of a key shown to its owner (`krtk_3f9aQ2x`), as manage_keys stores them.
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// The outcome of checking a presented key.
+#[derive(Debug)]
+pub enum Verification {
+    Valid(VerifiedKey),
+    /// No key with this hash exists.
+    Unknown,
+    /// A real key past its expiry. It still names its owner, so the rejection can be
+    /// filed where they will see it.
+    Expired { owner_id: Option<String>, prefix: Option<String> },
+}
+
+/// A key that verified: whose it is and what it may do.
+#[derive(Debug)]
+pub struct VerifiedKey {
+    /// Identifies the key's rate limit bucket.
+    pub key_hash: String,
+    pub owner_id: String,
+    pub scopes: Vec<Scope>,
+    pub rate_limit_per_minute: u32,
+}
 
 /// Verify an API key against the key table.
 ///
-/// Returns `Ok(Some(owner_id))` on success, `Ok(None)` if the key is invalid or
-/// expired, and `Err` only on infrastructure failures.
+/// Returns `Ok(Verification::Valid(key))` on success, another [`Verification`] if the key
+/// is unknown or expired, and `Err` only on infrastructure failures.
 ///
 /// Side effect: bumps `LastUsedAt` best-effort when the stored value is >1h old.
 pub async fn verify_api_key(
     client: &aws_sdk_dynamodb::Client,
     table_name: &str,
     raw_key: &str,
-) -> Result<Option<String>, ApiKeyError> {
+) -> Result<Verification, ApiKeyError> {
     let key_hash = hash_key(raw_key);
 
     // GetItem by KeyHash
@@ -26,7 +51,7 @@ pub async fn verify_api_key(
 
     let item = match result.item {
         Some(item) => item,
-        None => return Ok(None), // Key not found
+        None => return Ok(Verification::Unknown),
     };
 
     // Check ExpiresAt — absent means never expires, otherwise must be in the future.
@@ -43,7 +68,11 @@ pub async fn verify_api_key(
         && let Ok(expires_epoch) = expires_at_str.parse::<i64>()
         && expires_epoch <= Utc::now().timestamp()
     {
-        return Ok(None); // Expired
+        let string = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+        return Ok(Verification::Expired {
+            owner_id: string("OwnerId"),
+            prefix: string("KeyPrefix"),
+        });
     }
 
     // Extract OwnerId
@@ -53,10 +82,61 @@ pub async fn verify_api_key(
         .map(|s| s.to_string())
         .ok_or_else(|| ApiKeyError::Storage("Missing OwnerId in key record".to_string()))?;
 
+    let scopes = scopes_of(item.get("Scopes"));
+
+    // A rotated key keeps verifying until the ExpiresAt rotation gave it, so the job
+    // holding it can move to its successor. Logged so a caller still on it shows up.
+    if let Some(successor) = item.get("ReplacedBy").and_then(|v| v.as_s().ok()) {
+        tracing::info!("Rotated key used for owner={owner_id}; successor is {successor}");
+    }
+
+    // Keys minted before per-key limits get the default.
+    let rate_limit_per_minute = item
+        .get("RateLimitPerMinute")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<u32>().ok())
+        .filter(|&limit| limit > 0)
+        .unwrap_or(DEFAULT_RATE_LIMIT_PER_MINUTE);
+
     // Bump LastUsedAt best-effort (only if stored value is >1h old)
     bump_last_used_if_stale(client, table_name, &key_hash, &item).await;
 
-    Ok(Some(owner_id))
+    Ok(Verification::Valid(VerifiedKey { key_hash, owner_id, scopes, rate_limit_per_minute }))
+}
+
+/// What to call a presented key that matched nothing: the part an owner would
+/// recognise from their key list, and never the whole secret in case it is one.
+pub fn presented_prefix(raw_key: &str) -> String {
+    raw_key.chars().take(DISPLAY_PREFIX_LEN).collect()
+}
+
+/// The scopes stored on a key item.
+///
+/// A key with no `Scopes` attribute was minted before scopes existed, when every key
+/// could do everything; it keeps that until the owner replaces it. A name this build
+/// does not recognise is dropped rather than failing the key, so a scope added later
+/// cannot lock out keys that also hold the ones known here.
+pub fn scopes_of(attribute: Option<&AttributeValue>) -> Vec<Scope> {
+    let Some(attribute) = attribute else {
+        return Scope::ALL.to_vec();
+    };
+    let names = match attribute {
+        AttributeValue::Ss(names) => names.as_slice(),
+        _ => {
+            tracing::warn!("Scopes attribute is not a string set; granting nothing");
+            &[]
+        }
+    };
+    names
+        .iter()
+        .filter_map(|name| match Scope::parse(name) {
+            Ok(scope) => Some(scope),
+            Err(_) => {
+                tracing::warn!("Ignoring unknown scope {name:?} on key");
+                None
+            }
+        })
+        .collect()
 }
 
 /// SHA-256 hash the raw key, returning the hex-encoded digest.
@@ -153,6 +233,31 @@ mod tests {
         assert!(!should_bump_last_used(Some(ten_min_ago)));
     }
 
+    #[test]
+    fn test_scopes_are_read_from_the_string_set() {
+        let stored = AttributeValue::Ss(vec!["links:read".into(), "analytics:read".into()]);
+        assert_eq!(scopes_of(Some(&stored)), vec![Scope::LinksRead, Scope::AnalyticsRead]);
+    }
+
+    #[test]
+    fn test_keys_from_before_scopes_keep_full_access() {
+        assert_eq!(scopes_of(None), Scope::ALL.to_vec());
+    }
+
+    #[test]
+    fn test_unknown_or_malformed_scopes_grant_nothing_extra() {
+        let stored = AttributeValue::Ss(vec!["links:read".into(), "links:*".into()]);
+        assert_eq!(scopes_of(Some(&stored)), vec![Scope::LinksRead]);
+        assert!(scopes_of(Some(&AttributeValue::S("links:read".into()))).is_empty());
+    }
+
+    #[test]
+    fn test_presented_prefix_is_only_the_display_part() {
+        assert_eq!(presented_prefix("krtk_3f9aQ2xSECRETSECRETSECRET"), "krtk_3f9aQ2x");
+        assert_eq!(presented_prefix("short"), "short");
+        assert_eq!(presented_prefix("ключключключключ"), "ключключключ");
+    }
+
     #[test]
     fn test_expires_at_in_past_rejects() {
         // Simulating the expiry check logic
@@ -1,5 +1,7 @@
 use lambda_runtime::{run, service_fn, Error, LambdaEvent};
 use serde::{Deserialize, Serialize};
+use shared::audit::{AuditAction, AuditEvent, AuditLog, ClientAddress};
+use shared::auth::Scope;
 use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
@@ -7,6 +9,10 @@ use std::sync::Arc;
 mod apikey;
 mod jwks;
 mod jwt;
+mod ratelimit;
+
+use apikey::Verification;
+use ratelimit::{Decision, RateLimiter};
 
 // --- Event / Response types for HTTP API v2 Lambda REQUEST authorizer ---
 
@@ -27,6 +33,17 @@ pub struct RequestContext {
 pub struct HttpContext {
     pub method: String,
     pub path: String,
+    #[serde(default, rename = "sourceIp")]
+    pub source_ip: Option<String>,
+}
+
+impl AuthorizerEvent {
+    fn client_address(&self) -> ClientAddress {
+        ClientAddress::new(
+            self.request_context.http.source_ip.as_deref(),
+            self.headers.get("x-forwarded-for").map(String::as_str),
+        )
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -42,15 +59,19 @@ pub struct AuthorizerResponse {
 pub struct AuthorizerContext {
     pub owner_id: String,
     pub auth_method: String,
+    /// Space-separated; read back by `shared::auth::require_scope`. Context values
+    /// must be flat, so this cannot be an array.
+    pub scopes: String,
 }
 
 impl AuthorizerResponse {
-    pub fn allow(owner_id: String, auth_method: &str) -> Self {
+    pub fn allow(owner_id: String, auth_method: &str, scopes: &[Scope]) -> Self {
         Self {
             is_authorized: true,
             context: Some(AuthorizerContext {
                 owner_id,
                 auth_method: auth_method.to_string(),
+                scopes: Scope::join(scopes),
             }),
         }
     }
@@ -72,6 +93,8 @@ struct AppState {
     cognito_region: String,
     dynamodb_client: aws_sdk_dynamodb::Client,
     api_key_table: String,
+    rate_limiter: RateLimiter,
+    audit_log: AuditLog,
 }
 
 async fn function_handler(
@@ -87,12 +110,12 @@ async fn function_handler(
         .or_else(|| headers.get("Authorization"))
         && let Some(token) = auth_header.strip_prefix("Bearer ")
     {
-        return handle_jwt(state, token.trim()).await;
+        return handle_jwt(state, token.trim(), &event.payload).await;
     }
 
     // Try API key path
     if let Some(api_key) = headers.get("x-api-key").or_else(|| headers.get("X-Api-Key")) {
-        return handle_api_key(state, api_key).await;
+        return handle_api_key(state, api_key, &event.payload).await;
     }
 
     // No credential presented
@@ -100,7 +123,11 @@ async fn function_handler(
     Ok(AuthorizerResponse::deny())
 }
 
-async fn handle_jwt(state: &AppState, token: &str) -> Result<AuthorizerResponse, Error> {
+async fn handle_jwt(
+    state: &AppState,
+    token: &str,
+    event: &AuthorizerEvent,
+) -> Result<AuthorizerResponse, Error> {
     let issuer = format!(
         "https://cognito-idp.{}.amazonaws.com/{}",
         state.cognito_region, state.cognito_pool_id
@@ -116,23 +143,75 @@ async fn handle_jwt(state: &AppState, token: &str) -> Result<AuthorizerResponse,
     {
         Ok(claims) => {
             tracing::info!("JWT verified for sub={}", claims.sub);
-            Ok(AuthorizerResponse::allow(claims.sub, "jwt"))
+            // A session is the user themselves, so it can do anything their keys can.
+            Ok(AuthorizerResponse::allow(claims.sub, "jwt", &Scope::ALL))
         }
         Err(e) => {
             tracing::warn!("JWT verification failed: {e}");
+            // Unattributed: the claims of a token that did not verify are whatever the
+            // caller wrote, so its `sub` cannot say whose log this belongs in.
+            state
+                .audit_log
+                .record(
+                    AuditEvent::new(AuditAction::JwtRejected, None, event.client_address())
+                        .with_detail(e.to_string()),
+                )
+                .await;
             Ok(AuthorizerResponse::deny())
         }
     }
 }
 
-async fn handle_api_key(state: &AppState, key: &str) -> Result<AuthorizerResponse, Error> {
-    match apikey::verify_api_key(&state.dynamodb_client, &state.api_key_table, key).await {
-        Ok(Some(owner_id)) => {
-            tracing::info!("API key verified for owner={owner_id}");
-            Ok(AuthorizerResponse::allow(owner_id, "apikey"))
+async fn handle_api_key(
+    state: &AppState,
+    raw_key: &str,
+    event: &AuthorizerEvent,
+) -> Result<AuthorizerResponse, Error> {
+    match apikey::verify_api_key(&state.dynamodb_client, &state.api_key_table, raw_key).await {
+        Ok(Verification::Valid(key)) => {
+            let scopes = Scope::join(&key.scopes);
+            tracing::info!("API key verified for owner={} scopes=[{scopes}]", key.owner_id);
+
+            // A deny from a SIMPLE authorizer reaches the caller as a 403; there is no
+            // way to answer 429 from here, so the log is where the reason lives.
+            match state.rate_limiter.check(&key.key_hash, key.rate_limit_per_minute).await {
+                Ok(Decision::Allowed) => {}
+                Ok(Decision::Limited(reason)) => {
+                    tracing::warn!(
+                        "API key rate limited for owner={} limit={}/min: {reason}",
+                        key.owner_id,
+                        key.rate_limit_per_minute
+                    );
+                    return Ok(AuthorizerResponse::deny());
+                }
+                // Fails open: the limiter guards against one key crowding out the
+                // rest, and the stage throttle still applies. Denying every key while
+                // its table is unavailable would be the larger outage.
+                Err(e) => tracing::error!("Rate limit check failed, allowing: {e:?}"),
+            }
+
+            Ok(AuthorizerResponse::allow(key.owner_id, "apikey", &key.scopes))
+        }
+        Ok(Verification::Unknown) => {
+            tracing::warn!("API key verification failed: unknown key");
+            state
+                .audit_log
+                .record(
+                    AuditEvent::new(AuditAction::ApiKeyRejected, None, event.client_address())
+                        .with_auth_method("apikey")
+                        .with_target(apikey::presented_prefix(raw_key))
+                        .with_detail("unknown key"),
+                )
+                .await;
+            Ok(AuthorizerResponse::deny())
         }
-        Ok(None) => {
-            tracing::warn!("API key verification failed");
+        Ok(Verification::Expired { owner_id, prefix }) => {
+            tracing::warn!("API key verification failed: expired key");
+            let mut audit = AuditEvent::new(AuditAction::ApiKeyRejected, owner_id.as_deref(), event.client_address())
+                .with_auth_method("apikey")
+                .with_detail("expired key");
+            audit.target = prefix;
+            state.audit_log.record(audit).await;
             Ok(AuthorizerResponse::deny())
         }
         Err(e) => {
@@ -154,6 +233,8 @@ async fn main() -> Result<(), Error> {
     let cognito_client_id = env::var("COGNITO_CLIENT_ID").expect("COGNITO_CLIENT_ID not set");
     let cognito_region = env::var("COGNITO_REGION").expect("COGNITO_REGION not set");
     let api_key_table = env::var("API_KEY_TABLE_NAME").expect("API_KEY_TABLE_NAME not set");
+    let rate_limit_table = env::var("RATE_LIMIT_TABLE_NAME").expect("RATE_LIMIT_TABLE_NAME not set");
+    let audit_table = env::var("AUDIT_TABLE_NAME").expect("AUDIT_TABLE_NAME not set");
 
     let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
@@ -163,6 +244,8 @@ async fn main() -> Result<(), Error> {
         cognito_region, cognito_pool_id
     );
     let jwks_cache = jwks::JwksCache::new(&jwks_url);
+    let rate_limiter = RateLimiter::new(&rate_limit_table, dynamodb_client.clone());
+    let audit_log = AuditLog::new(&audit_table, dynamodb_client.clone());
 
     let state = Arc::new(AppState {
         jwks_cache,
@@ -171,6 +254,8 @@ async fn main() -> Result<(), Error> {
         cognito_region,
         dynamodb_client,
         api_key_table,
+        rate_limiter,
+        audit_log,
     });
 
     run(service_fn(|event| {
@@ -0,0 +1,186 @@
+//! Per-key token bucket, so one leaked or buggy API key cannot spend the whole stage
+//! throttle on its own.
+//!
+//! Each key's bucket holds up to its per-minute limit in tokens and refills at that
+//! many per minute; a request takes one. The bucket lives in DynamoDB because many
+//! authorizer instances serve the same key at once, and any in-memory count would be
+//! per instance. Correctness across them comes from optimistic concurrency: the write
+//! is conditional on the bucket still being the one the decision was made from, and
+//! an instance that loses the race recomputes from the winner's state.
+//!
+//! Items are keyed `KeyId` (the key hash) and carry `PurgeAt` for the table's TTL. A
+//! bucket idle long enough to be purged would have refilled completely anyway, so
+//! losing it changes nothing.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValuesOnConditionCheckFailure};
+
+use crate::apikey::ApiKeyError;
+
+/// Lost races before giving up. Only reachable when this many requests on one key land
+/// in the same few milliseconds, which is itself a burst worth refusing.
+const MAX_ATTEMPTS: usize = 5;
+const PURGE_AFTER_SECS: i64 = 60 * 60;
+
+/// A bucket as stored: tokens left as of `refilled_at_ms`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bucket {
+    pub tokens: f64,
+    pub refilled_at_ms: i64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    /// Refused, with why, for the authorizer's log.
+    Limited(&'static str),
+}
+
+/// Takes one token from `bucket` at `now_ms`, or `None` if there is not a whole one.
+///
+/// A missing bucket is a full one: a key that has not been used lately has its whole
+/// burst available. Time running backwards between instances counts as no time passing
+/// rather than as negative refill.
+pub fn take(per_minute: u32, bucket: Option<Bucket>, now_ms: i64) -> Option<Bucket> {
+    let capacity = f64::from(per_minute);
+    let available = match bucket {
+        None => capacity,
+        Some(bucket) => {
+            let elapsed_ms = (now_ms - bucket.refilled_at_ms).max(0) as f64;
+            (bucket.tokens + elapsed_ms * capacity / 60_000.0).min(capacity)
+        }
+    };
+    (available >= 1.0).then_some(Bucket { tokens: available - 1.0, refilled_at_ms: now_ms })
+}
+
+fn bucket_of(item: &HashMap<String, AttributeValue>) -> Option<Bucket> {
+    let tokens = item.get("Tokens")?.as_n().ok()?.parse().ok()?;
+    let refilled_at_ms = item.get("RefilledAt")?.as_n().ok()?.parse().ok()?;
+    Some(Bucket { tokens, refilled_at_ms })
+}
+
+pub struct RateLimiter {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+}
+
+impl RateLimiter {
+    pub fn new(table_name: &str, client: aws_sdk_dynamodb::Client) -> Self {
+        Self {
+            client,
+            table_name: table_name.to_string(),
+        }
+    }
+
+    /// Spends one of `key_hash`'s tokens, if it has one.
+    pub async fn check(&self, key_hash: &str, per_minute: u32) -> Result<Decision, ApiKeyError> {
+        let stored = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("KeyId", AttributeValue::S(key_hash.to_string()))
+            .consistent_read(true)
+            .send()
+            .await
+            .map_err(|e| ApiKeyError::Storage(e.to_string()))?;
+        let mut bucket = stored.item().and_then(bucket_of);
+
+        for _ in 0..MAX_ATTEMPTS {
+            let now_ms = chrono::Utc::now().timestamp_millis();
+            let Some(next) = take(per_minute, bucket, now_ms) else {
+                return Ok(Decision::Limited("bucket empty"));
+            };
+
+            let update = self
+                .client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("KeyId", AttributeValue::S(key_hash.to_string()))
+                .update_expression("SET Tokens = :tokens, RefilledAt = :now, PurgeAt = :purge")
+                .expression_attribute_values(":tokens", AttributeValue::N(next.tokens.to_string()))
+                .expression_attribute_values(":now", AttributeValue::N(now_ms.to_string()))
+                .expression_attribute_values(
+                    ":purge",
+                    AttributeValue::N((now_ms / 1_000 + PURGE_AFTER_SECS).to_string()),
+                )
+                .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld);
+            // Conditional on the state the decision was made from, so two instances
+            // cannot both spend the same last token. Both attributes, since two writes
+            // can land in the same millisecond.
+            let update = match bucket {
+                Some(seen) => update
+                    .condition_expression("RefilledAt = :seen AND Tokens = :seen_tokens")
+                    .expression_attribute_values(":seen", AttributeValue::N(seen.refilled_at_ms.to_string()))
+                    .expression_attribute_values(":seen_tokens", AttributeValue::N(seen.tokens.to_string())),
+                None => update.condition_expression("attribute_not_exists(KeyId)"),
+            };
+
+            match update.send().await {
+                Ok(_) => return Ok(Decision::Allowed),
+                Err(SdkError::ServiceError(err)) => match err.err() {
+                    // Another instance wrote first; its state comes back with the
+                    // failure, so try again from there without another read.
+                    UpdateItemError::ConditionalCheckFailedException(lost) => {
+                        bucket = lost.item().and_then(bucket_of);
+                    }
+                    other => return Err(ApiKeyError::Storage(other.to_string())),
+                },
+                Err(e) => return Err(ApiKeyError::Storage(e.to_string())),
+            }
+        }
+
+        Ok(Decision::Limited("too many concurrent requests"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_739_035_776_000;
+
+    #[test]
+    fn an_unused_key_has_its_whole_burst() {
+        let mut bucket = None;
+        for _ in 0..60 {
+            bucket = take(60, bucket, NOW);
+            assert!(bucket.is_some());
+        }
+        assert_eq!(take(60, bucket, NOW), None, "the 61st request in the same instant is refused");
+    }
+
+    #[test]
+    fn tokens_come_back_at_the_per_minute_rate() {
+        let empty = Some(Bucket { tokens: 0.0, refilled_at_ms: NOW });
+        assert_eq!(take(60, empty, NOW + 500), None, "half a token is not a token");
+        let next = take(60, empty, NOW + 1_000).expect("one second refills one token at 60/min");
+        assert!(next.tokens.abs() < 1e-9);
+        assert_eq!(next.refilled_at_ms, NOW + 1_000);
+    }
+
+    #[test]
+    fn refill_stops_at_the_limit() {
+        let idle = Some(Bucket { tokens: 0.0, refilled_at_ms: NOW - 3_600_000 });
+        let next = take(60, idle, NOW).unwrap();
+        assert!((next.tokens - 59.0).abs() < 1e-9, "an hour idle must not bank 3600 tokens");
+    }
+
+    #[test]
+    fn a_clock_behind_the_stored_state_refills_nothing() {
+        let bucket = Some(Bucket { tokens: 0.5, refilled_at_ms: NOW });
+        assert_eq!(take(60, bucket, NOW - 10_000), None);
+    }
+
+    #[test]
+    fn reads_a_stored_bucket() {
+        let item = HashMap::from([
+            ("KeyId".to_string(), AttributeValue::S("hash".into())),
+            ("Tokens".to_string(), AttributeValue::N("12.5".into())),
+            ("RefilledAt".to_string(), AttributeValue::N(NOW.to_string())),
+        ]);
+        assert_eq!(bucket_of(&item), Some(Bucket { tokens: 12.5, refilled_at_ms: NOW }));
+    }
+}
@@ -0,0 +1,132 @@
+use lambda_http::http::StatusCode;
+use lambda_http::{run, service_fn, tracing, Error, IntoResponse, Request, RequestPayloadExt};
+
+use shared::audit::{AuditAction, AuditEvent, AuditLog};
+use shared::auth::{owner_from_request, require_scope, Scope};
+use shared::core::{BatchShortenRequest, BatchShortenResponse, ShortenUrlRequest, UrlShortener, MAX_BATCH_SIZE};
+use shared::error::AppError;
+use shared::reputation::ReputationPolicy;
+use shared::response::{error_response, json_response};
+use shared::safe_browsing::SafeBrowsing;
+use shared::secrets::SecretCache;
+use shared::url_info::UrlInfo;
+
+use std::env;
+
+// The main bit of code that will run every time this function is triggered
+async fn function_handler(
+    url_shortener: &UrlShortener,
+    url_info: &UrlInfo,
+    audit_log: &AuditLog,
+    reputation: &ReputationPolicy,
+    event: Request,
+) -> Result<impl IntoResponse, Error> {
+    // Tracing. Not the whole event: a batch body can run to hundreds of URLs.
+    tracing::info!("Received batch create request");
+
+    // Identity comes from the authorizer context, never from the request body, exactly
+    // as for a single create (FR-3.2, FR-3.6).
+    let owner_sub = match owner_from_request(&event) {
+        Ok(sub) => sub,
+        Err(e) => {
+            tracing::error!("rejecting batch create request without owner identity: {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    if let Err(e) = require_scope(&event, Scope::LinksCreate) {
+        return error_response(&e);
+    }
+
+    let batch = match event.payload::<BatchShortenRequest>() {
+        Ok(Some(batch)) => batch,
+        _ => {
+            return error_response(&AppError::Validation(
+                "Invalid request body: expected a 'links' array".to_string(),
+            ));
+        }
+    };
+
+    // Whole-request problems are a plain 4xx; everything past this point is reported
+    // per item and the request itself succeeds.
+    if batch.links.is_empty() || batch.links.len() > MAX_BATCH_SIZE {
+        return error_response(&AppError::Validation(format!(
+            "A batch must contain between 1 and {MAX_BATCH_SIZE} links"
+        )));
+    }
+    let requested = batch.links.len();
+
+    let validated = ShortenUrlRequest::validate_batch(
+        batch.links,
+        &url_shortener.shortener_domain,
+        reputation,
+    )
+    .await;
+
+    let results = url_shortener
+        .shorten_urls(validated, url_info, &owner_sub)
+        .await;
+
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    for (index, result) in results.iter().enumerate() {
+        if let Err(e) = result {
+            tracing::warn!("Batch item {index} failed 💥 : {:?}", e);
+        }
+    }
+    tracing::info!("Batch create finished: {} of {requested} links created", requested - failed);
+
+    // One event per link, as a single create would record, so the log reads the same
+    // however the links were made.
+    let created = results
+        .iter()
+        .flatten()
+        .map(|link| {
+            AuditEvent::from_request(AuditAction::LinkCreated, &owner_sub, &event)
+                .with_target(&link.link_id)
+                .with_detail("batch")
+        })
+        .collect();
+    audit_log.record_all(created).await;
+
+    json_response(
+        &StatusCode::OK,
+        &BatchShortenResponse {
+            results: results.into_iter().map(Into::into).collect(),
+        },
+    )
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    // Get the table name from the env variables
+    let table_name = env::var("TABLE_NAME").expect("No TABLE_NAME environment variable set");
+    let shortener_domain = env::var("SHORTENER_DOMAIN").expect("No SHORTENER_DOMAIN environment variable set");
+    let secret_arn = env::var("GOOGLE_API_KEY_SECRET").expect("No GOOGLE_API_KEY_SECRET environment variable set");
+    let audit_table_name = env::var("AUDIT_TABLE_NAME").expect("No AUDIT_TABLE_NAME environment variable set");
+    // Set up the AWS DynamoDB SDK Client
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+    let secrets_client = aws_sdk_secretsmanager::Client::new(&config);
+
+    // Http Client for retrieving additional information from the posted URLs
+    let http_client = shared::Client::builder()
+        .timeout(std::time::Duration::from_secs(2))
+        .build()?;
+
+    let reputation = ReputationPolicy::from_env(SafeBrowsing::new(
+        SecretCache::new(secrets_client, &secret_arn),
+        http_client.clone(),
+    ))?;
+
+    let url_info = UrlInfo::new(http_client);
+
+    let audit_log = AuditLog::new(&audit_table_name, dynamodb_client.clone());
+    let shortener = UrlShortener::new(&table_name, &shortener_domain, dynamodb_client);
+
+    run(service_fn(|event| {
+        function_handler(&shortener, &url_info, &audit_log, &reputation, event)
+    }))
+    .await
+}
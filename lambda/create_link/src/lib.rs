@@ -0,0 +1,112 @@
+use lambda_http::http::StatusCode;
+use lambda_http::{tracing, Error, IntoResponse, Request, RequestPayloadExt};
+
+use shared::audit::{AuditAction, AuditEvent, AuditLog};
+use shared::auth::{owner_from_request, require_scope, Scope};
+use shared::core::{ShortenUrlRequest, UrlShortener};
+use shared::reputation::ReputationPolicy;
+use shared::response::{empty_response, error_response, json_response, html_response};
+use shared::url_info::UrlInfo;
+use shared::templates::{NewShortLink, ErrorPopup, Template};
+
+
+// The main bit of code that will run every time this function is triggered
+pub async fn function_handler(
+    url_shortener: &UrlShortener,
+    url_info: &UrlInfo,
+    audit_log: &AuditLog,
+    reputation: &ReputationPolicy,
+    event: Request,
+) -> Result<impl IntoResponse, Error> {
+    // Tracing
+    tracing::info!("Received event: {:?}", event);
+
+    // Identity comes from the authorizer context, never from the request body. Accepting
+    // an owner from the payload would let any caller create links owned by anyone
+    // (FR-3.2, FR-3.6).
+    let owner_sub = match owner_from_request(&event) {
+        Ok(sub) => sub,
+        Err(e) => {
+            tracing::error!("rejecting create request without owner identity: {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    if let Err(e) = require_scope(&event, Scope::LinksCreate) {
+        return error_response(&e);
+    }
+
+    // Get the Request
+    let shorten_url_request_body = event.payload::<ShortenUrlRequest>()?;
+
+    let htmx_request = event.headers().get("Hx-Request");
+    match shorten_url_request_body {
+        // If it cannot parse the payload (no "url_to_shorten")
+        None => empty_response(&StatusCode::BAD_REQUEST),
+        // Was able to parse the payload, lets shorten it
+        Some(shorten_url_request) => {
+            match shorten_url_request.validate(&url_shortener.shortener_domain, reputation).await {
+                Ok(ser) => {
+                    let shortened_url_response = url_shortener
+                        .shorten_url(ser, url_info, &owner_sub)
+                        .await;
+
+                    if let Ok(response) = &shortened_url_response {
+                        audit_log
+                            .record(
+                                AuditEvent::from_request(AuditAction::LinkCreated, &owner_sub, &event)
+                                    .with_target(&response.link_id),
+                            )
+                            .await;
+                    }
+
+                    // See if the request is coming from the front end HTMX
+                    //let htmx_request = event.headers().get("Hx-Request");
+
+                    // See if we managed to shorten it
+                    match shortened_url_response {
+                        Ok(response) if htmx_request.is_some() => {
+                            tracing::info!("Request is HTMX");
+                            let new_link_html = NewShortLink {
+                                link: response.link_id,
+                                // TODO: Make this not hardcoded
+                                domain: "krtk.rs/"
+                            };
+                            let body = new_link_html.render()?; // Render HTML
+                            html_response(&StatusCode::OK, body) // Respond with HTML
+                        },
+                        // Yes, return the JSON back
+                        Ok(response) => json_response(&StatusCode::OK, &response),
+                        // No, fail spectacularly
+                        Err(e) if htmx_request.is_some() => {
+                            tracing::error!("Failed to shorten URL 💥 : {:?}", e);
+                            let error_html = ErrorPopup {
+                                message: e.to_string(),
+                            };
+                            let body = error_html.render()?; // Render HTML
+                            html_response(&StatusCode::OK, body) // Respond with HTML
+                        },
+                        // The error's own status: a taken custom slug is a 409 the
+                        // caller can act on, not a generic 500.
+                        Err(e) => {
+                            tracing::error!("Failed to shorten URL 💥 : {:?}", e);
+                            error_response(&e)
+                        }
+                    }
+                },
+                Err(e) if htmx_request.is_some() => {
+                    tracing::error!("Failed to validate URL 💥 : {:?}", e);
+                    let error_html = ErrorPopup {
+                        message: e.to_string(),
+                    };
+                    let body = error_html.render()?; // Render HTML
+                    html_response(&StatusCode::OK, body) // Respond with HTML
+                },
+                Err(e) => {
+                    tracing::error!("Failed to validate URL 💥 : {:?}", e);
+                    error_response(&e)
+                }
+            }
+        }
+    }
+}
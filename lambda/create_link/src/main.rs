@@ -1,100 +1,15 @@
-use lambda_http::http::StatusCode;
-use lambda_http::{run, service_fn, tracing, Error, IntoResponse, Request, RequestPayloadExt};
-
-use shared::auth::owner_from_request;
-use shared::core::{ShortenUrlRequest, UrlShortener};
-use shared::response::{empty_response, error_response, json_response, html_response};
+use lambda_http::{run, service_fn, tracing, Error};
+
+use create_link::function_handler;
+use shared::audit::AuditLog;
+use shared::core::UrlShortener;
+use shared::reputation::ReputationPolicy;
+use shared::safe_browsing::SafeBrowsing;
+use shared::secrets::SecretCache;
 use shared::url_info::UrlInfo;
-use shared::templates::{NewShortLink, ErrorPopup, Template};
 
 use std::env;
 
-// The main bit of code that will run every time this function is triggered
-async fn function_handler(
-    url_shortener: &UrlShortener,
-    url_info: &UrlInfo,
-    secrets_client: &aws_sdk_secretsmanager::Client,
-    secret_arn: &str,
-    event: Request,
-) -> Result<impl IntoResponse, Error> {
-    // Tracing
-    tracing::info!("Received event: {:?}", event);
-
-    // Identity comes from the authorizer context, never from the request body. Accepting
-    // an owner from the payload would let any caller create links owned by anyone
-    // (FR-3.2, FR-3.6).
-    let owner_sub = match owner_from_request(&event) {
-        Ok(sub) => sub,
-        Err(e) => {
-            tracing::error!("rejecting create request without owner identity: {:?}", e);
-            return error_response(&e);
-        }
-    };
-
-    // Get the Request
-    let shorten_url_request_body = event.payload::<ShortenUrlRequest>()?;
-
-    let htmx_request = event.headers().get("Hx-Request");
-    match shorten_url_request_body {
-        // If it cannot parse the payload (no "url_to_shorten")
-        None => empty_response(&StatusCode::BAD_REQUEST),
-        // Was able to parse the payload, lets shorten it
-        Some(shorten_url_request) => {
-            match shorten_url_request.validate(&url_shortener.shortener_domain, secrets_client, secret_arn, &url_info.http_client).await {
-                Ok(ser) => {
-                    let shortened_url_response = url_shortener
-                        .shorten_url(ser, url_info, &owner_sub)
-                        .await;
-
-                    // See if the request is coming from the front end HTMX
-                    //let htmx_request = event.headers().get("Hx-Request");
-
-                    // See if we managed to shorten it
-                    match shortened_url_response {
-                        Ok(response) if htmx_request.is_some() => {
-                            tracing::info!("Request is HTMX");
-                            let new_link_html = NewShortLink {
-                                link: response.link_id,
-                                // TODO: Make this not hardcoded
-                                domain: "krtk.rs/"
-                            };
-                            let body = new_link_html.render()?; // Render HTML
-                            html_response(&StatusCode::OK, body) // Respond with HTML
-                        },
-                        // Yes, return the JSON back
-                        Ok(response) => json_response(&StatusCode::OK, &response),
-                        // No, fail spectacularly
-                        Err(e) if htmx_request.is_some() => {
-                            tracing::error!("Failed to shorten URL 💥 : {:?}", e);
-                            let error_html = ErrorPopup {
-                                message: e.to_string(),
-                            };
-                            let body = error_html.render()?; // Render HTML
-                            html_response(&StatusCode::OK, body) // Respond with HTML
-                        },
-                        Err(e) => {
-                            tracing::error!("Failed to shorten URL 💥 : {:?}", e);
-                            empty_response(&StatusCode::INTERNAL_SERVER_ERROR)
-                        }
-                    }
-                },
-                Err(e) if htmx_request.is_some() => {
-                    tracing::error!("Failed to validate URL 💥 : {:?}", e);
-                    let error_html = ErrorPopup {
-                        message: e.to_string(),
-                    };
-                    let body = error_html.render()?; // Render HTML
-                    html_response(&StatusCode::OK, body) // Respond with HTML
-                },
-                Err(e) => {
-                    tracing::error!("Failed to validate URL 💥 : {:?}", e);
-                    empty_response(&StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            }
-        }
-    }
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing::init_default_subscriber();
@@ -103,6 +18,7 @@ async fn main() -> Result<(), Error> {
     let table_name = env::var("TABLE_NAME").expect("No TABLE_NAME environment variable set");
     let shortener_domain = env::var("SHORTENER_DOMAIN").expect("No SHORTENER_DOMAIN environment variable set");
     let secret_arn = env::var("GOOGLE_API_KEY_SECRET").expect("No GOOGLE_API_KEY_SECRET environment variable set");
+    let audit_table_name = env::var("AUDIT_TABLE_NAME").expect("No AUDIT_TABLE_NAME environment variable set");
     // Set up the AWS DynamoDB SDK Client
     let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
@@ -114,14 +30,21 @@ async fn main() -> Result<(), Error> {
         .timeout(std::time::Duration::from_secs(2))
         .build()?;
 
+    let reputation = ReputationPolicy::from_env(SafeBrowsing::new(
+        SecretCache::new(secrets_client, &secret_arn),
+        http_client.clone(),
+    ))?;
+
     // Instantiate UrlInfo
     let url_info = UrlInfo::new(http_client);
 
+    let audit_log = AuditLog::new(&audit_table_name, dynamodb_client.clone());
+
     // Creating a new UrlShortener struct with defaults
     let shortener = UrlShortener::new(&table_name, &shortener_domain, dynamodb_client);
 
     run(service_fn(|event| {
-        function_handler(&shortener, &url_info, &secrets_client, &secret_arn, event)
+        function_handler(&shortener, &url_info, &audit_log, &reputation, event)
     }))
     .await
 }
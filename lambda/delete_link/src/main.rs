@@ -0,0 +1,25 @@
+use lambda_http::{run, service_fn, tracing, Error};
+
+use delete_link::function_handler;
+use shared::audit::AuditLog;
+use shared::core::UrlShortener;
+
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    // Get the table name from the env variables
+    let table_name = env::var("TABLE_NAME").expect("No TABLE_NAME environment variable set");
+    let shortener_domain = env::var("SHORTENER_DOMAIN").expect("No SHORTENER_DOMAIN environment variable set");
+    let audit_table_name = env::var("AUDIT_TABLE_NAME").expect("No AUDIT_TABLE_NAME environment variable set");
+    // Set up the AWS DynamoDB SDK Client
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+
+    let audit_log = AuditLog::new(&audit_table_name, dynamodb_client.clone());
+    let shortener = UrlShortener::new(&table_name, &shortener_domain, dynamodb_client);
+
+    run(service_fn(|event| function_handler(&shortener, &audit_log, event))).await
+}
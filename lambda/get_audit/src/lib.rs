@@ -0,0 +1,48 @@
+use lambda_http::http::StatusCode;
+use lambda_http::{tracing, Error, IntoResponse, Request, RequestExt};
+
+use shared::audit::AuditLog;
+use shared::auth::owner_from_request;
+use shared::response::{error_response, html_response, json_response};
+use shared::templates::{AuditLogPage, Template};
+
+
+// The main bit of code that will run every time this function is triggered
+pub async fn function_handler(audit_log: &AuditLog, event: Request) -> Result<impl IntoResponse, Error> {
+    // Tracing
+    tracing::info!("Received event: {:?}", event);
+
+    // Behind the user pool authorizer only, like key management: the log records what
+    // was done with API keys, so a key must not be able to read it.
+    let owner_sub = match owner_from_request(&event) {
+        Ok(sub) => sub,
+        Err(e) => {
+            tracing::error!("rejecting audit request without owner identity: {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    let query_params = event.query_string_parameters();
+    let cursor = query_params.first("cursor");
+
+    // Only this owner's events; the partition key is the scoping.
+    let page = match audit_log.list(&owner_sub, cursor).await {
+        Ok(page) => page,
+        Err(e) => {
+            tracing::error!("Failed to list audit events 🔥 : {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    // See if the request is coming from the front end HTMX
+    if event.headers().get("Hx-Request").is_some() {
+        let body = AuditLogPage {
+            events: page.events,
+            next_cursor: page.next_cursor,
+        }
+        .render()?;
+        return html_response(&StatusCode::OK, body);
+    }
+
+    json_response(&StatusCode::OK, &page)
+}
@@ -0,0 +1,20 @@
+use lambda_http::{run, service_fn, tracing, Error};
+
+use get_audit::function_handler;
+use shared::audit::AuditLog;
+
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    let audit_table_name = env::var("AUDIT_TABLE_NAME").expect("No AUDIT_TABLE_NAME environment variable set");
+    // Set up the AWS DynamoDB SDK Client
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+
+    let audit_log = AuditLog::new(&audit_table_name, dynamodb_client);
+
+    run(service_fn(|event| function_handler(&audit_log, event))).await
+}
@@ -0,0 +1,143 @@
+use lambda_http::http::StatusCode;
+use lambda_http::{tracing, Error, IntoResponse, Request, RequestExt};
+
+use futures::future::join_all;
+
+use shared::auth::{has_scope, owner_from_request, require_scope, Scope};
+use shared::clicks::{ClickHistory, HistoryRange};
+use shared::error::AppError;
+use shared::core::{LinkFilter, PageSize, UrlShortener};
+use shared::response::{empty_response, error_response, json_response, html_response};
+use shared::secrets::SecretCache;
+use shared::templates::{LinksTable, Link, Template};
+
+use std::collections::HashMap;
+
+// The main bit of code that will run every time this function is triggered
+pub async fn function_handler(
+    url_shortener: &UrlShortener,
+    click_history: &ClickHistory,
+    cursor_key: &SecretCache,
+    event: Request,
+) -> Result<impl IntoResponse, Error> {
+    // Tracing
+    tracing::info!("Received event: {:?}", event);
+
+    // Identity comes from the authorizer context, never from the request itself.
+    // Behind a configured authorizer this cannot fail; if it does, the route is
+    // misconfigured and failing closed is the only safe answer.
+    let owner_sub = match owner_from_request(&event) {
+        Ok(sub) => sub,
+        Err(e) => {
+            tracing::error!("rejecting list request without owner identity: {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    if let Err(e) = require_scope(&event, Scope::LinksRead) {
+        return error_response(&e);
+    }
+
+    // Get the query parameters from the event
+    let query_params = event.query_string_parameters();
+    // Where the previous page stopped, as the opaque cursor it ended with.
+    let cursor = query_params.first("cursor").filter(|cursor| !cursor.is_empty());
+    let page_size = match PageSize::from_param(query_params.first("limit")) {
+        Ok(page_size) => page_size,
+        Err(e) => return error_response(&e),
+    };
+    // Window for each link's click history; the dashboard defaults to a week.
+    let range = match HistoryRange::from_days(query_params.first("days").unwrap_or("7")) {
+        Ok(range) => range,
+        Err(e) => return error_response(&e),
+    };
+
+    let filter = match LinkFilter::from_params(|name| query_params.first(name)) {
+        Ok(filter) => filter,
+        Err(e) => return error_response(&e),
+    };
+
+    let cursor_key = match cursor_key.get().await {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::error!("Cannot sign pagination cursors 🔥 : {:?}", e);
+            return empty_response(&StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    // Only this owner's links. Scoping is in the query's partition key, so another
+    // owner's items are never read rather than being read and filtered. A search reads
+    // the whole partition, so the plain listing keeps its page-at-a-time query.
+    let links = if filter.is_unfiltered() {
+        url_shortener
+            .list_urls(&owner_sub, cursor, page_size, cursor_key.as_bytes())
+            .await
+    } else {
+        url_shortener
+            .search_urls(&owner_sub, &filter, cursor, page_size, cursor_key.as_bytes())
+            .await
+    };
+
+    // History is best-effort: a link whose series cannot be read is still listed,
+    // just without a sparkline. One query per link on the page, run side by side.
+    // A key without analytics:read gets the listing alone, as if there were no history.
+    let links = match links {
+        Ok(links) if !has_scope(&event, Scope::AnalyticsRead) => Ok(links),
+        Ok(mut links) => {
+            let now = chrono::Utc::now().timestamp();
+            let link_ids = links.link_ids();
+            let series = join_all(
+                link_ids
+                    .iter()
+                    .map(|link_id| click_history.series(link_id, range, now)),
+            )
+            .await;
+            let history: HashMap<String, _> = link_ids
+                .into_iter()
+                .zip(series)
+                .filter_map(|(link_id, series)| match series {
+                    Ok(series) => Some((link_id, series)),
+                    Err(e) => {
+                        tracing::warn!("No click history for {link_id}: {:?}", e);
+                        None
+                    }
+                })
+                .collect();
+            links.attach_click_history(history);
+            Ok(links)
+        }
+        Err(e) => Err(e),
+    };
+
+    // See if the request is coming from the front end HTMX
+    let htmx_request = event.headers().get("Hx-Request");
+
+    // Handle the links
+    match links {
+        Ok(links) if htmx_request.is_some() => {
+                tracing::info!("Request is HTMX");
+                // TODO: Make this more compact and handle the Results
+                let links_str = serde_json::to_value(&links)?;
+                let table_links: Vec<Link> = serde_json::from_value(links_str["short_urls"].clone())?;
+                let table_html = LinksTable {
+                    links: table_links,
+                    // TODO: Make this not hardcoded
+                    domain: "krtk.rs/",
+                    has_more: links.has_more,
+                    cursor: links.cursor.clone().unwrap_or_default(),
+                    days: range.days(),
+                    limit: page_size.links(),
+                    filter: filter.query_string(),
+                };
+                let body = table_html.render()?; // Render HTML
+                html_response(&StatusCode::OK, body) // Respond with HTML
+        },
+        Ok(links) => json_response(&StatusCode::OK, &links),
+        // A cursor that does not verify, or a bad filter or limit.
+        Err(e @ AppError::Validation(_)) => error_response(&e),
+        Err(e) => {
+            tracing::error!("Failed to list URLs 🔥 : {:?}", e);
+            empty_response(&StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! What one change to a link means for the tag index.
+//!
+//! A stream record carries the link as it was and as it is now; either side is empty for
+//! a link that was just created or just deleted. The index only cares about three
+//! attributes, so both images are read down to those and compared.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Deserialize;
+use shared::tags::tag_changes;
+
+#[derive(Debug, Deserialize)]
+struct TaggedLink {
+    #[serde(rename = "LinkId")]
+    link_id: String,
+    // Absent on links from before ownership; those are never listed, so never indexed.
+    #[serde(rename = "OwnerId", default)]
+    owner_id: Option<String>,
+    #[serde(rename = "Tags", default)]
+    tags: Vec<String>,
+}
+
+/// Index entries to write and delete for one owner's link.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IndexChange {
+    pub owner_sub: String,
+    pub link_id: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// The index changes a link going from `old` to `new` calls for.
+///
+/// Usually one change or none: most writes to a link (clicks above all) leave its tags
+/// alone. A link whose `OwnerId` changed -- `migrate_owners` gives old links one -- is
+/// handled as leaving the old owner's index and joining the new one's.
+pub fn index_changes(
+    old: HashMap<String, AttributeValue>,
+    new: HashMap<String, AttributeValue>,
+) -> Result<Vec<IndexChange>, serde_dynamo::Error> {
+    let old = read(old)?;
+    let new = read(new)?;
+
+    let changes = match (old, new) {
+        (Some(old), Some(new)) if old.owner_id == new.owner_id => vec![diff(&new, &old.tags, &new.tags)],
+        (old, new) => old
+            .map(|old| diff(&old, &old.tags, &[]))
+            .into_iter()
+            .chain(new.map(|new| diff(&new, &[], &new.tags)))
+            .collect(),
+    };
+    Ok(changes
+        .into_iter()
+        .flatten()
+        .filter(|change| !change.added.is_empty() || !change.removed.is_empty())
+        .collect())
+}
+
+/// `link`'s tags going `from` -> `to`, or `None` for a link with no owner.
+fn diff(link: &TaggedLink, from: &[String], to: &[String]) -> Option<IndexChange> {
+    let (added, removed) = tag_changes(from, to);
+    Some(IndexChange {
+        owner_sub: link.owner_id.clone()?,
+        link_id: link.link_id.clone(),
+        added: added.into_iter().map(str::to_string).collect(),
+        removed: removed.into_iter().map(str::to_string).collect(),
+    })
+}
+
+fn read(image: HashMap<String, AttributeValue>) -> Result<Option<TaggedLink>, serde_dynamo::Error> {
+    if image.is_empty() {
+        return Ok(None);
+    }
+    serde_dynamo::from_item(image).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(owner: &str, tags: &[&str]) -> HashMap<String, AttributeValue> {
+        let mut image = HashMap::from([
+            ("LinkId".to_string(), AttributeValue::S("abc1234".to_string())),
+            ("OwnerId".to_string(), AttributeValue::S(owner.to_string())),
+            ("Clicks".to_string(), AttributeValue::N("3".to_string())),
+        ]);
+        if !tags.is_empty() {
+            image.insert("Tags".to_string(), AttributeValue::Ss(tags.iter().map(|t| t.to_string()).collect()));
+        }
+        image
+    }
+
+    fn change(owner: &str, added: &[&str], removed: &[&str]) -> IndexChange {
+        IndexChange {
+            owner_sub: owner.to_string(),
+            link_id: "abc1234".to_string(),
+            added: added.iter().map(|t| t.to_string()).collect(),
+            removed: removed.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn a_created_or_deleted_link_adds_or_removes_all_its_tags() {
+        let tagged = || image("owner-a", &["launch", "q3"]);
+        assert_eq!(
+            index_changes(HashMap::new(), tagged()).unwrap(),
+            [change("owner-a", &["launch", "q3"], &[])]
+        );
+        assert_eq!(
+            index_changes(tagged(), HashMap::new()).unwrap(),
+            [change("owner-a", &[], &["launch", "q3"])]
+        );
+    }
+
+    #[test]
+    fn an_edit_touches_only_the_tags_that_changed() {
+        let changes = index_changes(image("owner-a", &["launch", "q3"]), image("owner-a", &["q3", "q4"])).unwrap();
+        assert_eq!(changes, [change("owner-a", &["q4"], &["launch"])]);
+
+        // A click, or an untagged link being created, leaves the index alone.
+        assert!(index_changes(image("owner-a", &["q3"]), image("owner-a", &["q3"])).unwrap().is_empty());
+        assert!(index_changes(HashMap::new(), image("owner-a", &[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_link_that_changes_hands_moves_between_owners_indexes() {
+        let changes = index_changes(image("owner-a", &["q3"]), image("owner-b", &["q3"])).unwrap();
+        assert_eq!(changes, [change("owner-a", &[], &["q3"]), change("owner-b", &["q3"], &[])]);
+    }
+
+    #[test]
+    fn links_without_an_owner_are_not_indexed() {
+        let mut unowned = image("owner-a", &["q3"]);
+        unowned.remove("OwnerId");
+        assert!(index_changes(HashMap::new(), unowned).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+use lambda_runtime::{run, service_fn, tracing, Error, LambdaEvent};
+use aws_lambda_events::event::dynamodb::Event;
+use aws_lambda_events::event::streams::{DynamoDbBatchItemFailure, DynamoDbEventResponse};
+use shared::tags::TagIndex;
+use std::env;
+
+mod changes;
+
+use changes::index_changes;
+
+/// Keeps the tag index in step with the link table, from the table's stream.
+///
+/// Records arrive in the order the links changed, so they are applied one at a time in
+/// that order: a tag added and removed again within one batch must end up removed.
+///
+/// As in `process_analytics`, the two kinds of failure are handled differently:
+///
+/// - An image that cannot be read is logged and skipped. Retrying cannot fix it.
+/// - A failed index write is transient, so its record is reported in
+///   `batchItemFailures` and the batch stops there. Lambda re-delivers from that record;
+///   the writes before it that are repeated are puts and deletes, which land the same
+///   way twice.
+pub async fn function_handler(
+    tag_index: &TagIndex,
+    event: LambdaEvent<Event>,
+) -> Result<DynamoDbEventResponse, Error> {
+    for record in event.payload.records {
+        let sequence_number = record.change.sequence_number.unwrap_or_default();
+        let changes = match index_changes(record.change.old_image.into(), record.change.new_image.into()) {
+            Ok(changes) => changes,
+            Err(e) => {
+                tracing::warn!("Skipping unreadable link change {sequence_number}: {:?}", e);
+                continue;
+            }
+        };
+
+        for change in changes {
+            for tag in &change.added {
+                if let Err(e) = tag_index.add(&change.owner_sub, tag, &change.link_id).await {
+                    tracing::error!("Failed to index tag {tag} on {}: {:?}", change.link_id, e);
+                    return Ok(retry_from(sequence_number));
+                }
+            }
+            for tag in &change.removed {
+                if let Err(e) = tag_index.remove(&change.owner_sub, tag, &change.link_id).await {
+                    tracing::error!("Failed to unindex tag {tag} on {}: {:?}", change.link_id, e);
+                    return Ok(retry_from(sequence_number));
+                }
+            }
+        }
+    }
+
+    Ok(DynamoDbEventResponse::default())
+}
+
+/// A response that has Lambda re-deliver the batch from `sequence_number` on.
+fn retry_from(sequence_number: String) -> DynamoDbEventResponse {
+    let mut failure = DynamoDbBatchItemFailure::default();
+    failure.item_identifier = Some(sequence_number);
+    let mut response = DynamoDbEventResponse::default();
+    response.batch_item_failures.push(failure);
+    response
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+    let tag_table_name = env::var("TAG_TABLE_NAME").expect("No TAG_TABLE_NAME environment variable set");
+    // Set up the AWS DynamoDB SDK Client
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+
+    let tag_index = TagIndex::new(&tag_table_name, dynamodb_client);
+
+    run(service_fn(|event| function_handler(&tag_index, event))).await
+}
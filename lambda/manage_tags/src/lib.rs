@@ -0,0 +1,120 @@
+use lambda_http::http::StatusCode;
+use lambda_http::{tracing, Body, Error, Request, RequestExt, RequestPayloadExt, Response};
+use serde::{Deserialize, Serialize};
+
+use shared::auth::{owner_from_request, require_scope};
+use shared::collection::{reply, Route};
+use shared::core::UrlShortener;
+use shared::error::AppError;
+use shared::response::{empty_response, error_response};
+use shared::tags::{normalize_tag, TagCount, TagIndex};
+
+/// Body of `PATCH /api/tags/{tag}`.
+#[derive(Debug, Deserialize)]
+struct RenameRequest {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TagList {
+    tags: Vec<TagCount>,
+}
+
+/// The outcome of a rename or delete: `tag` is the new name, absent after a delete.
+#[derive(Debug, Serialize)]
+struct Retagged {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    links: usize,
+}
+
+/// Lists the caller's tags with counts, and renames or deletes one across all of the
+/// caller's links.
+///
+/// Reading the tags takes the same scope as reading the links they are on. Renaming and
+/// deleting rewrite those links, so they take the scope that edits a link; deleting a
+/// tag never deletes a link.
+pub async fn function_handler(
+    url_shortener: &UrlShortener,
+    tag_index: &TagIndex,
+    event: Request,
+) -> Result<Response<Body>, Error> {
+    tracing::info!("Received event: {:?}", event);
+
+    // Identity comes from the authorizer context. Both the index query and every link
+    // write are scoped to this value, never to anything in the request.
+    let owner_sub = match owner_from_request(&event) {
+        Ok(sub) => sub,
+        Err(e) => {
+            tracing::error!("rejecting tag request without owner identity: {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    // The tag is the `{tag+}` path parameter: greedy, so a folder-like tag
+    // (`clients/acme`) arrives whole.
+    let path_parameters = event.path_parameters();
+    let route = Route::of(event.method().as_str(), "PATCH", path_parameters.first("tag"));
+
+    let Some(scope) = route.scope() else {
+        tracing::warn!("no tag route for {} {:?}", event.method(), path_parameters.first("tag"));
+        return empty_response(&StatusCode::METHOD_NOT_ALLOWED);
+    };
+    if let Err(e) = require_scope(&event, scope) {
+        return error_response(&e);
+    }
+
+    match route {
+        Route::List => reply("Tag", tag_index.counts(&owner_sub).await.map(|tags| TagList { tags })),
+        Route::Write(tag) => reply("Tag", rename(url_shortener, tag_index, &owner_sub, tag, &event).await),
+        Route::Delete(tag) => reply("Tag", delete(url_shortener, tag_index, &owner_sub, tag).await),
+        Route::NotAllowed => unreachable!("answered above"),
+    }
+}
+
+async fn rename(
+    url_shortener: &UrlShortener,
+    tag_index: &TagIndex,
+    owner_sub: &str,
+    tag: &str,
+    event: &Request,
+) -> Result<Retagged, AppError> {
+    let from = normalize_tag(tag)?;
+    let to = match event.payload::<RenameRequest>() {
+        Ok(Some(req)) => normalize_tag(&req.name)?,
+        _ => {
+            return Err(AppError::Validation(
+                "Invalid request body: expected a 'name' field".to_string(),
+            ));
+        }
+    };
+
+    let link_ids = tag_index.links(owner_sub, &from).await?;
+    let links = url_shortener.retag(owner_sub, &link_ids, &from, Some(&to)).await?;
+    Ok(Retagged { tag: Some(to), links })
+}
+
+async fn delete(
+    url_shortener: &UrlShortener,
+    tag_index: &TagIndex,
+    owner_sub: &str,
+    tag: &str,
+) -> Result<Retagged, AppError> {
+    let tag = normalize_tag(tag)?;
+    let link_ids = tag_index.links(owner_sub, &tag).await?;
+    let links = url_shortener.retag(owner_sub, &link_ids, &tag, None).await?;
+    Ok(Retagged { tag: None, links })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_deleted_tag_reports_only_the_count() {
+        let deleted = serde_json::to_value(Retagged { tag: None, links: 3 }).unwrap();
+        assert_eq!(deleted, serde_json::json!({ "links": 3 }));
+        let renamed = serde_json::to_value(Retagged { tag: Some("q4".to_string()), links: 1 }).unwrap();
+        assert_eq!(renamed, serde_json::json!({ "tag": "q4", "links": 1 }));
+    }
+}
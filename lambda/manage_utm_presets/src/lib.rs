@@ -0,0 +1,99 @@
+use lambda_http::http::StatusCode;
+use lambda_http::{tracing, Body, Error, Request, RequestExt, RequestPayloadExt, Response};
+use serde::Serialize;
+
+use shared::auth::{owner_from_request, require_scope};
+use shared::collection::{reply, reply_empty, Route};
+use shared::error::AppError;
+use shared::response::{empty_response, error_response};
+use shared::utm::{check_preset_room, normalize_preset_name, Utm, UtmPreset, UtmPresets};
+
+#[derive(Debug, Serialize)]
+struct PresetList {
+    presets: Vec<UtmPreset>,
+}
+
+/// Lists, saves and deletes the caller's UTM presets.
+///
+/// A preset is only ever read back by its owner and merged into links they create, so
+/// the scopes follow the links' (see `shared::collection`).
+pub async fn function_handler(presets: &UtmPresets, event: Request) -> Result<Response<Body>, Error> {
+    tracing::info!("Received event: {:?}", event);
+
+    // Identity comes from the authorizer context; every query and write is scoped to it.
+    let owner_sub = match owner_from_request(&event) {
+        Ok(sub) => sub,
+        Err(e) => {
+            tracing::error!("rejecting preset request without owner identity: {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    let path_parameters = event.path_parameters();
+    let route = Route::of(event.method().as_str(), "PUT", path_parameters.first("name"));
+
+    let Some(scope) = route.scope() else {
+        tracing::warn!("no preset route for {} {:?}", event.method(), path_parameters.first("name"));
+        return empty_response(&StatusCode::METHOD_NOT_ALLOWED);
+    };
+    if let Err(e) = require_scope(&event, scope) {
+        return error_response(&e);
+    }
+
+    match route {
+        Route::List => reply("Preset", presets.list(&owner_sub).await.map(|presets| PresetList { presets })),
+        Route::Write(name) => reply("Preset", save(presets, &owner_sub, name, &event).await),
+        Route::Delete(name) => reply_empty("Preset", delete(presets, &owner_sub, name).await),
+        Route::NotAllowed => unreachable!("answered above"),
+    }
+}
+
+async fn save(presets: &UtmPresets, owner_sub: &str, name: &str, event: &Request) -> Result<UtmPreset, AppError> {
+    let name = normalize_preset_name(name)?;
+    let utm = match event.payload::<Utm>() {
+        Ok(Some(utm)) => utm.normalized()?,
+        _ => {
+            return Err(AppError::Validation(
+                "Invalid request body: expected source, medium, campaign, term or content".to_string(),
+            ));
+        }
+    };
+    // A preset that sets nothing would apply as a no-op and only clutter the list.
+    if utm.is_empty() {
+        return Err(AppError::Validation("A preset must set at least one parameter".to_string()));
+    }
+
+    check_preset_room(&presets.list(owner_sub).await?, &name)?;
+    let preset = UtmPreset { name, utm };
+    presets.save(owner_sub, &preset).await?;
+    Ok(preset)
+}
+
+async fn delete(presets: &UtmPresets, owner_sub: &str, name: &str) -> Result<(), AppError> {
+    let name = normalize_preset_name(name)?;
+    if !presets.delete(owner_sub, &name).await? {
+        return Err(AppError::NotFound(name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_list_with_only_the_parameters_they_set() {
+        let list = PresetList {
+            presets: vec![UtmPreset {
+                name: "newsletter".to_string(),
+                utm: Utm { source: Some("newsletter".to_string()), medium: Some("email".to_string()), ..Default::default() },
+            }],
+        };
+        assert_eq!(
+            serde_json::to_value(list).unwrap(),
+            serde_json::json!({
+                "presets": [{ "name": "newsletter", "utm": { "source": "newsletter", "medium": "email" } }]
+            })
+        );
+    }
+}
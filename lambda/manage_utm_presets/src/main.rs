@@ -0,0 +1,21 @@
+use lambda_http::{run, service_fn, tracing, Error};
+
+use manage_utm_presets::function_handler;
+use shared::utm::UtmPresets;
+
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    let preset_table_name =
+        env::var("UTM_PRESET_TABLE_NAME").expect("No UTM_PRESET_TABLE_NAME environment variable set");
+    // Set up the AWS DynamoDB SDK Client
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+
+    let presets = UtmPresets::new(&preset_table_name, dynamodb_client);
+
+    run(service_fn(|event| function_handler(&presets, event))).await
+}
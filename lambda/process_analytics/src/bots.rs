@@ -0,0 +1,109 @@
+//! Tells people apart from crawlers and link unfurlers by their user agent.
+//!
+//! Pasting a short link into Slack, X, iMessage or Discord makes the service fetch it
+//! for a preview before anyone clicks, and search engines crawl whatever they find.
+//! Those hits are real requests, but counting them as clicks makes every shared link
+//! look popular. They are counted separately instead (`BotClicks`).
+//!
+//! This is a heuristic, not a defence: a bot that claims to be a browser is counted as
+//! one. It only has to catch the well-behaved automation that says what it is, which is
+//! the bulk of what inflates the numbers.
+
+/// Who made a request, as far as the user agent tells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Traffic {
+    Human,
+    Bot,
+}
+
+/// Lowercase fragments of user agents that belong to automation. Matched anywhere in
+/// the agent, so version numbers and wrappers do not matter.
+const BOT_MARKERS: &[&str] = &[
+    // Generic self-descriptions, which also catch most crawlers not listed by name.
+    "bot",
+    "crawler",
+    "spider",
+    "preview",
+    "fetcher",
+    // Link unfurlers. iMessage sends "facebookexternalhit ... Twitterbot".
+    "facebookexternalhit",
+    "facebot",
+    "whatsapp",
+    "embedly",
+    "vkshare",
+    "skypeuripreview",
+    "outlook-ios",
+    "iframely",
+    "google-pagerenderer",
+    "mastodon",
+    "bluesky cardyb",
+    // Scripts and headless browsers.
+    "curl/",
+    "wget/",
+    "python-requests",
+    "python-urllib",
+    "go-http-client",
+    "okhttp",
+    "java/",
+    "libwww-perl",
+    "node-fetch",
+    "axios/",
+    "headlesschrome",
+    "phantomjs",
+    "lighthouse",
+];
+
+/// Classifies a request by its (already decoded) user agent.
+///
+/// A request with no user agent at all is a bot: every browser sends one.
+pub fn classify(user_agent: Option<&str>) -> Traffic {
+    let Some(agent) = user_agent.map(str::trim).filter(|a| !a.is_empty()) else {
+        return Traffic::Bot;
+    };
+    let agent = agent.to_ascii_lowercase();
+    if BOT_MARKERS.iter().any(|marker| agent.contains(marker)) {
+        Traffic::Bot
+    } else {
+        Traffic::Human
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browsers_are_human() {
+        for agent in [
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
+            "Mozilla/5.0 (iPhone; CPU iPhone OS 18_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.1 Mobile/15E148 Safari/604.1",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:133.0) Gecko/20100101 Firefox/133.0",
+        ] {
+            assert_eq!(classify(Some(agent)), Traffic::Human, "{agent}");
+        }
+    }
+
+    #[test]
+    fn unfurlers_and_crawlers_are_bots() {
+        for agent in [
+            "Slackbot-LinkExpanding 1.0 (+https://api.slack.com/robots)",
+            "Twitterbot/1.0",
+            "facebookexternalhit/1.1 Facebot Twitterbot/1.0",
+            "Mozilla/5.0 (compatible; Discordbot/2.0; +https://discordapp.com)",
+            "WhatsApp/2.23.20.0",
+            "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+            "Mozilla/5.0 AppleWebKit/537.36 (KHTML, like Gecko; compatible; bingbot/2.0)",
+            "TelegramBot (like TwitterBot)",
+            "curl/8.5.0",
+            "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) HeadlessChrome/120.0.0.0 Safari/537.36",
+        ] {
+            assert_eq!(classify(Some(agent)), Traffic::Bot, "{agent}");
+        }
+    }
+
+    #[test]
+    fn a_missing_user_agent_is_a_bot() {
+        assert_eq!(classify(None), Traffic::Bot);
+        assert_eq!(classify(Some("  ")), Traffic::Bot);
+    }
+}
@@ -0,0 +1,338 @@
+//! Parser for CloudFront real-time log records.
+//!
+//! CloudFront writes one tab-separated line per request to Kinesis, with the columns in
+//! whatever order the real-time log config lists its `fields`. Nothing in the record says
+//! which column is which, so the parser is built from that same list (the stack passes
+//! it in as `LOG_FIELDS`) instead of assuming positions. Adding a field to the config
+//! then means adding it to one array in the stack, not re-counting indexes here.
+//!
+//! Parsing never panics. A bad line becomes a [`ParseError`] for that line alone; one
+//! malformed record must not take the rest of the batch, or the shard, down with it.
+
+use thiserror::Error;
+
+/// The config fields this parser knows how to read. Others may be present in the config
+/// and are skipped over by position.
+const TIMESTAMP: &str = "timestamp";
+const CLIENT_IP: &str = "c-ip";
+const URI_STEM: &str = "cs-uri-stem";
+const STATUS: &str = "sc-status";
+const USER_AGENT: &str = "cs-user-agent";
+
+/// What `visit_link` answers when it sends a visitor on to the destination.
+const REDIRECT_STATUS: u16 = 302;
+
+/// CloudFront writes a hyphen for a field it has no value for.
+const EMPTY_VALUE: &str = "-";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FormatError {
+    #[error("log field list must include '{0}'")]
+    MissingField(&'static str),
+    #[error("log field list names '{0}' more than once")]
+    DuplicateField(String),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("record is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("expected {expected} fields, found {found}")]
+    FieldCount { expected: usize, found: usize },
+    #[error("'{0}' has no value")]
+    MissingValue(&'static str),
+    #[error("invalid timestamp {0:?}")]
+    InvalidTimestamp(String),
+    #[error("invalid status code {0:?}")]
+    InvalidStatus(String),
+}
+
+/// One request, as CloudFront logged it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    /// Unix seconds. CloudFront logs milliseconds as a fraction; nothing downstream
+    /// needs them.
+    pub timestamp: i64,
+    pub client_ip: Option<String>,
+    pub uri_stem: String,
+    pub status: Option<u16>,
+    /// Percent-decoded: CloudFront escapes spaces and the like in this field.
+    pub user_agent: Option<String>,
+}
+
+impl LogRecord {
+    /// The short link id the request was for: the path without its leading slash.
+    ///
+    /// `None` for anything that cannot be a link id, such as a nested path, so a stray
+    /// request is never counted against a link it only resembles.
+    pub fn link_id(&self) -> Option<&str> {
+        let id = self.uri_stem.strip_prefix('/')?;
+        (!id.is_empty() && !id.contains('/')).then_some(id)
+    }
+
+    /// The link this request followed through to its destination, if it did.
+    ///
+    /// Only a redirect does. The preview page, the password form and the pages for an
+    /// expired or disabled link all answer for a link without sending anyone on, and
+    /// counting them would count visits that never reached the destination.
+    pub fn followed_link_id(&self) -> Option<&str> {
+        self.link_id().filter(|_| self.status == Some(REDIRECT_STATUS))
+    }
+}
+
+/// Column positions for one real-time log config.
+#[derive(Debug, Clone)]
+pub struct LogFormat {
+    field_count: usize,
+    timestamp: usize,
+    uri_stem: usize,
+    client_ip: Option<usize>,
+    status: usize,
+    user_agent: usize,
+}
+
+impl LogFormat {
+    /// Builds a format from the config's field names, in order.
+    ///
+    /// `timestamp`, `cs-uri-stem`, `sc-status` and `cs-user-agent` are required:
+    /// without them a record cannot be attributed to a link or a time, or told to be a
+    /// click at all. A request without a user agent is a bot (see `bots::classify`),
+    /// so a config without the column would quietly count every visit as one.
+    pub fn new<S: AsRef<str>>(fields: &[S]) -> Result<Self, FormatError> {
+        let names: Vec<&str> = fields.iter().map(|f| f.as_ref().trim()).collect();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(FormatError::DuplicateField(name.to_string()));
+            }
+        }
+        let position = |field: &str| names.iter().position(|name| *name == field);
+
+        Ok(Self {
+            field_count: names.len(),
+            timestamp: position(TIMESTAMP).ok_or(FormatError::MissingField(TIMESTAMP))?,
+            uri_stem: position(URI_STEM).ok_or(FormatError::MissingField(URI_STEM))?,
+            client_ip: position(CLIENT_IP),
+            status: position(STATUS).ok_or(FormatError::MissingField(STATUS))?,
+            user_agent: position(USER_AGENT).ok_or(FormatError::MissingField(USER_AGENT))?,
+        })
+    }
+
+    /// Builds a format from a comma-separated list, as passed in `LOG_FIELDS`.
+    pub fn from_config(fields: &str) -> Result<Self, FormatError> {
+        let names: Vec<&str> = fields.split(',').filter(|f| !f.trim().is_empty()).collect();
+        Self::new(&names)
+    }
+
+    /// Parses one Kinesis record's data. CloudFront sends one line per record, but a
+    /// record holding several is handled line by line rather than rejected.
+    pub fn parse_record(&self, data: &[u8]) -> Vec<Result<LogRecord, ParseError>> {
+        match std::str::from_utf8(data) {
+            Ok(text) => text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| self.parse_line(line))
+                .collect(),
+            Err(_) => vec![Err(ParseError::InvalidUtf8)],
+        }
+    }
+
+    pub fn parse_line(&self, line: &str) -> Result<LogRecord, ParseError> {
+        let values: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+        if values.len() != self.field_count {
+            return Err(ParseError::FieldCount { expected: self.field_count, found: values.len() });
+        }
+        let value = |index: usize| Some(values[index].trim()).filter(|v| !v.is_empty() && *v != EMPTY_VALUE);
+
+        let raw_timestamp = value(self.timestamp).ok_or(ParseError::MissingValue(TIMESTAMP))?;
+        let timestamp = raw_timestamp
+            .parse::<f64>()
+            .ok()
+            .filter(|t| t.is_finite() && *t >= 0.0)
+            .ok_or_else(|| ParseError::InvalidTimestamp(raw_timestamp.to_string()))?
+            as i64;
+
+        let uri_stem = value(self.uri_stem).ok_or(ParseError::MissingValue(URI_STEM))?.to_string();
+
+        let status = match value(self.status) {
+            Some(raw) => Some(raw.parse::<u16>().map_err(|_| ParseError::InvalidStatus(raw.to_string()))?),
+            None => None,
+        };
+
+        Ok(LogRecord {
+            timestamp,
+            client_ip: self.client_ip.and_then(value).map(str::to_string),
+            uri_stem,
+            status,
+            user_agent: value(self.user_agent).map(percent_decode),
+        })
+    }
+}
+
+/// Undoes CloudFront's URI-style escaping. An escape that is not valid hex is kept as
+/// written, and so are bytes that do not decode to UTF-8: the value is only ever
+/// matched against, so a lossy result is better than dropping the line.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `REALTIME_LOG_FIELDS` in `lib/krtk-rs-stack.ts`.
+    const DEPLOYED_FIELDS: [&str; 5] = ["timestamp", "c-ip", "cs-uri-stem", "sc-status", "cs-user-agent"];
+
+    fn deployed() -> LogFormat {
+        LogFormat::new(&DEPLOYED_FIELDS).unwrap()
+    }
+
+    #[test]
+    fn parses_a_line_in_the_deployed_layout() {
+        let record = deployed()
+            .parse_line("1739035776.180\t24.18.218.96\t/k120oizrul\t302\tMozilla/5.0%20(X11)\n")
+            .unwrap();
+        assert_eq!(
+            record,
+            LogRecord {
+                timestamp: 1_739_035_776,
+                client_ip: Some("24.18.218.96".to_string()),
+                uri_stem: "/k120oizrul".to_string(),
+                status: Some(302),
+                user_agent: Some("Mozilla/5.0 (X11)".to_string()),
+            }
+        );
+        assert_eq!(record.link_id(), Some("k120oizrul"));
+    }
+
+    /// The whole point of taking the field list: a reordered config still parses.
+    #[test]
+    fn follows_the_configured_field_order() {
+        let format = LogFormat::from_config("sc-status,cs-user-agent,cs-uri-stem,time-taken,timestamp").unwrap();
+        let record = format.parse_line("410\t-\t/gone123\t0.002\t1739035776.000").unwrap();
+        assert_eq!(record.status, Some(410));
+        assert_eq!(record.link_id(), Some("gone123"));
+        assert_eq!(record.timestamp, 1_739_035_776);
+        assert_eq!(record.client_ip, None);
+    }
+
+    #[test]
+    fn a_config_without_the_required_fields_is_rejected() {
+        assert_eq!(
+            LogFormat::from_config("c-ip,cs-uri-stem").unwrap_err(),
+            FormatError::MissingField("timestamp")
+        );
+        assert_eq!(
+            LogFormat::from_config("timestamp,c-ip").unwrap_err(),
+            FormatError::MissingField("cs-uri-stem")
+        );
+        assert_eq!(
+            LogFormat::from_config("timestamp,cs-uri-stem").unwrap_err(),
+            FormatError::MissingField("sc-status")
+        );
+        assert_eq!(
+            LogFormat::from_config("timestamp,cs-uri-stem,sc-status").unwrap_err(),
+            FormatError::MissingField("cs-user-agent")
+        );
+        assert!(matches!(
+            LogFormat::from_config("timestamp,cs-uri-stem,timestamp"),
+            Err(FormatError::DuplicateField(_))
+        ));
+    }
+
+    #[test]
+    fn a_short_line_is_an_error_not_a_panic() {
+        assert_eq!(
+            deployed().parse_line("1739035776.180\t24.18.218.96").unwrap_err(),
+            ParseError::FieldCount { expected: 5, found: 2 }
+        );
+    }
+
+    #[test]
+    fn bad_values_are_reported_per_field() {
+        assert_eq!(
+            deployed().parse_line("yesterday\t1.2.3.4\t/abc\t302\t-").unwrap_err(),
+            ParseError::InvalidTimestamp("yesterday".to_string())
+        );
+        assert_eq!(
+            deployed().parse_line("1739035776.180\t1.2.3.4\t/abc\tok\t-").unwrap_err(),
+            ParseError::InvalidStatus("ok".to_string())
+        );
+        assert_eq!(
+            deployed().parse_line("1739035776.180\t1.2.3.4\t-\t302\t-").unwrap_err(),
+            ParseError::MissingValue("cs-uri-stem")
+        );
+    }
+
+    #[test]
+    fn hyphens_mean_no_value_for_optional_fields() {
+        let record = deployed().parse_line("1739035776.180\t-\t/abc\t-\t-").unwrap();
+        assert_eq!(record.client_ip, None);
+        assert_eq!(record.status, None);
+        assert_eq!(record.user_agent, None);
+    }
+
+    #[test]
+    fn the_user_agent_is_percent_decoded() {
+        let format = LogFormat::from_config("timestamp,cs-uri-stem,sc-status,cs-user-agent").unwrap();
+        let record = format
+            .parse_line("1739035776.180\t/abc\t302\tSlackbot-LinkExpanding%201.0%20(+https://api.slack.com/robots)")
+            .unwrap();
+        assert_eq!(
+            record.user_agent.as_deref(),
+            Some("Slackbot-LinkExpanding 1.0 (+https://api.slack.com/robots)")
+        );
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+    }
+
+    #[test]
+    fn invalid_utf8_fails_the_record_not_the_process() {
+        let results = deployed().parse_record(&[0xff, 0xfe, b'\t']);
+        assert_eq!(results, vec![Err(ParseError::InvalidUtf8)]);
+    }
+
+    #[test]
+    fn each_line_of_a_record_gets_its_own_result() {
+        let data = b"1739035776.1\t1.2.3.4\t/abc\t302\t-\nbroken\n\n1739035777.2\t1.2.3.4\t/def\t302\t-\n";
+        let results = deployed().parse_record(data);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().link_id(), Some("def"));
+    }
+
+    #[test]
+    fn only_single_segment_paths_are_link_ids() {
+        let record = |stem: &str| LogRecord {
+            timestamp: 0,
+            client_ip: None,
+            uri_stem: stem.to_string(),
+            status: None,
+            user_agent: None,
+        };
+        assert_eq!(record("/").link_id(), None);
+        assert_eq!(record("/api/links").link_id(), None);
+        assert_eq!(record("abc").link_id(), None);
+        assert_eq!(record("/abc").link_id(), Some("abc"));
+    }
+}
@@ -1,52 +1,110 @@
 use lambda_runtime::{run, service_fn, tracing, Error, LambdaEvent};
+use shared::clicks::ClickHistory;
 use shared::core::UrlShortener;
 use aws_lambda_events::event::kinesis::KinesisEvent;
+use aws_lambda_events::event::streams::KinesisEventResponse;
+use futures::stream::{self, StreamExt};
+use shared::error::AppError;
 use std::env;
 
-#[derive(Debug)]
-pub struct CfAnalyticsData {
-    _timestamp: String, // Should be f64 or u64
-    _source_ip: String,
-    _status_code: String, // Should be an ENUM?
-    link_id: String,
-}
+mod bots;
+mod log_parser;
+mod tally;
+
+use log_parser::LogFormat;
+use tally::ClickTally;
+
+// How many links' counters are written at once. Enough to drain a spike across many
+// links quickly, few enough not to throttle the table.
+const WRITE_CONCURRENCY: usize = 16;
 
+/// Counts the clicks in a batch of CloudFront real-time log records.
+///
+/// Only a redirect to the destination is a visit; a preview, password form or error
+/// page for a link is not. Each visit is classified as a person or a bot (see `bots`);
+/// only people count as clicks. The whole batch is tallied first (see `tally`), then each link gets one counter
+/// write and one history write per hour touched, a bounded number at a time.
+///
+/// Two kinds of failure are handled differently on purpose:
+///
+/// - A line that does not parse is logged and skipped. Retrying cannot fix it, and
+///   reporting it would have Lambda re-deliver it until the record expires from the
+///   stream, holding up every record behind it.
+/// - A failed counter write is transient, so the first record that counted towards
+///   that link is reported in `batchItemFailures`. Lambda checkpoints everything before
+///   it and re-delivers the rest. Links in that suffix whose write did succeed are
+///   counted again on the retry: a deliberate lean towards an occasional overcount
+///   during a DynamoDB incident over silently dropping the batch.
+///
+/// A hit on an id that is not a link is neither: it is simply not a click.
+/// The history buckets stay best-effort, as the counter once was, and never cause a
+/// retry -- that would count the record's clicks a second time.
 pub async fn function_handler(
     url_shortener: &UrlShortener,
+    click_history: &ClickHistory,
+    log_format: &LogFormat,
     event: LambdaEvent<KinesisEvent>
-    ) -> Result<(), Error> {
-    // Extract some useful information from the request
-    let records = event.payload.records;
+    ) -> Result<KinesisEventResponse, Error> {
+    let mut response = KinesisEventResponse::default();
+    let mut sequence_numbers = vec![];
+    let mut tally = ClickTally::default();
 
-    for record in records {
-        let decoded_data: &[u8] = &record.kinesis.data;
+    for (index, record) in event.payload.records.into_iter().enumerate() {
+        let sequence_number = record.kinesis.sequence_number;
 
-        // Convert into string or fail if it does not work, something must be off
-        let string_data = String::from_utf8(decoded_data.to_vec())
-            .expect("Failed to convert the Kinesis data from UTF-8 into a String");
+        for parsed in log_format.parse_record(&record.kinesis.data) {
+            let log = match parsed {
+                Ok(log) => log,
+                Err(e) => {
+                    tracing::warn!("Skipping unparseable log line in record {sequence_number}: {e}");
+                    continue;
+                }
+            };
+            if !tally.add_visit(&log, index) {
+                tracing::debug!("Not a link visit: {} ({:?})", log.uri_stem, log.status);
+            }
+        }
+        sequence_numbers.push(sequence_number);
+    }
 
-        // Data coming in looks like this:
-        // "1739035776.180\t24.18.218.96\t302\t/k120oizrul/n"
-        // I know ... TSV 🙄
-        let fields: Vec<&str> = string_data.split('\t').collect();
+    if tally.is_empty() {
+        return Ok(response);
+    }
 
-        // Put it in a Struct
-        let analytics = CfAnalyticsData {
-            _timestamp: fields[0].to_string(),
-            _source_ip: fields[1].to_string(),
-            _status_code: fields[2].to_string(),
-            link_id: fields[3]
-                .trim()                     // .trim() removes the `/n`
-                .trim_start_matches("/")    // Remove the "/" at the front
-                .to_string(), 
-        };
-        if let Err(e) = url_shortener.increment_click_count(&analytics.link_id).await {
-            // Log the error but do not fail the function. As this is not a critical thing.
-            tracing::warn!("Failed to increment click count for {}: {:?}", analytics.link_id, e);
-        }
+    let failed_from: Option<usize> = stream::iter(tally.into_links())
+        .map(|(link_id, link)| async move {
+            match url_shortener.add_clicks(&link_id, link.clicks, link.bot_clicks).await {
+                Ok(()) => {}
+                Err(AppError::NotFound(_)) => {
+                    tracing::debug!("Hits on {link_id}, which is not a link");
+                    return None;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to add {} clicks to {link_id}: {:?}", link.clicks, e);
+                    return Some(link.first_record);
+                }
+            }
+
+            for (hour, clicks) in link.by_hour {
+                if let Err(e) = click_history.record_clicks(&link_id, hour, clicks).await {
+                    tracing::warn!("Failed to record click history for {link_id}: {:?}", e);
+                }
+            }
+            None
+        })
+        .buffer_unordered(WRITE_CONCURRENCY)
+        .filter_map(|failed| async move { failed })
+        .fold(None, |earliest: Option<usize>, record| async move {
+            Some(earliest.map_or(record, |e| e.min(record)))
+        })
+        .await;
+
+    if let Some(index) = failed_from {
+        tracing::error!("Retrying the batch from record {}", sequence_numbers[index]);
+        response.add_failure(sequence_numbers[index].clone());
     }
 
-    Ok(())
+    Ok(response)
 }
 
 #[tokio::main]
@@ -55,11 +113,17 @@ async fn main() -> Result<(), Error> {
     // Get the table name from the env variables
     let table_name = env::var("TABLE_NAME").expect("No TABLE_NAME environment variable set");
     let shortener_domain = env::var("SHORTENER_DOMAIN").expect("No SHORTENER_DOMAIN environment variable set");
+    let click_table_name = env::var("CLICK_TABLE_NAME").expect("No CLICK_TABLE_NAME environment variable set");
+    // The real-time log config's field list, in order. Set from the same array as the
+    // config itself, so the two cannot disagree about column positions.
+    let log_fields = env::var("LOG_FIELDS").expect("No LOG_FIELDS environment variable set");
+    let log_format = LogFormat::from_config(&log_fields).expect("LOG_FIELDS is not a usable field list");
     // Set up the AWS DynamoDB SDK Client
     let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
 
+    let click_history = ClickHistory::new(&click_table_name, dynamodb_client.clone());
     let shortener = UrlShortener::new(&table_name, &shortener_domain, dynamodb_client);
 
-    run(service_fn(|event| function_handler(&shortener, event))).await
+    run(service_fn(|event| function_handler(&shortener, &click_history, &log_format, event))).await
 }
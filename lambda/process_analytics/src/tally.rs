@@ -0,0 +1,169 @@
+//! Coalesces a batch of link visits into one count per link before anything is written.
+//!
+//! A spike on one link puts hundreds of its hits in the same batch. Counting them here
+//! turns those into a single `ADD Clicks :n` and one history write per hour touched,
+//! instead of a write per hit.
+
+use std::collections::{BTreeMap, HashMap};
+
+use shared::clicks::hour_bucket;
+
+use crate::bots::{self, Traffic};
+use crate::log_parser::LogRecord;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LinkTally {
+    /// Human visits only; see [`crate::bots`].
+    pub clicks: u32,
+    pub bot_clicks: u32,
+    /// Human clicks per hour bucket start, for the click history. Bots are left out of
+    /// the history as well as the total, or the sparkline would show every unfurl.
+    pub by_hour: BTreeMap<i64, u32>,
+    /// Position in the batch of the first record that counted towards this link. If the
+    /// link's write fails, that is the record Lambda has to resume from.
+    pub first_record: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ClickTally {
+    links: HashMap<String, LinkTally>,
+}
+
+impl ClickTally {
+    /// Counts `log` if it followed a link through (see [`LogRecord::followed_link_id`]),
+    /// seen in record `record` of the batch. `false` if it did not count.
+    pub fn add_visit(&mut self, log: &LogRecord, record: usize) -> bool {
+        let Some(link_id) = log.followed_link_id() else {
+            return false;
+        };
+        self.add(link_id, log.timestamp, record, bots::classify(log.user_agent.as_deref()));
+        true
+    }
+
+    /// Counts one visit to `link_id` at `timestamp`, seen in record `record` of the batch.
+    pub fn add(&mut self, link_id: &str, timestamp: i64, record: usize, traffic: Traffic) {
+        let tally = self
+            .links
+            .entry(link_id.to_string())
+            .or_insert_with(|| LinkTally { first_record: record, ..Default::default() });
+        match traffic {
+            Traffic::Human => {
+                tally.clicks += 1;
+                *tally.by_hour.entry(hour_bucket(timestamp)).or_default() += 1;
+            }
+            Traffic::Bot => tally.bot_clicks += 1,
+        }
+        tally.first_record = tally.first_record.min(record);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    pub fn into_links(self) -> impl Iterator<Item = (String, LinkTally)> {
+        self.links.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 1_739_034_000;
+
+    #[test]
+    fn repeat_hits_on_one_link_become_one_count() {
+        let mut tally = ClickTally::default();
+        for record in 0..500 {
+            tally.add("viral1", HOUR + 10, record, Traffic::Human);
+        }
+        let links: Vec<_> = tally.into_links().collect();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].1.clicks, 500);
+        assert_eq!(links[0].1.by_hour, BTreeMap::from([(HOUR, 500)]));
+    }
+
+    #[test]
+    fn hits_are_split_by_link_and_by_hour() {
+        let mut tally = ClickTally::default();
+        tally.add("a", HOUR + 5, 0, Traffic::Human);
+        tally.add("b", HOUR + 6, 1, Traffic::Human);
+        tally.add("a", HOUR + 3_600, 2, Traffic::Human);
+
+        let links: HashMap<_, _> = tally.into_links().collect();
+        assert_eq!(links["a"].clicks, 2);
+        assert_eq!(links["a"].by_hour, BTreeMap::from([(HOUR, 1), (HOUR + 3_600, 1)]));
+        assert_eq!(links["b"].clicks, 1);
+    }
+
+    #[test]
+    fn bot_hits_are_counted_apart_and_kept_out_of_the_history() {
+        let mut tally = ClickTally::default();
+        tally.add("a", HOUR, 0, Traffic::Bot);
+        tally.add("a", HOUR, 1, Traffic::Human);
+        tally.add("b", HOUR, 2, Traffic::Bot);
+
+        let links: HashMap<_, _> = tally.into_links().collect();
+        assert_eq!((links["a"].clicks, links["a"].bot_clicks), (1, 1));
+        assert_eq!(links["a"].by_hour, BTreeMap::from([(HOUR, 1)]));
+        assert_eq!(links["a"].first_record, 0, "a bot hit still has to be written");
+        assert_eq!((links["b"].clicks, links["b"].bot_clicks), (0, 1));
+        assert!(links["b"].by_hour.is_empty());
+    }
+
+    /// Only the redirect sent anyone on; the other answers were for the same link.
+    #[test]
+    fn only_redirects_are_tallied() {
+        let format = crate::log_parser::LogFormat::from_config("timestamp,cs-uri-stem,sc-status,cs-user-agent").unwrap();
+        let mut tally = ClickTally::default();
+        let lines = [
+            "1739034010\t/abc1234\t200\tMozilla/5.0",
+            "1739034011\t/abc1234\t401\tMozilla/5.0",
+            "1739034012\t/abc1234\t410\tMozilla/5.0",
+            "1739034013\t/abc1234\t403\tMozilla/5.0",
+            "1739034014\t/abc1234%2B\t200\tMozilla/5.0",
+            "1739034015\t/abc1234\t-\tMozilla/5.0",
+            "1739034016\t/abc1234\t302\tMozilla/5.0",
+        ];
+        let counted: Vec<bool> = lines
+            .iter()
+            .enumerate()
+            .map(|(record, line)| tally.add_visit(&format.parse_line(line).unwrap(), record))
+            .collect();
+        assert_eq!(counted, [false, false, false, false, false, false, true]);
+
+        let links: HashMap<_, _> = tally.into_links().collect();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links["abc1234"].clicks, 1);
+        assert_eq!(links["abc1234"].first_record, 6);
+    }
+
+    /// A preview-mode link's page is a 200; its Continue button comes back through the
+    /// link as `?continue`, which CloudFront logs without the query and visit_link
+    /// answers with the redirect.
+    #[test]
+    fn a_preview_followed_through_continue_counts_once() {
+        let format = crate::log_parser::LogFormat::from_config("timestamp,cs-uri-stem,sc-status,cs-user-agent").unwrap();
+        let mut tally = ClickTally::default();
+        for (record, line) in ["1739034010\t/abc1234\t200\tMozilla/5.0", "1739034012\t/abc1234\t302\tMozilla/5.0"]
+            .iter()
+            .enumerate()
+        {
+            tally.add_visit(&format.parse_line(line).unwrap(), record);
+        }
+        let links: HashMap<_, _> = tally.into_links().collect();
+        assert_eq!(links["abc1234"].clicks, 1);
+    }
+
+    #[test]
+    fn remembers_the_first_record_each_link_came_from() {
+        let mut tally = ClickTally::default();
+        tally.add("a", HOUR, 3, Traffic::Human);
+        tally.add("b", HOUR, 4, Traffic::Human);
+        tally.add("a", HOUR, 7, Traffic::Human);
+
+        let links: HashMap<_, _> = tally.into_links().collect();
+        assert_eq!(links["a"].first_record, 3);
+        assert_eq!(links["b"].first_record, 4);
+    }
+}
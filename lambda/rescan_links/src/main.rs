@@ -0,0 +1,114 @@
+use futures::future::join_all;
+use lambda_runtime::{run, service_fn, tracing, Error, LambdaEvent};
+use serde::Serialize;
+use serde_json::Value;
+use shared::audit::{AuditAction, AuditEvent, AuditLog, ClientAddress};
+use shared::core::UrlShortener;
+use shared::reputation::ReputationPolicy;
+use shared::safe_browsing::SafeBrowsing;
+use shared::secrets::SecretCache;
+use std::env;
+
+mod segment;
+
+use segment::rescan_segment;
+
+/// Parallel scan segments, walked side by side within one invocation.
+const TOTAL_SEGMENTS: i32 = 4;
+
+#[derive(Debug, Default, Serialize)]
+struct RescanSummary {
+    scanned: usize,
+    disabled: usize,
+}
+
+/// Re-checks every link's destination against the reputation providers, on a schedule.
+///
+/// A destination is checked once when the link is made, but a domain can turn malicious
+/// long after. This walks the link table in [`TOTAL_SEGMENTS`] parallel scan segments
+/// and disables the links whose destination is now flagged (see `rescan_segment`);
+/// `visit_link` then shows a warning in place of the redirect. Each one is recorded in
+/// its owner's audit log, which is how the owner finds out.
+///
+/// Links already disabled are skipped, so a run costs a read of the table plus one
+/// reputation check per 500 live links. A segment that fails does not stop the others;
+/// the invocation still fails afterwards, so the error is visible, and the next run
+/// picks the segment up from the start.
+async fn function_handler(
+    url_shortener: &UrlShortener,
+    reputation: &ReputationPolicy,
+    audit_log: &AuditLog,
+    _event: LambdaEvent<Value>,
+) -> Result<RescanSummary, Error> {
+    let outcomes = join_all(
+        (0..TOTAL_SEGMENTS).map(|segment| rescan_segment(url_shortener, reputation, segment, TOTAL_SEGMENTS)),
+    )
+    .await;
+
+    let mut summary = RescanSummary::default();
+    let mut events = vec![];
+    let mut failed = 0;
+    for (segment, outcome) in outcomes.into_iter().enumerate() {
+        match outcome {
+            Ok(outcome) => {
+                summary.scanned += outcome.scanned;
+                summary.disabled += outcome.disabled.len();
+                events.extend(outcome.disabled.into_iter().map(|(destination, provider)| {
+                    AuditEvent::new(
+                        AuditAction::LinkDisabled,
+                        destination.owner_id.as_deref(),
+                        ClientAddress::default(),
+                    )
+                    .with_target(destination.link_id)
+                    .with_detail(provider)
+                }));
+            }
+            Err(e) => {
+                tracing::error!("Rescan of segment {segment} failed: {:?}", e);
+                failed += 1;
+            }
+        }
+    }
+    audit_log.record_all(events).await;
+
+    tracing::info!(
+        "Rescan finished: {} links checked, {} disabled",
+        summary.scanned,
+        summary.disabled
+    );
+    if failed > 0 {
+        return Err(format!("{failed} of {TOTAL_SEGMENTS} scan segments failed").into());
+    }
+    Ok(summary)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    // Get the table name from the env variables
+    let table_name = env::var("TABLE_NAME").expect("No TABLE_NAME environment variable set");
+    let shortener_domain = env::var("SHORTENER_DOMAIN").expect("No SHORTENER_DOMAIN environment variable set");
+    let secret_arn = env::var("GOOGLE_API_KEY_SECRET").expect("No GOOGLE_API_KEY_SECRET environment variable set");
+    let audit_table_name = env::var("AUDIT_TABLE_NAME").expect("No AUDIT_TABLE_NAME environment variable set");
+    // Set up the AWS DynamoDB SDK Client
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+    let secrets_client = aws_sdk_secretsmanager::Client::new(&config);
+
+    // Only Safe Browsing uses it; the destinations themselves are never fetched.
+    let http_client = shared::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()?;
+
+    // Configured like link creation, so a rescan applies the same rules a new link meets.
+    let reputation = ReputationPolicy::from_env(SafeBrowsing::new(
+        SecretCache::new(secrets_client, &secret_arn),
+        http_client,
+    ))?;
+
+    let audit_log = AuditLog::new(&audit_table_name, dynamodb_client.clone());
+    let shortener = UrlShortener::new(&table_name, &shortener_domain, dynamodb_client);
+
+    run(service_fn(|event| function_handler(&shortener, &reputation, &audit_log, event))).await
+}
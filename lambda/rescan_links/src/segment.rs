@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+
+use lambda_runtime::tracing;
+use shared::core::{Destination, UrlShortener};
+use shared::error::AppError;
+use shared::reputation::{Refusal, ReputationPolicy};
+use shared::store::LinkStore;
+
+/// Links per scan page, and so per reputation check: Safe Browsing takes at most 500
+/// `threatEntries` in one request, so a page without redirect rules is one request.
+pub const PAGE_SIZE: i32 = 500;
+
+/// What walking one segment found.
+#[derive(Debug, Default)]
+pub struct SegmentOutcome {
+    pub scanned: usize,
+    /// Each link disabled, with the provider that flagged it.
+    pub disabled: Vec<(Destination, &'static str)>,
+}
+
+/// Walks one scan segment to the end, checking each page's destinations together and
+/// disabling the links whose destination is flagged.
+///
+/// Only a provider's positive verdict disables a link. A check that could not be made,
+/// even by a provider configured to fail closed, leaves the page as it was: refusing a
+/// new link while a provider is down is reasonable, switching off links that worked
+/// yesterday because of an outage is not. The next run looks again.
+pub async fn rescan_segment<S: LinkStore>(
+    shortener: &UrlShortener<S>,
+    reputation: &ReputationPolicy,
+    segment: i32,
+    total_segments: i32,
+) -> Result<SegmentOutcome, AppError> {
+    let mut outcome = SegmentOutcome::default();
+    let mut start = None;
+
+    loop {
+        let page = shortener
+            .scan_destinations(segment, total_segments, start, PAGE_SIZE)
+            .await?;
+        // Links, not destinations: a link with rules has several.
+        outcome.scanned += page.destinations.iter().map(|d| &d.link_id).collect::<HashSet<_>>().len();
+
+        // Many links can share a destination; ask about each URL once.
+        let urls: Vec<String> = page
+            .destinations
+            .iter()
+            .map(|d| d.url.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let refused = reputation.refused(&urls).await;
+
+        // A link is disabled once, however many of its rule destinations are flagged.
+        let mut disabled = HashSet::new();
+        for destination in page.destinations {
+            let Some(Refusal::Flagged(provider)) = refused.get(&destination.url).copied() else {
+                continue;
+            };
+            if !disabled.insert(destination.link_id.clone()) {
+                continue;
+            }
+            match shortener.disable_link(&destination.link_id, provider).await {
+                Ok(()) => {
+                    tracing::warn!(
+                        "Disabled link {}: {provider} flagged {}",
+                        destination.link_id,
+                        destination.url
+                    );
+                    outcome.disabled.push((destination, provider));
+                }
+                // Deleted since the page was read.
+                Err(AppError::NotFound(_)) => {}
+                // Left for the next run rather than abandoning the rest of the segment.
+                Err(e) => tracing::error!("Failed to disable link {}: {:?}", destination.link_id, e),
+            }
+        }
+
+        match page.next {
+            Some(next) => start = Some(next),
+            None => return Ok(outcome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::core::ShortenUrlRequest;
+    use shared::reputation::{DomainList, FailureMode};
+    use shared::store::InMemoryLinkStore;
+    use shared::url_info::UrlInfo;
+
+    // Nothing listens on the discard port, so the scrape fails fast and offline.
+    fn offline_url_info() -> UrlInfo {
+        UrlInfo::new(shared::Client::builder().no_proxy().build().unwrap())
+    }
+
+    async fn shortener_with(urls: &[&str]) -> UrlShortener<InMemoryLinkStore> {
+        let shortener = UrlShortener::with_store(InMemoryLinkStore::default(), "krtk.rs");
+        for url in urls {
+            let req: ShortenUrlRequest =
+                serde_json::from_value(serde_json::json!({ "url_to_shorten": url })).unwrap();
+            shortener
+                .shorten_url(req, &offline_url_info(), "owner-a")
+                .await
+                .unwrap();
+        }
+        shortener
+    }
+
+    fn blocking(domain: &str) -> ReputationPolicy {
+        ReputationPolicy::new().with_provider(
+            DomainList::new([domain.to_string()], []),
+            FailureMode::Open,
+        )
+    }
+
+    #[tokio::test]
+    async fn flagged_destinations_are_disabled_and_the_rest_left_alone() {
+        let shortener = shortener_with(&[
+            "http://127.0.0.1:9/ok",
+            "http://localhost:9/bad",
+            "http://localhost:9/also-bad",
+        ])
+        .await;
+
+        let mut scanned = 0;
+        let mut disabled = vec![];
+        for segment in 0..3 {
+            let outcome = rescan_segment(&shortener, &blocking("localhost"), segment, 3).await.unwrap();
+            scanned += outcome.scanned;
+            disabled.extend(outcome.disabled);
+        }
+        assert_eq!(scanned, 3);
+        assert_eq!(disabled.len(), 2);
+        assert!(disabled.iter().all(|(d, provider)| {
+            d.url.contains("localhost") && *provider == "the domain blocklist"
+        }));
+
+        for (destination, _) in &disabled {
+            let target = shortener.retrieve_url(&destination.link_id).await.unwrap().unwrap();
+            assert!(target.is_disabled());
+            assert_eq!(target.disabled_reason.as_deref(), Some("the domain blocklist"));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_flagged_rule_destination_disables_its_link_once() {
+        let shortener = UrlShortener::with_store(InMemoryLinkStore::default(), "krtk.rs");
+        let req: ShortenUrlRequest = serde_json::from_value(serde_json::json!({
+            "url_to_shorten": "http://127.0.0.1:9/ok",
+            "rules": [
+                { "device": "ios", "destination": "http://localhost:9/bad" },
+                { "country": "DE", "destination": "http://localhost:9/also-bad" },
+            ],
+        }))
+        .unwrap();
+        let link = shortener.shorten_url(req, &offline_url_info(), "owner-a").await.unwrap();
+
+        let outcome = rescan_segment(&shortener, &blocking("localhost"), 0, 1).await.unwrap();
+        assert_eq!(outcome.disabled.len(), 1);
+        assert_eq!(outcome.disabled[0].0.link_id, link.link_id);
+        assert!(shortener.retrieve_url(&link.link_id).await.unwrap().unwrap().is_disabled());
+    }
+
+    /// A link disabled last run is not scanned, checked or recorded again.
+    #[tokio::test]
+    async fn a_second_run_skips_links_already_disabled() {
+        let shortener = shortener_with(&["http://localhost:9/bad"]).await;
+        let first = rescan_segment(&shortener, &blocking("localhost"), 0, 1).await.unwrap();
+        let second = rescan_segment(&shortener, &blocking("localhost"), 0, 1).await.unwrap();
+        assert_eq!(first.disabled.len(), 1);
+        assert_eq!(second.scanned, 0);
+        assert!(second.disabled.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use lambda_http::http::StatusCode;
+use lambda_http::{run, service_fn, tracing, Error, IntoResponse, Request, RequestExt, RequestPayloadExt};
+
+use shared::auth::{owner_from_request, require_scope, Scope};
+use shared::core::{ShortenUrlRequest, UrlShortener};
+use shared::error::AppError;
+use shared::reputation::ReputationPolicy;
+use shared::response::{error_response, json_response};
+use shared::safe_browsing::SafeBrowsing;
+use shared::secrets::SecretCache;
+use shared::url_info::UrlInfo;
+
+use std::env;
+
+// The main bit of code that will run every time this function is triggered
+async fn function_handler(
+    url_shortener: &UrlShortener,
+    url_info: &UrlInfo,
+    reputation: &ReputationPolicy,
+    event: Request,
+) -> Result<impl IntoResponse, Error> {
+    // Tracing
+    tracing::info!("Received event: {:?}", event);
+
+    // Identity comes from the authorizer context, never from the request body. The
+    // ownership check itself happens in the conditional write, against this value.
+    let owner_sub = match owner_from_request(&event) {
+        Ok(sub) => sub,
+        Err(e) => {
+            tracing::error!("rejecting update request without owner identity: {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    // Repointing a link is a write to it, so it takes the same scope as creating one.
+    if let Err(e) = require_scope(&event, Scope::LinksCreate) {
+        return error_response(&e);
+    }
+
+    let link_id = event
+        .path_parameters_ref()
+        .and_then(|params| params.first("linkId"))
+        .unwrap_or("");
+
+    if link_id.is_empty() {
+        return error_response(&AppError::Validation("Link ID is required".to_string()));
+    }
+
+    // The body is the same shape as a create, so it goes through the same validation.
+    let update_request = match event.payload::<ShortenUrlRequest>()? {
+        Some(req) => req,
+        None => {
+            return error_response(&AppError::Validation(
+                "Invalid request body: expected a 'url_to_shorten' field".to_string(),
+            ));
+        }
+    };
+
+    let validated = match update_request
+        .validate(&url_shortener.shortener_domain, reputation)
+        .await
+    {
+        Ok(req) => req,
+        Err(e) => {
+            tracing::error!("Failed to validate new destination 💥 : {:?}", e);
+            return error_response(&e);
+        }
+    };
+
+    match url_shortener
+        .update_destination(link_id, validated, url_info, &owner_sub)
+        .await
+    {
+        Ok(updated) => json_response(&StatusCode::OK, &updated),
+        Err(e) => {
+            tracing::error!("Failed to update link {} 💥 : {:?}", link_id, e);
+            error_response(&e)
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing::init_default_subscriber();
+
+    // Get the table name from the env variables
+    let table_name = env::var("TABLE_NAME").expect("No TABLE_NAME environment variable set");
+    let shortener_domain = env::var("SHORTENER_DOMAIN").expect("No SHORTENER_DOMAIN environment variable set");
+    let secret_arn = env::var("GOOGLE_API_KEY_SECRET").expect("No GOOGLE_API_KEY_SECRET environment variable set");
+    // Set up the AWS DynamoDB SDK Client
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
+    let secrets_client = aws_sdk_secretsmanager::Client::new(&config);
+
+    // Http Client for refreshing the metadata of the new destination
+    let http_client = shared::Client::builder()
+        .timeout(std::time::Duration::from_secs(2))
+        .build()?;
+
+    let reputation = ReputationPolicy::from_env(SafeBrowsing::new(
+        SecretCache::new(secrets_client, &secret_arn),
+        http_client.clone(),
+    ))?;
+
+    let url_info = UrlInfo::new(http_client);
+
+    let shortener = UrlShortener::new(&table_name, &shortener_domain, dynamodb_client);
+
+    run(service_fn(|event| {
+        function_handler(&shortener, &url_info, &reputation, event)
+    }))
+    .await
+}
@@ -0,0 +1,303 @@
+use lambda_http::http::header::SET_COOKIE;
+use lambda_http::http::{Method, StatusCode};
+use lambda_http::{tracing, Body, Error, IntoResponse, Request, RequestExt, RequestPayloadExt, Response};
+use serde::Deserialize;
+
+use shared::core::{destination_host, LinkTarget, UrlShortener};
+use shared::store::LinkStore;
+use shared::error::AppError;
+use shared::password::{access_cookie, has_access, verify_password};
+use shared::rules::Visitor;
+use shared::secrets::SecretCache;
+use shared::response::{empty_response, html_response, redirect_response, redirect_response_with_cookie};
+use shared::templates::{LinkDisabled, LinkExpired, LinkPassword, LinkPreview, Template};
+
+
+/// The body of the password prompt's form post.
+#[derive(Deserialize)]
+struct PasswordForm {
+    #[serde(default)]
+    password: String,
+}
+
+/// The key that signs unlock cookies, fetched from Secrets Manager on first use.
+///
+/// Lazy on purpose: most links have no password, and a Secrets Manager hiccup at cold
+/// start should not take down redirects that never needed the key. Held in a
+/// [`SecretCache`], so a rotated key is picked up once the cached one goes stale;
+/// cookies signed with the old key then just ask for the password again.
+pub struct CookieKey(SecretCache);
+
+impl CookieKey {
+    pub fn new(secrets_client: aws_sdk_secretsmanager::Client, secret_arn: &str) -> Self {
+        Self(SecretCache::new(secrets_client, secret_arn))
+    }
+
+    async fn get(&self) -> Result<Vec<u8>, AppError> {
+        self.0.get().await.map(String::into_bytes)
+    }
+}
+
+// The main bit of code that will run every time this function is triggered
+pub async fn function_handler<S: LinkStore>(
+    url_shortener: &UrlShortener<S>,
+    cookie_key: &CookieKey,
+    event: Request,
+) -> Result<impl IntoResponse, Error> {
+    // Tracing. Not the whole event: a prompt submission carries the passphrase in its body.
+    tracing::info!("Received {} {}", event.method(), event.uri().path());
+    // Try to get link ID, if there is none, just return empty
+    let link_id = event
+        .path_parameters_ref()
+        .and_then(|params| params.first("linkId"))
+        .unwrap_or(""); // TODO: Should be an Option
+    let (link_id, asked) = preview_request(link_id, &event);
+
+    if link_id.is_empty() {
+        return empty_response(&StatusCode::NOT_FOUND);
+    }
+    // As the pages print it, ahead of the id: `krtk.rs/abc1234`.
+    let domain = format!("{}/", url_shortener.shortener_domain);
+
+    let full_url = url_shortener
+        .retrieve_url(link_id)
+        .await;
+
+    match full_url {
+        Err(e) => {
+            tracing::error!("Failed to retrieve URL 🧨 : {:?}", e);
+            empty_response(&StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        Ok(None) => empty_response(&StatusCode::NOT_FOUND),
+        // Ahead of every other case: a password or a limit still in force must not be
+        // what stands between a visitor and a destination known to be malicious.
+        Ok(Some(target)) if target.is_disabled() => {
+            let body = LinkDisabled {
+                link_id: link_id.to_string(),
+                domain: &domain,
+                destination: target.original_link,
+                reason: target
+                    .disabled_reason
+                    .unwrap_or_else(|| "a safety check".to_string()),
+            }
+            .render()?;
+            // Not a 2xx, so nothing that follows links treats the warning as the page.
+            html_response(&StatusCode::FORBIDDEN, body)
+        }
+        // 410 rather than 404: the link existed and its owner retired it, which is worth
+        // telling a visitor who is holding a printed copy of it.
+        Ok(Some(target)) if target.is_expired(chrono::Utc::now().timestamp()) => {
+            let body = LinkExpired {
+                link_id: link_id.to_string(),
+                domain: &domain,
+            }
+            .render()?;
+            html_response(&StatusCode::GONE, body)
+        }
+        Ok(Some(target)) if target.password_hash.is_some() => {
+            let preview = asked.unwrap_or(target.preview);
+            protected_response(link_id, &domain, &target, preview, cookie_key, &event).await
+        }
+        // Only the password prompt posts back here; anything else posting to an open
+        // link is not a visitor.
+        Ok(Some(_)) if event.method() == Method::POST => empty_response(&StatusCode::METHOD_NOT_ALLOWED),
+        Ok(Some(target)) if asked.unwrap_or(target.preview) => preview_response(link_id, &domain, &target, &event),
+        Ok(Some(target)) => redirect_response(target.destination_for(&visitor(&event))),
+    }
+}
+
+/// The preview page, for the page this visitor would be sent to, rules and all.
+///
+/// A 200 and never a redirect, so the analytics do not count it as a click.
+fn preview_response(link_id: &str, domain: &str, target: &LinkTarget, event: &Request) -> Result<Response<Body>, Error> {
+    let destination = target.destination_for(&visitor(event)).to_string();
+    let body = LinkPreview {
+        link_id: link_id.to_string(),
+        domain,
+        host: destination_host(&destination),
+        destination,
+        title: target.title.clone(),
+        description: target.description.clone(),
+        image: target.image.clone(),
+    }
+    .render()?;
+    html_response(&StatusCode::OK, body)
+}
+
+/// The visitor as redirect rules see them. CloudFront adds `CloudFront-Viewer-Country`
+/// and the `/?*` behaviour's origin request policy passes it on with `User-Agent`.
+fn visitor(event: &Request) -> Visitor {
+    let header = |name: &str| event.headers().get(name).and_then(|value| value.to_str().ok());
+    Visitor::from_headers(header("user-agent"), header("cloudfront-viewer-country"))
+}
+
+/// Splits a preview request from the link it is for, and says whether the visitor
+/// asked for the preview (`Some(true)`), asked to skip it (`Some(false)`) or left it to
+/// the link (`None`).
+///
+/// `krtk.rs/abc1234+` previews any link; `?preview` does the same for clients that
+/// would rather not touch the path. A slug can never contain `+` (see
+/// `check_custom_slug`), so the suffix cannot collide with a real id. `?continue` is
+/// where the preview page's button leads: through the link, so the visit is a redirect
+/// the analytics count and a click limit sees, and not straight to the destination.
+///
+/// A preview never gets past the other pages: a disabled or expired link answers
+/// exactly as it would without one, and a protected one shows the preview only once
+/// unlocked, so the suffix is no way around a password.
+fn preview_request<'a>(link_id: &'a str, event: &Request) -> (&'a str, Option<bool>) {
+    match link_id.strip_suffix('+') {
+        Some(link_id) => (link_id, Some(true)),
+        None => {
+            let query = event.query_string_parameters_ref();
+            let has = |name: &str| query.is_some_and(|params| params.first(name).is_some());
+            let asked = if has("preview") {
+                Some(true)
+            } else if has("continue") {
+                Some(false)
+            } else {
+                None
+            };
+            (link_id, asked)
+        }
+    }
+}
+
+/// Answers a visit to a password-protected link.
+///
+/// A GET redirects if the browser already holds a valid unlock cookie and shows the
+/// prompt otherwise. A POST is the prompt's form: the right passphrase redirects and
+/// sets the cookie, a wrong one re-renders the prompt with a 401.
+///
+/// With `preview`, an unlocked visit gets the preview page where it would have been
+/// redirected, exactly as an open link would.
+///
+/// Guessing is bounded by the API stage throttle, not by anything per link.
+async fn protected_response(
+    link_id: &str,
+    domain: &str,
+    target: &LinkTarget,
+    preview: bool,
+    cookie_key: &CookieKey,
+    event: &Request,
+) -> Result<Response<Body>, Error> {
+    let (Some(hash), Some(salt)) = (target.password_hash.as_deref(), target.password_salt.as_deref()) else {
+        // Half a password is a corrupt item. Fail closed rather than redirect.
+        tracing::error!("Link {link_id} has a password hash without a salt");
+        return empty_response(&StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let key = match cookie_key.get().await {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::error!("Cannot check access to protected link {link_id} 🧨 : {:?}", e);
+            return empty_response(&StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let now = chrono::Utc::now().timestamp();
+
+    if event.method() == Method::POST {
+        let candidate = event
+            .payload::<PasswordForm>()
+            .ok()
+            .flatten()
+            .map(|form| form.password)
+            .unwrap_or_default();
+
+        if verify_password(&candidate, salt, hash) {
+            let cookie = access_cookie(&key, link_id, hash, now);
+            if preview {
+                let mut response = preview_response(link_id, domain, target, event)?;
+                response.headers_mut().insert(SET_COOKIE, cookie.parse()?);
+                return Ok(response);
+            }
+            return redirect_response_with_cookie(target.destination_for(&visitor(event)), &cookie);
+        }
+        tracing::info!("Wrong password submitted for link {link_id}");
+        return prompt_response(link_id, domain, preview, true);
+    }
+
+    let cookies = event
+        .headers()
+        .get("cookie")
+        .and_then(|value| value.to_str().ok());
+    if has_access(cookies, &key, link_id, hash, now) {
+        if preview {
+            return preview_response(link_id, domain, target, event);
+        }
+        return redirect_response(target.destination_for(&visitor(event)));
+    }
+    prompt_response(link_id, domain, preview, false)
+}
+
+fn prompt_response(link_id: &str, domain: &str, preview: bool, failed: bool) -> Result<Response<Body>, Error> {
+    let body = LinkPassword {
+        link_id: link_id.to_string(),
+        domain,
+        preview,
+        failed,
+    }
+    .render()?;
+    let status = if failed { StatusCode::UNAUTHORIZED } else { StatusCode::OK };
+    html_response(&status, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use shared::core::ShortenUrlRequest;
+    use shared::store::InMemoryLinkStore;
+    use shared::url_info::UrlInfo;
+
+    fn cookie_key() -> CookieKey {
+        let config = aws_sdk_secretsmanager::Config::builder()
+            .behavior_version(aws_config::BehaviorVersion::v2026_01_12())
+            .build();
+        CookieKey::new(aws_sdk_secretsmanager::Client::from_conf(config), "unused")
+    }
+
+    fn visit(path: &str, query: &[(&str, &str)]) -> Request {
+        let link_id = path.trim_start_matches('/').to_string();
+        let query: HashMap<String, String> =
+            query.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        lambda_http::http::Request::builder()
+            .uri(path)
+            .body(Body::Empty)
+            .unwrap()
+            .with_path_parameters(HashMap::from([("linkId".to_string(), link_id)]))
+            .with_query_string_parameters(query)
+    }
+
+    /// The preview page is a 200, which is not a click; its button comes back through the
+    /// link and gets the redirect, which is.
+    #[tokio::test]
+    async fn a_preview_links_continue_button_is_a_counted_redirect() {
+        let shortener = UrlShortener::with_store(InMemoryLinkStore::default(), "go.example");
+        let req: ShortenUrlRequest = serde_json::from_value(serde_json::json!({
+            "url_to_shorten": "http://127.0.0.1:9/page",
+            "preview": true,
+        }))
+        .unwrap();
+        // Nothing listens on the discard port, so the scrape fails fast and offline.
+        let url_info = UrlInfo::new(shared::Client::builder().no_proxy().build().unwrap());
+        let link = shortener.shorten_url(req, &url_info, "owner-a").await.unwrap();
+        let path = format!("/{}", link.link_id);
+
+        let page = function_handler(&shortener, &cookie_key(), visit(&path, &[])).await.unwrap().into_response().await;
+        assert_eq!(page.status(), StatusCode::OK);
+        let Body::Text(html) = page.body() else { panic!("expected the preview page") };
+        let button = format!(r#"href="{path}?continue""#);
+        assert!(html.contains(&button), "got: {html}");
+        let short = format!("go.example/{}", link.link_id);
+        assert!(html.contains(&short), "the page names the domain it was deployed on");
+
+        let followed = function_handler(&shortener, &cookie_key(), visit(&path, &[("continue", "")]))
+            .await
+            .unwrap()
+            .into_response()
+            .await;
+        assert_eq!(followed.status(), StatusCode::FOUND);
+        assert_eq!(followed.headers()["Location"], "http://127.0.0.1:9/page");
+    }
+}
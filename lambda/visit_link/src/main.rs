@@ -1,42 +1,10 @@
-use lambda_http::http::StatusCode;
-use lambda_http::{run, service_fn, tracing, Error, IntoResponse, Request, RequestExt};
+use lambda_http::{run, service_fn, tracing, Error};
 
 use shared::core::UrlShortener;
-use shared::response::{empty_response, redirect_response};
+use visit_link::{function_handler, CookieKey};
 
 use std::env;
 
-// The main bit of code that will run every time this function is triggered
-async fn function_handler(
-    url_shortener: &UrlShortener,
-    event: Request,
-) -> Result<impl IntoResponse, Error> {
-    // Tracing
-    tracing::info!("Received event: {:?}", event);
-    // Try to get link ID, if there is none, just return empty
-    let link_id = event
-        .path_parameters_ref()
-        .and_then(|params| params.first("linkId"))
-        .unwrap_or(""); // TODO: Should be an Option
-
-    if link_id.is_empty() {
-        return empty_response(&StatusCode::NOT_FOUND);
-    }
-
-    let full_url = url_shortener
-        .retrieve_url(link_id)
-        .await;
-
-    match full_url {
-        Err(e) => {
-            tracing::error!("Failed to retrieve URL 🧨 : {:?}", e);
-            empty_response(&StatusCode::INTERNAL_SERVER_ERROR)
-        }
-        Ok(None) => empty_response(&StatusCode::NOT_FOUND),
-        Ok(Some(url)) => redirect_response(&url),
-    }
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     tracing::init_default_subscriber();
@@ -44,11 +12,13 @@ async fn main() -> Result<(), Error> {
     // Get the table name from the env variables
     let table_name = env::var("TABLE_NAME").expect("No TABLE_NAME environment variable set");
     let shortener_domain = env::var("SHORTENER_DOMAIN").expect("No SHORTENER_DOMAIN environment variable set");
+    let cookie_secret_arn = env::var("LINK_COOKIE_SECRET").expect("No LINK_COOKIE_SECRET environment variable set");
     // Set up the AWS DynamoDB SDK Client
     let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12()).load().await;
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
 
     let shortener = UrlShortener::new(&table_name, &shortener_domain, dynamodb_client);
+    let cookie_key = CookieKey::new(aws_sdk_secretsmanager::Client::new(&config), &cookie_secret_arn);
 
-    run(service_fn(|event| function_handler(&shortener, event))).await
+    run(service_fn(|event| function_handler(&shortener, &cookie_key, event))).await
 }
@@ -10,18 +10,20 @@ import {
 } from 'aws-cdk-lib/aws-apigatewayv2-authorizers';
 import { HostedZone, ARecord, RecordTarget } from 'aws-cdk-lib/aws-route53';
 import { Certificate} from 'aws-cdk-lib/aws-certificatemanager';
-import { TableV2, AttributeType, ProjectionType } from 'aws-cdk-lib/aws-dynamodb';
+import { TableV2, AttributeType, ProjectionType, StreamViewType } from 'aws-cdk-lib/aws-dynamodb';
 import { CloudFrontTarget } from 'aws-cdk-lib/aws-route53-targets';
 import { Bucket, BlockPublicAccess } from 'aws-cdk-lib/aws-s3';
 import { BucketDeployment, Source } from 'aws-cdk-lib/aws-s3-deployment';
 import { Endpoint, RealtimeLogConfig, AllowedMethods, CachePolicy, Distribution, OriginProtocolPolicy, OriginRequestPolicy, ViewerProtocolPolicy } from 'aws-cdk-lib/aws-cloudfront';
 import { HttpOrigin, S3BucketOrigin } from 'aws-cdk-lib/aws-cloudfront-origins';
 import { Stream, StreamMode } from 'aws-cdk-lib/aws-kinesis';
-import { KinesisEventSource } from 'aws-cdk-lib/aws-lambda-event-sources';
+import { DynamoEventSource, KinesisEventSource } from 'aws-cdk-lib/aws-lambda-event-sources';
 import { Architecture, LoggingFormat, StartingPosition } from 'aws-cdk-lib/aws-lambda';
 import { FilterPattern, LogGroup, MetricFilter, RetentionDays } from 'aws-cdk-lib/aws-logs';
 import { Alarm, ComparisonOperator, TreatMissingData } from 'aws-cdk-lib/aws-cloudwatch';
 import { Secret } from 'aws-cdk-lib/aws-secretsmanager';
+import { Rule, Schedule } from 'aws-cdk-lib/aws-events';
+import { LambdaFunction } from 'aws-cdk-lib/aws-events-targets';
 import {
   AccountRecovery,
   FeaturePlan,
@@ -31,6 +33,18 @@ import {
 } from 'aws-cdk-lib/aws-cognito';
 import { UserPoolDomainTarget } from 'aws-cdk-lib/aws-route53-targets';
 
+/**
+ * Columns of the CloudFront real-time log, in order. The records carry no header, so
+ * processAnalytics is handed this same list (LOG_FIELDS) to know which column is which.
+ */
+const REALTIME_LOG_FIELDS = [
+  'timestamp',
+  'c-ip',
+  'cs-uri-stem',
+  'sc-status',
+  'cs-user-agent',
+];
+
 /** The public site. */
 const SITE_DOMAIN = 'krtk.rs';
 /** Where the Cognito Hosted UI is served, so a password is never typed into an AWS hostname. */
@@ -41,6 +55,10 @@ interface KrtkRsStackProps extends cdk.StackProps {
   /** ACM cert for auth.krtk.rs. Must be us-east-1 — a Cognito requirement independent of the pool's region. */
   authCertificateArn: string;
   googleApiKeySecret: Secret
+  /** Signing key for the cookie that remembers a correct link password. */
+  linkCookieSecret: Secret
+  /** Signing key for the link list's pagination cursors. */
+  cursorSecret: Secret
 }
 
 export class KrtkRsStack extends cdk.Stack {
@@ -86,12 +104,7 @@ export class KrtkRsStack extends cdk.Stack {
       endPoints: [
         Endpoint.fromKinesisStream(cfAnalyticsStream),
       ],
-      fields: [
-        'timestamp',
-        'c-ip',
-        'cs-uri-stem',
-        'sc-status',
-      ],
+      fields: REALTIME_LOG_FIELDS,
       realtimeLogConfigName: 'krtkAnalytics',
       samplingRate: 100,
     });
@@ -110,6 +123,14 @@ export class KrtkRsStack extends cdk.Stack {
         pointInTimeRecoveryEnabled: true,
       },
       deletionProtection: true,
+      // Only links created with an expiry carry PurgeAt, so TTL never touches a link its
+      // owner did not ask to retire. It trails ExpiresAt by a grace period (see
+      // LINK_EXPIRY_GRACE_SECS in shared/src/core.rs) so visitors get a 410 page before
+      // the item disappears. Expiry itself is enforced on read, never by this.
+      timeToLiveAttribute: 'PurgeAt',
+      // Both images, so indexTags can tell which tags a change added and which it
+      // removed -- including on a delete, where only the old image has them.
+      dynamoStream: StreamViewType.NEW_AND_OLD_IMAGES,
     });
     linkDatabase.addGlobalSecondaryIndex({
       indexName: 'TimeStampIndex',
@@ -124,6 +145,61 @@ export class KrtkRsStack extends cdk.Stack {
       projectionType: ProjectionType.ALL
     })
 
+    // Per-link click history: hourly and daily buckets written by processAnalytics and
+    // read back as a series by getLinks. Separate from linkTable because that table has
+    // no sort key and one bucket per item is what keeps the write a single ADD.
+    const clickTable = new TableV2(this, 'clickTable', {
+      // 'H#<linkId>' or 'D#<linkId>' -- the granularity is part of the partition so a
+      // window is one Query on BucketStart.
+      partitionKey: {
+        name: 'Series',
+        type: AttributeType.STRING,
+      },
+      sortKey: {
+        name: 'BucketStart',
+        type: AttributeType.NUMBER,
+      },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+      deletionProtection: true,
+      // Buckets age out once no range can show them (see shared/src/clicks.rs). No
+      // point-in-time recovery: this is telemetry, and the all-time count that matters
+      // lives on the link item.
+      timeToLiveAttribute: 'PurgeAt',
+    });
+
+    // Owner-scoped tag index: one item per (owner, tag, link), kept in step with
+    // linkTable's Tags by indexTags. OwnerId is the owner key, so listing one owner's
+    // tags is a Query on their partition and never reads anyone else's.
+    const tagTable = new TableV2(this, 'tagTable', {
+      partitionKey: {
+        name: 'OwnerId',
+        type: AttributeType.STRING,
+      },
+      // '<tag>#<linkId>', so every link under one tag is a begins_with on the sort key.
+      sortKey: {
+        name: 'TagLink',
+        type: AttributeType.STRING,
+      },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+      deletionProtection: true,
+      // No point-in-time recovery: everything here can be rebuilt from linkTable.
+    });
+
+    // Saved UTM presets, one item per (owner, preset name). Keyed like tagTable so an
+    // owner's presets are a Query on their own partition.
+    const utmPresetTable = new TableV2(this, 'utmPresetTable', {
+      partitionKey: {
+        name: 'OwnerId',
+        type: AttributeType.STRING,
+      },
+      sortKey: {
+        name: 'Name',
+        type: AttributeType.STRING,
+      },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+      deletionProtection: true,
+    });
+
     // ---------------------------------------------------------------------------
     // Authentication
     // ---------------------------------------------------------------------------
@@ -271,6 +347,38 @@ export class KrtkRsStack extends cdk.Stack {
       projectionType: ProjectionType.ALL,
     });
 
+    // Per-key token buckets for the authorizer's rate limit. Not on the key item itself:
+    // a bucket is written on every request, and the key table's OwnerIndex projects ALL,
+    // so each of those writes would be paid for twice. Buckets are disposable -- a lost
+    // one is a full one -- so DESTROY, no PITR, and TTL clears out idle keys.
+    const rateLimitTable = new TableV2(this, 'rateLimitTable', {
+      partitionKey: {
+        name: 'KeyId',
+        type: AttributeType.STRING,
+      },
+      removalPolicy: cdk.RemovalPolicy.DESTROY,
+      timeToLiveAttribute: 'PurgeAt',
+    });
+
+    // Audit log: key mints, rotations and revokes, link creates and deletes, and
+    // credentials the authorizer rejected (see shared/src/audit.rs). Partitioned per
+    // owner so GET /api/audit is one Query; rejections with no trustworthy owner share
+    // an UNATTRIBUTED partition. RETAIN, since it is what an owner reads after an
+    // incident; no PITR, since events age out through TTL regardless.
+    const auditTable = new TableV2(this, 'auditTable', {
+      partitionKey: {
+        name: 'Owner',
+        type: AttributeType.STRING,
+      },
+      sortKey: {
+        name: 'EventId',
+        type: AttributeType.STRING,
+      },
+      removalPolicy: cdk.RemovalPolicy.RETAIN,
+      deletionProtection: true,
+      timeToLiveAttribute: 'PurgeAt',
+    });
+
     // Explicit, CDK-owned log groups for every function. Without these, Lambda creates the
     // group implicitly on first invocation with retention set to "Never expire", which is
     // both a cost leak and outside CloudFormation's control. Passing the group via the
@@ -281,11 +389,19 @@ export class KrtkRsStack extends cdk.Stack {
       removalPolicy: cdk.RemovalPolicy.DESTROY,
     };
     const createLinkLogGroup = new LogGroup(this, 'createLinkLogGroup', logGroupDefaults);
+    const batchCreateLinkLogGroup = new LogGroup(this, 'batchCreateLinkLogGroup', logGroupDefaults);
+    const updateLinkLogGroup = new LogGroup(this, 'updateLinkLogGroup', logGroupDefaults);
+    const deleteLinkLogGroup = new LogGroup(this, 'deleteLinkLogGroup', logGroupDefaults);
     const getLinksLogGroup = new LogGroup(this, 'getLinksLogGroup', logGroupDefaults);
     const visitLinkLogGroup = new LogGroup(this, 'visitLinkLogGroup', logGroupDefaults);
     const processAnalyticsLogGroup = new LogGroup(this, 'processAnalyticsLogGroup', logGroupDefaults);
+    const rescanLinksLogGroup = new LogGroup(this, 'rescanLinksLogGroup', logGroupDefaults);
+    const indexTagsLogGroup = new LogGroup(this, 'indexTagsLogGroup', logGroupDefaults);
+    const manageTagsLogGroup = new LogGroup(this, 'manageTagsLogGroup', logGroupDefaults);
+    const manageUtmPresetsLogGroup = new LogGroup(this, 'manageUtmPresetsLogGroup', logGroupDefaults);
     const authorizerLogGroup = new LogGroup(this, 'authorizerLogGroup', logGroupDefaults);
     const manageKeysLogGroup = new LogGroup(this, 'manageKeysLogGroup', logGroupDefaults);
+    const getAuditLogGroup = new LogGroup(this, 'getAuditLogGroup', logGroupDefaults);
 
     // 3x Lambda
     const authorizerLambda = new RustFunction(this, 'authorizer', {
@@ -302,10 +418,13 @@ export class KrtkRsStack extends cdk.Stack {
         COGNITO_CLIENT_ID: userPoolClient.userPoolClientId,
         COGNITO_REGION: this.region,
         API_KEY_TABLE_NAME: apiKeyTable.tableName,
+        RATE_LIMIT_TABLE_NAME: rateLimitTable.tableName,
+        AUDIT_TABLE_NAME: auditTable.tableName,
       }
     });
     // Verification is a hash lookup; LastUsedAt is a best-effort write, so it needs both.
     apiKeyTable.grantReadWriteData(authorizerLambda);
+    rateLimitTable.grantReadWriteData(authorizerLambda);
 
     const manageKeysLambda = new RustFunction(this, 'manageKeys', {
       manifestPath: 'lambda/manage_keys/Cargo.toml',
@@ -316,10 +435,23 @@ export class KrtkRsStack extends cdk.Stack {
       loggingFormat: LoggingFormat.JSON,
       environment: {
         API_KEY_TABLE_NAME: apiKeyTable.tableName,
+        AUDIT_TABLE_NAME: auditTable.tableName,
       }
     });
     apiKeyTable.grantReadWriteData(manageKeysLambda);
 
+    const getAuditLambda = new RustFunction(this, 'getAudit', {
+      manifestPath: 'lambda/get_audit/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.seconds(10),
+      logGroup: getAuditLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        AUDIT_TABLE_NAME: auditTable.tableName,
+      }
+    });
+
     const createLinkLambda = new RustFunction(this, 'createLink', {
       manifestPath: 'lambda/create_link/Cargo.toml',
       runtime: 'provided.al2023',
@@ -330,6 +462,47 @@ export class KrtkRsStack extends cdk.Stack {
       environment: {
         TABLE_NAME: linkDatabase.tableName,
         SHORTENER_DOMAIN: 'krtk.rs',
+        AUDIT_TABLE_NAME: auditTable.tableName,
+      }
+    });
+    // Scrapes up to 100 pages, ten at a time, against the 2s per-page client timeout:
+    // ~20s worst case, inside API Gateway's 30s integration limit.
+    const batchCreateLinkLambda = new RustFunction(this, 'batchCreateLink', {
+      manifestPath: 'lambda/batch_create_link/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.seconds(30),
+      logGroup: batchCreateLinkLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        TABLE_NAME: linkDatabase.tableName,
+        SHORTENER_DOMAIN: 'krtk.rs',
+        AUDIT_TABLE_NAME: auditTable.tableName,
+      }
+    });
+    const updateLinkLambda = new RustFunction(this, 'updateLink', {
+      manifestPath: 'lambda/update_link/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.seconds(30),
+      logGroup: updateLinkLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        TABLE_NAME: linkDatabase.tableName,
+        SHORTENER_DOMAIN: 'krtk.rs',
+      }
+    });
+    const deleteLinkLambda = new RustFunction(this, 'deleteLink', {
+      manifestPath: 'lambda/delete_link/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.seconds(10),
+      logGroup: deleteLinkLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        TABLE_NAME: linkDatabase.tableName,
+        SHORTENER_DOMAIN: 'krtk.rs',
+        AUDIT_TABLE_NAME: auditTable.tableName,
       }
     });
     const getLinksLambda = new RustFunction(this, 'getLinks', {
@@ -341,6 +514,7 @@ export class KrtkRsStack extends cdk.Stack {
       loggingFormat: LoggingFormat.JSON,
       environment: {
         TABLE_NAME: linkDatabase.tableName,
+        CLICK_TABLE_NAME: clickTable.tableName,
         SHORTENER_DOMAIN: 'krtk.rs',
       }
     });
@@ -360,12 +534,37 @@ export class KrtkRsStack extends cdk.Stack {
     linkDatabase.grantReadData(getLinksLambda);
     linkDatabase.grantReadData(visitLinkLambda);
     linkDatabase.grantWriteData(createLinkLambda);
+    // BatchGetItem to check generated ids are free, then BatchWriteItem.
+    linkDatabase.grantReadWriteData(batchCreateLinkLambda);
+    // UpdateItem with ReturnValues is a write; the ownership check rides on its condition.
+    linkDatabase.grantWriteData(updateLinkLambda);
+    // A conditional DeleteItem needs no read: the ownership check is the condition.
+    linkDatabase.grantWriteData(deleteLinkLambda);
+
+    // Audit events are written where they happen and read back only by getAudit.
+    for (const fn of [authorizerLambda, manageKeysLambda, createLinkLambda, batchCreateLinkLambda, deleteLinkLambda]) {
+      auditTable.grantWriteData(fn);
+    }
+    auditTable.grantReadData(getAuditLambda);
 
     // Secrets permissions
+    // An edit re-runs the full creation validation, Safe Browsing included.
     props.googleApiKeySecret.grantRead(createLinkLambda);
+    props.googleApiKeySecret.grantRead(batchCreateLinkLambda);
+    props.googleApiKeySecret.grantRead(updateLinkLambda);
 
     // Append secret
     createLinkLambda.addEnvironment('GOOGLE_API_KEY_SECRET', props.googleApiKeySecret.secretArn);
+    batchCreateLinkLambda.addEnvironment('GOOGLE_API_KEY_SECRET', props.googleApiKeySecret.secretArn);
+    updateLinkLambda.addEnvironment('GOOGLE_API_KEY_SECRET', props.googleApiKeySecret.secretArn);
+
+    // Only the public redirect signs and checks unlock cookies.
+    props.linkCookieSecret.grantRead(visitLinkLambda);
+    visitLinkLambda.addEnvironment('LINK_COOKIE_SECRET', props.linkCookieSecret.secretArn);
+
+    // Only the listing mints and checks pagination cursors.
+    props.cursorSecret.grantRead(getLinksLambda);
+    getLinksLambda.addEnvironment('CURSOR_SECRET', props.cursorSecret.secretArn);
 
     const processAnalyticsLambda = new RustFunction(this, 'processAnalyticsLambda', {
       manifestPath: 'lambda/process_analytics/Cargo.toml',
@@ -376,17 +575,112 @@ export class KrtkRsStack extends cdk.Stack {
       loggingFormat: LoggingFormat.JSON,
       environment: {
         TABLE_NAME: linkDatabase.tableName,
+        CLICK_TABLE_NAME: clickTable.tableName,
         SHORTENER_DOMAIN: 'krtk.rs',
+        LOG_FIELDS: REALTIME_LOG_FIELDS.join(','),
       }
     });
     // Give Function permission to Kinesis
     cfAnalyticsStream.grantRead(processAnalyticsLambda);
     // ESM for Kinesis
     processAnalyticsLambda.addEventSource(new KinesisEventSource(cfAnalyticsStream,{
-      batchSize: 1,
+      // Large batches are what let the handler coalesce a spike on one link into a
+      // single counter write; the window trades a few seconds of dashboard lag for it.
+      batchSize: 500,
+      maxBatchingWindow: cdk.Duration.seconds(5),
       startingPosition: StartingPosition.TRIM_HORIZON,
+      // The handler reports a record whose click could not be stored, so only that record
+      // and those after it are retried. Bounded, so a write that keeps failing cannot hold
+      // the shard for the stream's whole 24h retention.
+      reportBatchItemFailures: true,
+      retryAttempts: 10,
     }));
     linkDatabase.grantWriteData(processAnalyticsLambda);
+    clickTable.grantWriteData(processAnalyticsLambda);
+    clickTable.grantReadData(getLinksLambda);
+
+    // Re-checks every destination daily, since a domain can turn malicious after the
+    // link to it passed Safe Browsing at creation. The whole table is scanned in one
+    // invocation, hence the long timeout.
+    const rescanLinksLambda = new RustFunction(this, 'rescanLinks', {
+      manifestPath: 'lambda/rescan_links/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.minutes(15),
+      logGroup: rescanLinksLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        TABLE_NAME: linkDatabase.tableName,
+        SHORTENER_DOMAIN: 'krtk.rs',
+        AUDIT_TABLE_NAME: auditTable.tableName,
+        GOOGLE_API_KEY_SECRET: props.googleApiKeySecret.secretArn,
+      }
+    });
+    // Scan to read, UpdateItem to disable.
+    linkDatabase.grantReadWriteData(rescanLinksLambda);
+    auditTable.grantWriteData(rescanLinksLambda);
+    props.googleApiKeySecret.grantRead(rescanLinksLambda);
+    new Rule(this, 'rescanLinksSchedule', {
+      schedule: Schedule.rate(cdk.Duration.days(1)),
+      targets: [new LambdaFunction(rescanLinksLambda, { retryAttempts: 0 })],
+    });
+
+    // Maintains tagTable from linkTable's stream, so no writer of links has to know
+    // the index exists.
+    const indexTagsLambda = new RustFunction(this, 'indexTags', {
+      manifestPath: 'lambda/index_tags/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.seconds(30),
+      logGroup: indexTagsLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        TAG_TABLE_NAME: tagTable.tableName,
+      }
+    });
+    indexTagsLambda.addEventSource(new DynamoEventSource(linkDatabase, {
+      batchSize: 100,
+      startingPosition: StartingPosition.TRIM_HORIZON,
+      // As for processAnalytics: a failed index write retries from its own record on,
+      // a bounded number of times.
+      reportBatchItemFailures: true,
+      retryAttempts: 10,
+    }));
+    tagTable.grantWriteData(indexTagsLambda);
+
+    // Lists an owner's tags from tagTable, and renames or deletes one by rewriting the
+    // links it is on.
+    const manageTagsLambda = new RustFunction(this, 'manageTags', {
+      manifestPath: 'lambda/manage_tags/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.seconds(30),
+      logGroup: manageTagsLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        TABLE_NAME: linkDatabase.tableName,
+        TAG_TABLE_NAME: tagTable.tableName,
+        SHORTENER_DOMAIN: 'krtk.rs',
+      }
+    });
+    tagTable.grantReadData(manageTagsLambda);
+    // GetItem for each link's current tags, then a conditional UpdateItem.
+    linkDatabase.grantReadWriteData(manageTagsLambda);
+
+    // Saved UTM presets. Never touches the link table: a preset is merged into a link by
+    // the client sending it as the link's `utm`.
+    const manageUtmPresetsLambda = new RustFunction(this, 'manageUtmPresets', {
+      manifestPath: 'lambda/manage_utm_presets/Cargo.toml',
+      runtime: 'provided.al2023',
+      architecture: Architecture.ARM_64,
+      timeout: cdk.Duration.seconds(10),
+      logGroup: manageUtmPresetsLogGroup,
+      loggingFormat: LoggingFormat.JSON,
+      environment: {
+        UTM_PRESET_TABLE_NAME: utmPresetTable.tableName,
+      }
+    });
+    utmPresetTable.grantReadWriteData(manageUtmPresetsLambda);
 
     // HTTP Api
     const api = new HttpApi(this, 'httpApi',{
@@ -400,6 +694,8 @@ export class KrtkRsStack extends cdk.Stack {
         allowMethods: [
           CorsHttpMethod.GET,
           CorsHttpMethod.POST,
+          CorsHttpMethod.PUT,
+          CorsHttpMethod.PATCH,
           CorsHttpMethod.DELETE,
           CorsHttpMethod.OPTIONS,
         ],
@@ -465,6 +761,13 @@ export class KrtkRsStack extends cdk.Stack {
       integration: createLinkInteg,
       authorizer: linksAuthorizer,
     });
+    const batchCreateLinkInteg = new HttpLambdaIntegration('batchCreateLinkInteg', batchCreateLinkLambda);
+    api.addRoutes({
+      path: '/api/links/batch',
+      methods: [HttpMethod.POST],
+      integration: batchCreateLinkInteg,
+      authorizer: linksAuthorizer,
+    });
     const getLinksInteg = new HttpLambdaIntegration('getLinksInteg', getLinksLambda);
     api.addRoutes({
       path: '/api/links',
@@ -473,6 +776,52 @@ export class KrtkRsStack extends cdk.Stack {
       authorizer: linksAuthorizer,
     });
 
+    const updateLinkInteg = new HttpLambdaIntegration('updateLinkInteg', updateLinkLambda);
+    api.addRoutes({
+      path: '/api/links/{linkId}',
+      methods: [HttpMethod.PATCH],
+      integration: updateLinkInteg,
+      authorizer: linksAuthorizer,
+    });
+    const deleteLinkInteg = new HttpLambdaIntegration('deleteLinkInteg', deleteLinkLambda);
+    api.addRoutes({
+      path: '/api/links/{linkId}',
+      methods: [HttpMethod.DELETE],
+      integration: deleteLinkInteg,
+      authorizer: linksAuthorizer,
+    });
+
+    // Tags: the same authorizer as the links they are on, so an API key with the
+    // right scopes can manage them too. `{tag+}` is greedy because a tag may hold '/'.
+    const manageTagsInteg = new HttpLambdaIntegration('manageTagsInteg', manageTagsLambda);
+    api.addRoutes({
+      path: '/api/tags',
+      methods: [HttpMethod.GET],
+      integration: manageTagsInteg,
+      authorizer: linksAuthorizer,
+    });
+    api.addRoutes({
+      path: '/api/tags/{tag+}',
+      methods: [HttpMethod.PATCH, HttpMethod.DELETE],
+      integration: manageTagsInteg,
+      authorizer: linksAuthorizer,
+    });
+
+    // UTM presets, under the links authorizer for the same reason as tags.
+    const manageUtmPresetsInteg = new HttpLambdaIntegration('manageUtmPresetsInteg', manageUtmPresetsLambda);
+    api.addRoutes({
+      path: '/api/utm-presets',
+      methods: [HttpMethod.GET],
+      integration: manageUtmPresetsInteg,
+      authorizer: linksAuthorizer,
+    });
+    api.addRoutes({
+      path: '/api/utm-presets/{name}',
+      methods: [HttpMethod.PUT, HttpMethod.DELETE],
+      integration: manageUtmPresetsInteg,
+      authorizer: linksAuthorizer,
+    });
+
     // Key management. JWT-only by construction (see above).
     const manageKeysInteg = new HttpLambdaIntegration('manageKeysInteg', manageKeysLambda);
     api.addRoutes({
@@ -487,13 +836,30 @@ export class KrtkRsStack extends cdk.Stack {
       integration: manageKeysInteg,
       authorizer: keysAuthorizer,
     });
+    api.addRoutes({
+      path: '/api/keys/{keyId}/rotate',
+      methods: [HttpMethod.POST],
+      integration: manageKeysInteg,
+      authorizer: keysAuthorizer,
+    });
+
+    // The owner's audit log. JWT-only like key management: it records what was done
+    // with API keys, so a key must not be able to read it.
+    const getAuditInteg = new HttpLambdaIntegration('getAuditInteg', getAuditLambda);
+    api.addRoutes({
+      path: '/api/audit',
+      methods: [HttpMethod.GET],
+      integration: getAuditInteg,
+      authorizer: keysAuthorizer,
+    });
 
     // Public redirect path -- deliberately NO authorizer. Ownership controls management,
     // not resolution: anyone holding a short URL can follow it (FR-2.3, FR-3.5).
     const visitLinkInteg = new HttpLambdaIntegration('visitLinkInteg', visitLinkLambda);
+    // POST is the password prompt's form submission for protected links.
     api.addRoutes({
       path: '/{linkId}',
-      methods: [HttpMethod.GET],
+      methods: [HttpMethod.GET, HttpMethod.POST],
       integration: visitLinkInteg
     });
 
@@ -597,6 +963,9 @@ export class KrtkRsStack extends cdk.Stack {
           }),
           viewerProtocolPolicy: ViewerProtocolPolicy.REDIRECT_TO_HTTPS,
           allowedMethods: AllowedMethods.ALLOW_ALL,
+          // Never cached: with redirect rules, the same link answers different visitors
+          // differently. The policy forwards User-Agent and CloudFront-Viewer-Country,
+          // which the rules are evaluated on.
           cachePolicy: CachePolicy.CACHING_DISABLED,
           originRequestPolicy: OriginRequestPolicy.ALL_VIEWER_EXCEPT_HOST_HEADER,
           realtimeLogConfig: realTimeConfig,
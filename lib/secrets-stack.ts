@@ -3,6 +3,8 @@ import { Secret } from 'aws-cdk-lib/aws-secretsmanager';
 
 export class SecretsStack extends cdk.Stack {
   public readonly googleApiSecret: Secret;
+  public readonly linkCookieSecret: Secret;
+  public readonly cursorSecret: Secret;
   constructor(scope: cdk.App, id: string, props?: cdk.StackProps) {
     super(scope, id, props);
 
@@ -11,6 +13,26 @@ export class SecretsStack extends cdk.Stack {
       description: 'Google API Key',
     });
 
+    // Signs the cookie that remembers a correct link password. Generated, never pushed
+    // by hand: nothing outside visit_link ever needs to know its value.
+    this.linkCookieSecret = new Secret(this, 'linkCookieSecret', {
+      description: 'Signing key for password-protected link cookies',
+      generateSecretString: {
+        passwordLength: 64,
+        excludePunctuation: true,
+      },
+    });
+
+    // Signs the pagination cursors getLinks hands out, so a client cannot forge one that
+    // starts a listing anywhere it likes. Generated for the same reason as the above.
+    this.cursorSecret = new Secret(this, 'cursorSecret', {
+      description: 'Signing key for link list pagination cursors',
+      generateSecretString: {
+        passwordLength: 64,
+        excludePunctuation: true,
+      },
+    });
+
     new cdk.CfnOutput(this, 'googleApiSecretArn',{
       value: this.googleApiSecret.secretArn,
       exportName: 'googleApiSecretArn'
@@ -0,0 +1,536 @@
+//! Security-relevant events, kept per owner so they can see who did what to their
+//! account and from where.
+//!
+//! Recorded: API keys minted, rotated and revoked; links created and deleted, or
+//! disabled by a rescan; and credentials the authorizer turned away. A rejected
+//! credential often has no owner -- an unknown API key or a JWT that did not verify
+//! says nothing trustworthy about whose it claims to be -- so those land in a shared
+//! [`UNATTRIBUTED`] partition that only an operator reads. An expired key still names
+//! its owner and is filed under them.
+//!
+//! Items are keyed `Owner` (`OWNER#<sub>` or [`UNATTRIBUTED`]) with `EventId` as the sort
+//! key: the time in zero-padded milliseconds plus a random suffix, so events sort in the
+//! order they happened and two in the same millisecond do not overwrite each other. The
+//! id doubles as the page cursor, so it sticks to characters that need no escaping in a
+//! query string.
+//! Items carry `PurgeAt` for the table's TTL.
+//!
+//! Recording is best-effort. An audit write that fails is logged and the action it
+//! describes still goes ahead: refusing to revoke a leaked key because the log of the
+//! revocation could not be written would be the worse outcome.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::{AttributeValue, PutRequest, WriteRequest};
+use aws_sdk_dynamodb::Client;
+use lambda_http::request::RequestContext;
+use lambda_http::{tracing, Request, RequestExt};
+use rand::rngs::OsRng;
+use rand::TryRngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::auth_method;
+use crate::error::AppError;
+
+/// Partition for events with no owner to file them under.
+pub const UNATTRIBUTED: &str = "UNATTRIBUTED";
+/// How long events are kept.
+const RETENTION_SECS: i64 = 90 * 24 * 60 * 60;
+/// Events per page of `GET /api/audit`.
+pub const PAGE_SIZE: i32 = 25;
+// `BatchWriteItem` takes at most 25 puts per call.
+const BATCH_WRITE_LIMIT: usize = 25;
+
+/// What happened. The wire form (`key.minted`, ...) is what is stored and returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    KeyMinted,
+    KeyRotated,
+    KeyRevoked,
+    /// An API key that does not exist, or no longer works, was presented.
+    ApiKeyRejected,
+    /// A bearer token failed verification.
+    JwtRejected,
+    LinkCreated,
+    LinkDeleted,
+    /// A rescan found the link's destination flagged and stopped it redirecting.
+    LinkDisabled,
+}
+
+impl AuditAction {
+    const ALL: [AuditAction; 8] = [
+        Self::KeyMinted,
+        Self::KeyRotated,
+        Self::KeyRevoked,
+        Self::ApiKeyRejected,
+        Self::JwtRejected,
+        Self::LinkCreated,
+        Self::LinkDeleted,
+        Self::LinkDisabled,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::KeyMinted => "key.minted",
+            Self::KeyRotated => "key.rotated",
+            Self::KeyRevoked => "key.revoked",
+            Self::ApiKeyRejected => "auth.api_key_rejected",
+            Self::JwtRejected => "auth.jwt_rejected",
+            Self::LinkCreated => "link.created",
+            Self::LinkDeleted => "link.deleted",
+            Self::LinkDisabled => "link.disabled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.as_str() == value)
+    }
+
+    /// How the dashboard names it.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::KeyMinted => "API key created",
+            Self::KeyRotated => "API key rotated",
+            Self::KeyRevoked => "API key revoked",
+            Self::ApiKeyRejected => "API key rejected",
+            Self::JwtRejected => "Sign-in token rejected",
+            Self::LinkCreated => "Link created",
+            Self::LinkDeleted => "Link deleted",
+            Self::LinkDisabled => "Link disabled as unsafe",
+        }
+    }
+}
+
+/// Where a request came from.
+///
+/// API Gateway's `sourceIp` is the connection it accepted, which for requests through
+/// the site is a CloudFront edge rather than the person. CloudFront appends the viewer's
+/// address to `X-Forwarded-For`, so its last hop is kept too -- but the execute-api
+/// endpoint is still reachable, and a caller going to it directly writes that header
+/// themselves. Only `sourceIp` is evidence; the forwarded hop is a hint.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientAddress {
+    pub source_ip: Option<String>,
+    pub forwarded_for: Option<String>,
+}
+
+impl ClientAddress {
+    /// From the two raw values, as both `lambda_http` handlers and the authorizer see them.
+    pub fn new(source_ip: Option<&str>, x_forwarded_for: Option<&str>) -> Self {
+        let non_empty = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+        Self {
+            source_ip: source_ip.and_then(non_empty),
+            forwarded_for: x_forwarded_for
+                .and_then(|header| header.rsplit(',').next())
+                .and_then(non_empty),
+        }
+    }
+
+    pub fn of_request(event: &Request) -> Self {
+        let source_ip = match event.request_context_ref() {
+            Some(RequestContext::ApiGatewayV2(ctx)) => ctx.http.source_ip.clone(),
+            _ => None,
+        };
+        let forwarded_for = event
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok());
+        Self::new(source_ip.as_deref(), forwarded_for)
+    }
+}
+
+/// One event, as recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub action: AuditAction,
+    /// `None` files the event under [`UNATTRIBUTED`].
+    pub owner_id: Option<String>,
+    /// `jwt` or `apikey`, when the actor authenticated.
+    pub auth_method: Option<String>,
+    /// What was acted on: a link id, or a key's display prefix (never its hash).
+    pub target: Option<String>,
+    pub address: ClientAddress,
+    pub detail: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, owner_id: Option<&str>, address: ClientAddress) -> Self {
+        Self {
+            action,
+            owner_id: owner_id.map(str::to_string),
+            auth_method: None,
+            target: None,
+            address,
+            detail: None,
+        }
+    }
+
+    /// An action an authenticated owner took through the API.
+    pub fn from_request(action: AuditAction, owner_id: &str, event: &Request) -> Self {
+        Self {
+            auth_method: auth_method(event),
+            ..Self::new(action, Some(owner_id), ClientAddress::of_request(event))
+        }
+    }
+
+    pub fn with_auth_method(mut self, auth_method: &str) -> Self {
+        self.auth_method = Some(auth_method.to_string());
+        self
+    }
+
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    fn partition(&self) -> String {
+        match &self.owner_id {
+            Some(owner) => owner_partition(owner),
+            None => UNATTRIBUTED.to_string(),
+        }
+    }
+
+    /// The item for this event, recorded at `at_ms` with `suffix` to keep its id unique.
+    fn item(&self, at_ms: i64, suffix: u32) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::from([
+            ("Owner".to_string(), AttributeValue::S(self.partition())),
+            ("EventId".to_string(), AttributeValue::S(event_id(at_ms, suffix))),
+            ("Action".to_string(), AttributeValue::S(self.action.as_str().to_string())),
+            ("At".to_string(), AttributeValue::N((at_ms / 1_000).to_string())),
+            (
+                "PurgeAt".to_string(),
+                AttributeValue::N((at_ms / 1_000 + RETENTION_SECS).to_string()),
+            ),
+        ]);
+        let optional = [
+            ("AuthMethod", &self.auth_method),
+            ("Target", &self.target),
+            ("SourceIp", &self.address.source_ip),
+            ("ForwardedFor", &self.address.forwarded_for),
+            ("Detail", &self.detail),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                item.insert(name.to_string(), AttributeValue::S(value.clone()));
+            }
+        }
+        item
+    }
+}
+
+fn owner_partition(owner_id: &str) -> String {
+    format!("OWNER#{owner_id}")
+}
+
+fn event_id(at_ms: i64, suffix: u32) -> String {
+    format!("{at_ms:013}-{suffix:08x}")
+}
+
+/// Whether `cursor` is an `EventId` this module could have handed out. Anything else is
+/// refused rather than passed to DynamoDB as a start key.
+fn is_event_id(cursor: &str) -> bool {
+    match cursor.split_once('-') {
+        Some((ms, suffix)) => {
+            ms.len() == 13
+                && ms.bytes().all(|b| b.is_ascii_digit())
+                && suffix.len() == 8
+                && suffix.bytes().all(|b| b.is_ascii_hexdigit())
+        }
+        None => false,
+    }
+}
+
+fn random_suffix() -> u32 {
+    let mut buf = [0u8; 4];
+    OsRng.try_fill_bytes(&mut buf).expect("OS RNG failed");
+    u32::from_le_bytes(buf)
+}
+
+/// One event as the owner sees it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Also the cursor that pages past this event.
+    pub event_id: String,
+    pub action: String,
+    pub at: i64,
+    pub auth_method: Option<String>,
+    pub target: Option<String>,
+    pub source_ip: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub detail: Option<String>,
+}
+
+impl AuditEntry {
+    /// The dashboard's name for the action, or its wire name if this build does not
+    /// know it.
+    pub fn label(&self) -> &str {
+        match AuditAction::parse(&self.action) {
+            Some(action) => action.label(),
+            None => &self.action,
+        }
+    }
+
+    /// The address to show: the connection API Gateway accepted, the one value a
+    /// caller cannot choose.
+    pub fn address(&self) -> Option<&str> {
+        self.source_ip.as_deref()
+    }
+
+    /// The address `X-Forwarded-For` claims, when it says something the connection
+    /// does not. Unverified: see [`ClientAddress`].
+    pub fn claimed_address(&self) -> Option<&str> {
+        self.forwarded_for.as_deref().filter(|forwarded| Some(*forwarded) != self.address())
+    }
+}
+
+// DynamoDB-side shape; `AuditEntry` keeps the snake_case JSON names.
+#[derive(Deserialize)]
+struct AuditRow {
+    #[serde(rename = "EventId")]
+    event_id: String,
+    #[serde(rename = "Action")]
+    action: String,
+    #[serde(rename = "At")]
+    at: i64,
+    #[serde(rename = "AuthMethod")]
+    auth_method: Option<String>,
+    #[serde(rename = "Target")]
+    target: Option<String>,
+    #[serde(rename = "SourceIp")]
+    source_ip: Option<String>,
+    #[serde(rename = "ForwardedFor")]
+    forwarded_for: Option<String>,
+    #[serde(rename = "Detail")]
+    detail: Option<String>,
+}
+
+impl From<AuditRow> for AuditEntry {
+    fn from(row: AuditRow) -> Self {
+        Self {
+            event_id: row.event_id,
+            action: row.action,
+            at: row.at,
+            auth_method: row.auth_method,
+            target: row.target,
+            source_ip: row.source_ip,
+            forwarded_for: row.forwarded_for,
+            detail: row.detail,
+        }
+    }
+}
+
+/// A page of events, newest first.
+#[derive(Debug, Serialize)]
+pub struct AuditPage {
+    pub events: Vec<AuditEntry>,
+    /// Pass back as `cursor` for the next page; absent on the last one.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    dynamodb_audit_table: String,
+    dynamodb_client: Client,
+}
+
+impl AuditLog {
+    pub fn new(dynamodb_audit_table: &str, dynamodb_client: Client) -> Self {
+        Self {
+            dynamodb_audit_table: dynamodb_audit_table.to_string(),
+            dynamodb_client,
+        }
+    }
+
+    /// Records `event`, logging rather than returning a failure.
+    pub async fn record(&self, event: AuditEvent) {
+        let at_ms = chrono::Utc::now().timestamp_millis();
+        let result = self
+            .dynamodb_client
+            .put_item()
+            .table_name(&self.dynamodb_audit_table)
+            .set_item(Some(event.item(at_ms, random_suffix())))
+            .send()
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Could not record audit event {}: {:?}", event.action.as_str(), e);
+        }
+    }
+
+    /// Records several events, 25 to a write. Unprocessed items are logged, not retried:
+    /// like [`record`](Self::record), this must not hold up the action being recorded.
+    pub async fn record_all(&self, events: Vec<AuditEvent>) {
+        let at_ms = chrono::Utc::now().timestamp_millis();
+        for chunk in events.chunks(BATCH_WRITE_LIMIT) {
+            let writes: Result<Vec<WriteRequest>, _> = chunk
+                .iter()
+                .map(|event| {
+                    PutRequest::builder()
+                        .set_item(Some(event.item(at_ms, random_suffix())))
+                        .build()
+                        .map(|put| WriteRequest::builder().put_request(put).build())
+                })
+                .collect();
+            let writes = match writes {
+                Ok(writes) => writes,
+                Err(e) => {
+                    tracing::error!("Could not build audit events: {:?}", e);
+                    continue;
+                }
+            };
+
+            let result = self
+                .dynamodb_client
+                .batch_write_item()
+                .request_items(&self.dynamodb_audit_table, writes)
+                .send()
+                .await;
+
+            match result {
+                Ok(output) => {
+                    let unprocessed = output
+                        .unprocessed_items()
+                        .and_then(|items| items.get(&self.dynamodb_audit_table))
+                        .map_or(0, Vec::len);
+                    if unprocessed > 0 {
+                        tracing::error!("{unprocessed} audit events were not recorded");
+                    }
+                }
+                Err(e) => tracing::error!("Could not record {} audit events: {:?}", chunk.len(), e),
+            }
+        }
+    }
+
+    /// A page of `owner_id`'s events, newest first, starting after `cursor`.
+    ///
+    /// Scoped by the query's partition key, so a cursor copied from another owner's
+    /// page cannot reach their events: it only says where to start in this one.
+    pub async fn list(&self, owner_id: &str, cursor: Option<&str>) -> Result<AuditPage, AppError> {
+        let partition = owner_partition(owner_id);
+        let mut query = self
+            .dynamodb_client
+            .query()
+            .table_name(&self.dynamodb_audit_table)
+            .key_condition_expression("#owner = :owner")
+            .expression_attribute_names("#owner", "Owner")
+            .expression_attribute_values(":owner", AttributeValue::S(partition.clone()))
+            .scan_index_forward(false)
+            .limit(PAGE_SIZE);
+
+        if let Some(cursor) = cursor {
+            if !is_event_id(cursor) {
+                return Err(AppError::Validation("Invalid cursor".to_string()));
+            }
+            query = query
+                .exclusive_start_key("Owner", AttributeValue::S(partition))
+                .exclusive_start_key("EventId", AttributeValue::S(cursor.to_string()));
+        }
+
+        let result = query.send().await.map_err(|e| {
+            tracing::error!("Error reading audit events: {:?}", e);
+            AppError::database(e)
+        })?;
+
+        let rows: Vec<AuditRow> = serde_dynamo::from_items(result.items().to_vec())?;
+        let next_cursor = result
+            .last_evaluated_key()
+            .and_then(|key| key.get("EventId"))
+            .and_then(|id| id.as_s().ok())
+            .cloned();
+
+        Ok(AuditPage {
+            events: rows.into_iter().map(AuditEntry::from).collect(),
+            next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AT_MS: i64 = 1_739_035_776_123;
+
+    #[test]
+    fn actions_round_trip_through_their_wire_names() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::parse(action.as_str()), Some(action));
+        }
+        assert_eq!(AuditAction::parse("key.exploded"), None);
+    }
+
+    #[test]
+    fn event_ids_sort_in_time_order() {
+        let earlier = event_id(999_999_999_999, u32::MAX);
+        let later = event_id(AT_MS, 0);
+        assert!(earlier < later, "{earlier} should sort before {later}");
+        assert!(is_event_id(&later));
+    }
+
+    #[test]
+    fn only_event_ids_are_accepted_as_cursors() {
+        assert!(is_event_id("1739035776123-00c0ffee"));
+        for bad in ["", "1739035776123", "1739035776123-xyz", "OWNER#abc", "173903577612-00c0ffee"] {
+            assert!(!is_event_id(bad), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn an_owned_event_is_filed_under_its_owner() {
+        let event = AuditEvent::new(
+            AuditAction::LinkDeleted,
+            Some("user-1"),
+            ClientAddress::new(Some("130.176.0.1"), Some("203.0.113.7")),
+        )
+        .with_auth_method("apikey")
+        .with_target("abc1234");
+        let item = event.item(AT_MS, 0xc0ffee);
+
+        assert_eq!(item["Owner"].as_s().unwrap(), "OWNER#user-1");
+        assert_eq!(item["EventId"].as_s().unwrap(), "1739035776123-00c0ffee");
+        assert_eq!(item["Action"].as_s().unwrap(), "link.deleted");
+        assert_eq!(item["At"].as_n().unwrap(), "1739035776");
+        assert_eq!(item["Target"].as_s().unwrap(), "abc1234");
+        assert_eq!(item["SourceIp"].as_s().unwrap(), "130.176.0.1");
+        assert_eq!(item["ForwardedFor"].as_s().unwrap(), "203.0.113.7");
+        assert!(!item.contains_key("Detail"), "absent fields are left off the item");
+    }
+
+    #[test]
+    fn an_event_without_an_owner_is_unattributed() {
+        let item = AuditEvent::new(AuditAction::JwtRejected, None, ClientAddress::default()).item(AT_MS, 1);
+        assert_eq!(item["Owner"].as_s().unwrap(), UNATTRIBUTED);
+        assert!(!item.contains_key("SourceIp"));
+    }
+
+    #[test]
+    fn the_forwarded_address_is_kept_beside_the_connection() {
+        // CloudFront appends; whatever the caller wrote comes first.
+        let address = ClientAddress::new(Some("130.176.0.1"), Some("10.0.0.1, 203.0.113.7"));
+        assert_eq!(address.source_ip.as_deref(), Some("130.176.0.1"));
+        assert_eq!(address.forwarded_for.as_deref(), Some("203.0.113.7"));
+        assert_eq!(ClientAddress::new(None, Some(" ")), ClientAddress::default());
+    }
+
+    #[test]
+    fn entries_read_back_with_their_label_and_address() {
+        // A direct call to execute-api can forge the header, so it never displaces the
+        // connection address.
+        let address = ClientAddress::new(Some("198.51.100.2"), Some("203.0.113.7"));
+        let item = AuditEvent::new(AuditAction::KeyMinted, Some("user-1"), address)
+            .with_target("krtk_3f9aQ2x")
+            .item(AT_MS, 7);
+        let entry: AuditEntry = serde_dynamo::from_item::<_, AuditRow>(item).unwrap().into();
+
+        assert_eq!(entry.label(), "API key created");
+        assert_eq!(entry.address(), Some("198.51.100.2"));
+        assert_eq!(entry.claimed_address(), Some("203.0.113.7"));
+        assert_eq!(entry.target.as_deref(), Some("krtk_3f9aQ2x"));
+        assert_eq!(entry.at, AT_MS / 1_000);
+    }
+}
@@ -23,6 +23,13 @@
 //!
 //! Handlers must call this rather than reaching into the request context.
 //!
+//! # Scopes
+//!
+//! An API key carries the [`Scope`]s it was minted with, and the custom authorizer
+//! passes them on as `scopes`. A handler states the scope it needs with
+//! [`require_scope`], right after resolving the owner. A browser session (a Cognito
+//! JWT) is the user themselves and holds every scope.
+//!
 //! Note on the field name: in `aws_lambda_events` the custom-authorizer context
 //! map is the Rust field `fields`, serde-renamed from the wire name `lambda`.
 
@@ -32,6 +39,57 @@ use lambda_http::{tracing, Request, RequestExt};
 
 use crate::error::AppError;
 
+/// Requests per minute an API key may make when it was minted without a limit of its
+/// own, including every key minted before limits existed.
+pub const DEFAULT_RATE_LIMIT_PER_MINUTE: u32 = 60;
+/// The stage throttle is 5 requests a second; a key allowed more than all of it would
+/// not be limited at all.
+pub const MAX_RATE_LIMIT_PER_MINUTE: u32 = 300;
+
+/// What an API key may do. The wire form (`links:create`, ...) is what is stored on the
+/// key item, passed through the authorizer context and accepted when minting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Create links, singly or in a batch, and change where an existing one points.
+    LinksCreate,
+    /// List links.
+    LinksRead,
+    LinksDelete,
+    /// Per-link click history. Without it a listing still works, minus the series.
+    AnalyticsRead,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::LinksCreate,
+        Scope::LinksRead,
+        Scope::LinksDelete,
+        Scope::AnalyticsRead,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LinksCreate => "links:create",
+            Self::LinksRead => "links:read",
+            Self::LinksDelete => "links:delete",
+            Self::AnalyticsRead => "analytics:read",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == value)
+            .ok_or_else(|| AppError::Validation(format!("Unknown scope '{value}'")))
+    }
+
+    /// Space-separated, as in OAuth. The authorizer context only carries flat values,
+    /// so the list travels as one string.
+    pub fn join(scopes: &[Scope]) -> String {
+        scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(" ")
+    }
+}
+
 /// Extracts the authenticated owner's Cognito `sub` from a request.
 ///
 /// Tries the custom Lambda authorizer's context first, then the native JWT
@@ -67,6 +125,53 @@ pub fn owner_from_request(event: &Request) -> Result<String, AppError> {
     Err(AppError::Unauthorized)
 }
 
+/// Fails with [`AppError::Forbidden`] unless the caller holds `scope`.
+///
+/// Call it after [`owner_from_request`], so a request with no identity at all still
+/// gets its 401 rather than a 403.
+pub fn require_scope(event: &Request, scope: Scope) -> Result<(), AppError> {
+    if has_scope(event, scope) {
+        Ok(())
+    } else {
+        tracing::warn!("rejecting request without the {} scope", scope.as_str());
+        Err(AppError::Forbidden)
+    }
+}
+
+/// Whether the caller holds `scope`, for handlers where a missing scope narrows the
+/// answer rather than refusing it.
+pub fn has_scope(event: &Request, scope: Scope) -> bool {
+    let authorizer = match event.request_context_ref() {
+        Some(RequestContext::ApiGatewayV2(ctx)) => ctx.authorizer.as_ref(),
+        _ => None,
+    };
+    let Some(auth) = authorizer else {
+        return false;
+    };
+
+    // The custom authorizer always sets `scopes`. A context without it grants nothing:
+    // an older or misconfigured authorizer must not widen a key to everything.
+    if let Some(scopes) = auth.fields.get("scopes") {
+        return scopes
+            .as_str()
+            .is_some_and(|scopes| scopes.split_whitespace().any(|s| s == scope.as_str()));
+    }
+    // The native user pool authorizer only ever admits a signed-in user.
+    owner_from_lambda_context(auth).is_none() && owner_from_jwt_claims(auth).is_some()
+}
+
+/// How the caller authenticated: `jwt` or `apikey`, for the audit log.
+pub fn auth_method(event: &Request) -> Option<String> {
+    let auth = match event.request_context_ref() {
+        Some(RequestContext::ApiGatewayV2(ctx)) => ctx.authorizer.as_ref()?,
+        _ => return None,
+    };
+    if owner_from_lambda_context(auth).is_some() {
+        return auth.fields.get("authMethod").and_then(|v| v.as_str()).map(str::to_string);
+    }
+    owner_from_jwt_claims(auth).map(|_| "jwt".to_string())
+}
+
 /// Custom Lambda authorizer (`/api/links`): reads the `ownerId` we set ourselves.
 fn owner_from_lambda_context(auth: &ApiGatewayRequestAuthorizer) -> Option<String> {
     auth.fields
@@ -113,6 +218,13 @@ mod tests {
         auth
     }
 
+    fn api_key_authorizer(owner: &str, scopes: &str) -> ApiGatewayRequestAuthorizer {
+        let mut auth = lambda_authorizer(owner);
+        auth.fields
+            .insert("scopes".to_string(), serde_json::json!(scopes));
+        auth
+    }
+
     /// The shape the NATIVE user pool authorizer produces.
     fn jwt_authorizer(sub: &str) -> ApiGatewayRequestAuthorizer {
         let mut jwt = ApiGatewayRequestAuthorizerJwtDescription::default();
@@ -165,4 +277,66 @@ mod tests {
             Err(AppError::Unauthorized)
         ));
     }
+
+    #[test]
+    fn scopes_round_trip_through_their_wire_names() {
+        for scope in Scope::ALL {
+            assert_eq!(Scope::parse(scope.as_str()).unwrap(), scope);
+        }
+        assert!(matches!(Scope::parse("links:*"), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn a_key_holds_only_the_scopes_it_was_given() {
+        let req = request_with_context(Some(api_key_authorizer(
+            "owner",
+            &Scope::join(&[Scope::LinksRead, Scope::AnalyticsRead]),
+        )));
+        assert!(require_scope(&req, Scope::LinksRead).is_ok());
+        assert!(has_scope(&req, Scope::AnalyticsRead));
+        assert!(matches!(
+            require_scope(&req, Scope::LinksCreate),
+            Err(AppError::Forbidden)
+        ));
+        assert!(!has_scope(&req, Scope::LinksDelete));
+    }
+
+    /// A scope name must match whole, not as a prefix of a longer one.
+    #[test]
+    fn scopes_match_exactly() {
+        let req = request_with_context(Some(api_key_authorizer("owner", "links:created")));
+        assert!(!has_scope(&req, Scope::LinksCreate));
+    }
+
+    /// An authorizer context that identifies an owner but says nothing about scopes
+    /// must not be read as "all of them".
+    #[test]
+    fn a_lambda_context_without_scopes_grants_nothing() {
+        let req = request_with_context(Some(lambda_authorizer("owner")));
+        for scope in Scope::ALL {
+            assert!(!has_scope(&req, scope));
+        }
+    }
+
+    #[test]
+    fn a_native_jwt_session_holds_every_scope() {
+        let req = request_with_context(Some(jwt_authorizer("sub")));
+        for scope in Scope::ALL {
+            assert!(has_scope(&req, scope));
+        }
+    }
+
+    #[test]
+    fn no_authorizer_context_holds_no_scope() {
+        assert!(!has_scope(&Request::default(), Scope::LinksRead));
+    }
+
+    #[test]
+    fn auth_method_comes_from_whichever_authorizer_admitted_the_request() {
+        let mut key = api_key_authorizer("sub", "links:read");
+        key.fields.insert("authMethod".to_string(), serde_json::json!("apikey"));
+        assert_eq!(auth_method(&request_with_context(Some(key))).as_deref(), Some("apikey"));
+        assert_eq!(auth_method(&request_with_context(Some(jwt_authorizer("sub")))).as_deref(), Some("jwt"));
+        assert_eq!(auth_method(&request_with_context(None)), None);
+    }
 }
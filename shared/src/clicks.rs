@@ -0,0 +1,300 @@
+//! Per-link click history, kept as hourly and daily buckets in their own table.
+//!
+//! `Clicks` on the link item stays the all-time total that expiry and the table read.
+//! The buckets answer "when": every click adds one to the hour and to the day it landed
+//! in, and `get_links` reads a window of them back as a zero-filled series.
+//!
+//! Items are keyed `Series` = `H#<linkId>` or `D#<linkId>` with `BucketStart` (the unix
+//! time the bucket begins) as the sort key, so a window is a single `Query` with a
+//! `BETWEEN` on the sort key. Buckets carry `PurgeAt` for the table's TTL: hourly ones
+//! only have to outlive the shortest range that uses them, daily ones the longest.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use lambda_http::tracing;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+const HOUR_SECS: i64 = 60 * 60;
+const DAY_SECS: i64 = 24 * HOUR_SECS;
+// A little past the longest window each granularity serves, so a bucket at the very
+// start of the window has not been purged while it is still being drawn.
+const HOURLY_RETENTION_SECS: i64 = 8 * DAY_SECS;
+const DAILY_RETENTION_SECS: i64 = 92 * DAY_SECS;
+
+/// The windows the dashboard offers. Anything else is refused rather than clamped, so a
+/// typo in a script does not silently return a different range than it asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryRange {
+    Week,
+    Month,
+    Quarter,
+}
+
+impl HistoryRange {
+    pub fn from_days(days: &str) -> Result<Self, AppError> {
+        match days {
+            "7" => Ok(Self::Week),
+            "30" => Ok(Self::Month),
+            "90" => Ok(Self::Quarter),
+            _ => Err(AppError::Validation("days must be one of 7, 30 or 90".to_string())),
+        }
+    }
+
+    pub fn days(self) -> u32 {
+        match self {
+            Self::Week => 7,
+            Self::Month => 30,
+            Self::Quarter => 90,
+        }
+    }
+
+    // A week is drawn by the hour (168 points); longer ranges by the day, where hourly
+    // points would be too dense to read in a sparkline.
+    fn granularity(self) -> Granularity {
+        match self {
+            Self::Week => Granularity::Hourly,
+            Self::Month | Self::Quarter => Granularity::Daily,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Granularity {
+    Hourly,
+    Daily,
+}
+
+impl Granularity {
+    fn secs(self) -> i64 {
+        match self {
+            Self::Hourly => HOUR_SECS,
+            Self::Daily => DAY_SECS,
+        }
+    }
+
+    fn retention_secs(self) -> i64 {
+        match self {
+            Self::Hourly => HOURLY_RETENTION_SECS,
+            Self::Daily => DAILY_RETENTION_SECS,
+        }
+    }
+
+    fn series_key(self, link_id: &str) -> String {
+        match self {
+            Self::Hourly => format!("H#{link_id}"),
+            Self::Daily => format!("D#{link_id}"),
+        }
+    }
+
+    /// Start of the bucket `ts` falls in. Buckets are aligned to UTC.
+    fn bucket_start(self, ts: i64) -> i64 {
+        ts - ts.rem_euclid(self.secs())
+    }
+}
+
+/// Start of the hour `ts` falls in.
+///
+/// Clicks in the same hour land in the same hourly *and* daily bucket, so a caller can
+/// tally clicks by this and make one [`ClickHistory::record_clicks`] call per hour.
+pub fn hour_bucket(ts: i64) -> i64 {
+    Granularity::Hourly.bucket_start(ts)
+}
+
+/// A window of click counts, oldest bucket first.
+///
+/// Sent as a start and a bucket width plus bare counts rather than as (time, count)
+/// pairs: the buckets are contiguous, so the timestamps would be pure repetition.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClickSeries {
+    pub days: u32,
+    pub start: i64,
+    pub bucket_secs: i64,
+    pub counts: Vec<u32>,
+}
+
+impl ClickSeries {
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|&c| u64::from(c)).sum()
+    }
+}
+
+/// Lays stored buckets onto the window ending with the bucket that contains `now`.
+/// Buckets with no item had no clicks; buckets outside the window are ignored.
+fn fill_series(range: HistoryRange, now: i64, buckets: &[(i64, u32)]) -> ClickSeries {
+    let granularity = range.granularity();
+    let bucket_secs = granularity.secs();
+    let len = (i64::from(range.days()) * DAY_SECS / bucket_secs) as usize;
+    let start = granularity.bucket_start(now) - (len as i64 - 1) * bucket_secs;
+
+    let mut counts = vec![0; len];
+    for &(bucket, clicks) in buckets {
+        let offset = bucket - start;
+        if offset >= 0
+            && offset % bucket_secs == 0
+            && let Some(slot) = counts.get_mut((offset / bucket_secs) as usize)
+        {
+            *slot += clicks;
+        }
+    }
+
+    ClickSeries { days: range.days(), start, bucket_secs, counts }
+}
+
+#[derive(Debug)]
+pub struct ClickHistory {
+    dynamodb_clicks_table: String,
+    dynamodb_client: Client,
+}
+
+impl ClickHistory {
+    pub fn new(dynamodb_clicks_table: &str, dynamodb_client: Client) -> Self {
+        Self {
+            dynamodb_clicks_table: dynamodb_clicks_table.to_string(),
+            dynamodb_client,
+        }
+    }
+
+    /// Adds `count` clicks at `at` to the link's hourly and daily buckets.
+    ///
+    /// `ADD` creates the bucket on its first click, so there is nothing to initialise and
+    /// no read before the write. Both buckets are attempted even if one fails, and the
+    /// first error is returned.
+    pub async fn record_clicks(&self, link_id: &str, at: i64, count: u32) -> Result<(), AppError> {
+        let mut outcome = Ok(());
+        for granularity in [Granularity::Hourly, Granularity::Daily] {
+            let bucket = granularity.bucket_start(at);
+            let result = self
+                .dynamodb_client
+                .update_item()
+                .table_name(&self.dynamodb_clicks_table)
+                .key("Series", AttributeValue::S(granularity.series_key(link_id)))
+                .key("BucketStart", AttributeValue::N(bucket.to_string()))
+                .update_expression("ADD Clicks :n SET PurgeAt = :purge")
+                .expression_attribute_values(":n", AttributeValue::N(count.to_string()))
+                .expression_attribute_values(
+                    ":purge",
+                    AttributeValue::N((bucket + granularity.retention_secs()).to_string()),
+                )
+                .send()
+                .await;
+
+            if let Err(e) = result {
+                tracing::error!("Error recording click bucket for {link_id}: {:?}", e);
+                if outcome.is_ok() {
+                    outcome = Err(AppError::database(e));
+                }
+            }
+        }
+        outcome
+    }
+
+    /// The link's clicks over `range`, ending with the bucket that contains `now`.
+    ///
+    /// At most 168 small items, well inside one `Query` page, so there is no pagination.
+    pub async fn series(&self, link_id: &str, range: HistoryRange, now: i64) -> Result<ClickSeries, AppError> {
+        let window = fill_series(range, now, &[]);
+        let end = window.start + window.bucket_secs * window.counts.len() as i64;
+
+        let result = self
+            .dynamodb_client
+            .query()
+            .table_name(&self.dynamodb_clicks_table)
+            .key_condition_expression("Series = :series AND BucketStart BETWEEN :start AND :end")
+            .expression_attribute_values(
+                ":series",
+                AttributeValue::S(range.granularity().series_key(link_id)),
+            )
+            .expression_attribute_values(":start", AttributeValue::N(window.start.to_string()))
+            .expression_attribute_values(":end", AttributeValue::N(end.to_string()))
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error reading click history for {link_id}: {:?}", e);
+                AppError::database(e)
+            })?;
+
+        let buckets: Vec<(i64, u32)> = result
+            .items
+            .unwrap_or_default()
+            .iter()
+            .filter_map(bucket_of)
+            .collect();
+
+        Ok(fill_series(range, now, &buckets))
+    }
+}
+
+fn bucket_of(item: &HashMap<String, AttributeValue>) -> Option<(i64, u32)> {
+    let start = item.get("BucketStart")?.as_n().ok()?.parse().ok()?;
+    let clicks = item.get("Clicks")?.as_n().ok()?.parse().ok()?;
+    Some((start, clicks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-02-08T17:29:36Z
+    const NOW: i64 = 1_739_035_776;
+
+    #[test]
+    fn buckets_align_to_the_utc_hour_and_day() {
+        assert_eq!(Granularity::Hourly.bucket_start(NOW), 1_739_034_000);
+        assert_eq!(Granularity::Daily.bucket_start(NOW), 1_738_972_800);
+        assert_eq!(Granularity::Hourly.bucket_start(1_739_034_000), 1_739_034_000);
+    }
+
+    #[test]
+    fn hourly_and_daily_buckets_live_in_different_series() {
+        assert_ne!(
+            Granularity::Hourly.series_key("abc1234"),
+            Granularity::Daily.series_key("abc1234")
+        );
+    }
+
+    #[test]
+    fn only_the_offered_ranges_parse() {
+        assert_eq!(HistoryRange::from_days("7").unwrap(), HistoryRange::Week);
+        assert_eq!(HistoryRange::from_days("90").unwrap(), HistoryRange::Quarter);
+        assert!(matches!(HistoryRange::from_days("14"), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn a_week_is_168_hours_ending_now() {
+        let series = fill_series(HistoryRange::Week, NOW, &[]);
+        assert_eq!(series.counts.len(), 168);
+        assert_eq!(series.bucket_secs, HOUR_SECS);
+        assert_eq!(series.start + 167 * HOUR_SECS, Granularity::Hourly.bucket_start(NOW));
+    }
+
+    #[test]
+    fn longer_ranges_are_daily() {
+        assert_eq!(fill_series(HistoryRange::Month, NOW, &[]).counts.len(), 30);
+        let quarter = fill_series(HistoryRange::Quarter, NOW, &[]);
+        assert_eq!(quarter.counts.len(), 90);
+        assert_eq!(quarter.bucket_secs, DAY_SECS);
+    }
+
+    #[test]
+    fn stored_buckets_land_in_their_slots_and_the_rest_are_zero() {
+        let today = Granularity::Daily.bucket_start(NOW);
+        let series = fill_series(
+            HistoryRange::Month,
+            NOW,
+            &[(today, 5), (today - DAY_SECS, 2), (today - 40 * DAY_SECS, 99)],
+        );
+        assert_eq!(series.counts[29], 5);
+        assert_eq!(series.counts[28], 2);
+        assert_eq!(series.total(), 7, "the out-of-window bucket must be ignored");
+    }
+
+    #[test]
+    fn retention_outlives_the_longest_window_per_granularity() {
+        assert!(HOURLY_RETENTION_SECS > i64::from(HistoryRange::Week.days()) * DAY_SECS);
+        assert!(DAILY_RETENTION_SECS > i64::from(HistoryRange::Quarter.days()) * DAY_SECS);
+    }
+}
@@ -0,0 +1,93 @@
+//! Routing and replies for the endpoints that manage one owner's collection of named
+//! things: `/api/tags` and `/api/utm-presets`.
+//!
+//! Both answer `GET` on the collection with a list, one write method on a named member
+//! and `DELETE` on one, and both guard them the same way: reading takes the scope that
+//! reads links and changing anything the scope that creates them, because what is in
+//! the collection only ever ends up on the owner's links.
+
+use lambda_http::http::StatusCode;
+use lambda_http::{tracing, Body, Error, Response};
+use serde::Serialize;
+
+use crate::auth::Scope;
+use crate::error::AppError;
+use crate::response::{empty_response, error_response, json_response};
+
+/// What a request asks for, by method and the path parameter naming a member.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Route<'a> {
+    List,
+    /// The collection's write method on the named member.
+    Write(&'a str),
+    Delete(&'a str),
+    NotAllowed,
+}
+
+impl<'a> Route<'a> {
+    /// Routes `method`, with `write_method` as the one that changes a member. A blank
+    /// name is no name: API Gateway hands over `/api/tags/` with an empty parameter.
+    pub fn of(method: &str, write_method: &str, name: Option<&'a str>) -> Self {
+        match (method, name.filter(|name| !name.is_empty())) {
+            ("GET", None) => Self::List,
+            (method, Some(name)) if method == write_method => Self::Write(name),
+            ("DELETE", Some(name)) => Self::Delete(name),
+            _ => Self::NotAllowed,
+        }
+    }
+
+    /// The scope the route needs, or `None` for a request no route answers.
+    pub fn scope(&self) -> Option<Scope> {
+        match self {
+            Self::List => Some(Scope::LinksRead),
+            Self::Write(_) | Self::Delete(_) => Some(Scope::LinksCreate),
+            Self::NotAllowed => None,
+        }
+    }
+}
+
+/// Answers with `result` as JSON, or with its error; `what` names the collection in the
+/// log.
+pub fn reply(what: &str, result: Result<impl Serialize, AppError>) -> Result<Response<Body>, Error> {
+    match result {
+        Ok(body) => json_response(&StatusCode::OK, &body),
+        Err(e) => failed(what, &e),
+    }
+}
+
+/// [`reply`] for an operation with nothing to return: a 204.
+pub fn reply_empty(what: &str, result: Result<(), AppError>) -> Result<Response<Body>, Error> {
+    match result {
+        Ok(()) => empty_response(&StatusCode::NO_CONTENT),
+        Err(e) => failed(what, &e),
+    }
+}
+
+fn failed(what: &str, e: &AppError) -> Result<Response<Body>, Error> {
+    tracing::error!("{what} request failed 💥 : {:?}", e);
+    error_response(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_method_and_name_pick_the_operation() {
+        assert_eq!(Route::of("GET", "PUT", None), Route::List);
+        assert_eq!(Route::of("PUT", "PUT", Some("newsletter")), Route::Write("newsletter"));
+        assert_eq!(Route::of("PATCH", "PATCH", Some("clients/acme")), Route::Write("clients/acme"));
+        assert_eq!(Route::of("DELETE", "PUT", Some("newsletter")), Route::Delete("newsletter"));
+        assert_eq!(Route::of("GET", "PUT", None).scope(), Some(Scope::LinksRead));
+        assert_eq!(Route::of("DELETE", "PUT", Some("x")).scope(), Some(Scope::LinksCreate));
+    }
+
+    #[test]
+    fn an_operation_on_a_member_needs_its_name() {
+        assert_eq!(Route::of("PUT", "PUT", None), Route::NotAllowed);
+        assert_eq!(Route::of("DELETE", "PUT", Some("")), Route::NotAllowed);
+        assert_eq!(Route::of("GET", "PUT", Some("newsletter")), Route::NotAllowed);
+        assert_eq!(Route::of("PATCH", "PUT", Some("newsletter")), Route::NotAllowed);
+        assert_eq!(Route::of("POST", "PUT", None).scope(), None);
+    }
+}
@@ -1,21 +1,64 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use aws_sdk_dynamodb::operation::put_item::PutItemError;
-use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use aws_sdk_dynamodb::types::AttributeValue;
 use aws_sdk_dynamodb::Client;
-use aws_sdk_dynamodb::error::SdkError;
-use aws_sdk_secretsmanager::Client as SecretsClient;
 use cuid2::CuidConstructor;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use lambda_http::tracing;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use chrono::Utc;
+use std::fmt;
 
-use crate::url_info::UrlInfo;
-use crate::safe_browsing::is_url_safe;
+use crate::url_info::{UrlDetails, UrlInfo};
+use crate::reputation::ReputationPolicy;
+use crate::clicks::ClickSeries;
 use crate::error::AppError;
+use crate::password::{check_password, generate_salt, hash_password};
+use crate::cursor::PageCursor;
+use crate::store::{DynamoLinkStore, LinkEdit, LinkStore, PageKey};
+use crate::rules::{destination_for, normalize_rules, rules_attribute, RedirectRule, Visitor};
+use crate::tags::normalize_tags;
+use crate::utm::Utm;
 
 const URL_LENGTH: u16 = 7;  // The lenght of the shortened URL for CUID2 to generate
 
+// Bounds on a caller-chosen slug. The floor keeps vanity slugs out of the trivially
+// guessable two-letter space; the ceiling keeps them short enough to still be a short link.
+const CUSTOM_SLUG_MIN_LEN: usize = 3;
+const CUSTOM_SLUG_MAX_LEN: usize = 32;
+
+/// Slugs a caller may not claim, compared case-insensitively.
+///
+/// Most of these are first path segments that CloudFront routes to something other than
+/// `visit_link` (`/api/*`, `/assets/*`, `/auth/*`, `/terms`, `/privacy`), so a link with
+/// one of them as its id would be created successfully and then never resolve. The rest
+/// are names we are likely to want for pages of our own later, and are far cheaper to
+/// reserve now than to take back from someone who has already printed them.
+const RESERVED_SLUGS: &[&str] = &[
+    "api", "assets", "auth", "index", "terms", "privacy", "favicon", "admin", "login",
+    "logout", "static", "www",
+];
+
+/// Most links one `POST /api/links/batch` may create. Also the most keys a single
+/// `BatchGetItem` accepts, so the id collision check stays one call.
+pub const MAX_BATCH_SIZE: usize = 100;
+// How many destination pages a batch scrapes at once.
+const BATCH_DETAILS_CONCURRENCY: usize = 10;
+// Rounds of re-minting taken ids.
+const BATCH_ID_ATTEMPTS: u32 = 3;
+// How many links a tag rename or delete updates at once. A tag can be on thousands of
+// links, each up to two conditional writes, and one at a time that outlasts the API
+// Gateway timeout; this many side by side still stays clear of throttling the table.
+const RETAG_CONCURRENCY: usize = 16;
+
+/// How long an expired link keeps its item before DynamoDB TTL removes it.
+///
+/// During this window `visit_link` still finds the item and answers 410 with a page
+/// explaining the link has expired; after it, the item is gone and the link is a plain
+/// 404 like any id that never existed. Without the grace period TTL would race the
+/// friendly page and usually win.
+pub const LINK_EXPIRY_GRACE_SECS: i64 = 30 * 86_400;
+
 /// Builds the value stored in the `SortKey` attribute, which is the **partition key of
 /// the `TimeStampIndex` GSI** — not a sort key, despite the attribute's name.
 ///
@@ -37,17 +80,197 @@ pub fn owner_key(owner_sub: &str) -> String {
 #[derive(Deserialize)]
 pub struct ShortenUrlRequest {
     url_to_shorten: String,
+    /// A vanity id to use instead of a generated one. The htmx form always submits the
+    /// field, as an empty string when left blank, so validation treats blank as absent.
+    #[serde(default)]
+    custom_slug: Option<String>,
+    /// Unix timestamp after which the link stops redirecting.
+    #[serde(default)]
+    expires_at: Option<i64>,
+    /// Number of clicks after which the link stops redirecting.
+    #[serde(default)]
+    max_clicks: Option<u32>,
+    /// Passphrase a visitor must enter before being redirected. Blank means none, for
+    /// the same reason as `custom_slug`. Only its salted hash is ever stored.
+    #[serde(default)]
+    password: Option<String>,
+    /// Show visitors a preview page with the destination before they are sent there,
+    /// instead of redirecting straight away.
+    #[serde(default, deserialize_with = "deserialize_checkbox")]
+    preview: bool,
+    /// Labels to file the link under (see [`crate::tags`]). On an edit, absent leaves
+    /// the link's tags alone and an empty list clears them.
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    tags: Option<Vec<String>>,
+    /// Campaign parameters to merge into `url_to_shorten` (see [`crate::utm`]). Never
+    /// stored as such: by the time the link is written they are part of its destination.
+    #[serde(default)]
+    utm: Option<Utm>,
+    /// Where to send some visitors instead (see [`crate::rules`]), tried in order. On an
+    /// edit, absent leaves the link's rules alone and an empty list clears them.
+    #[serde(default)]
+    rules: Option<Vec<RedirectRule>>,
+}
+
+/// Deserializes a flag from either a JSON boolean or an HTML checkbox.
+///
+/// A checked box is submitted as `on` and an unchecked one not at all, so a plain
+/// `bool` would reject every form that ticked it.
+fn deserialize_checkbox<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct Checkbox;
+
+    impl de::Visitor<'_> for Checkbox {
+        type Value = bool;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a boolean, or a checkbox value")
+        }
+
+        fn visit_bool<E: de::Error>(self, value: bool) -> Result<bool, E> {
+            Ok(value)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<bool, E> {
+            match value {
+                "on" | "true" => Ok(true),
+                "" | "false" => Ok(false),
+                other => Err(E::invalid_value(de::Unexpected::Str(other), &self)),
+            }
+        }
+    }
+
+    deserializer.deserialize_any(Checkbox)
+}
+
+/// Deserializes tags from either a JSON array or a form's comma-separated text field.
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct Tags;
+
+    impl<'de> de::Visitor<'de> for Tags {
+        type Value = Option<Vec<String>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a list of tags, or tags separated by commas")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(Some(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ))
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut tags = vec![];
+            while let Some(tag) = seq.next_element()? {
+                tags.push(tag);
+            }
+            Ok(Some(tags))
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+    }
+
+    deserializer.deserialize_any(Tags)
 }
 
 impl ShortenUrlRequest {
-    pub async fn validate(self, shortener_domain: &str, secrets_client: &SecretsClient, secret_arn: &str, http_client: &reqwest::Client) -> Result<Self, AppError> {
+    pub async fn validate(self, shortener_domain: &str, reputation: &ReputationPolicy) -> Result<Self, AppError> {
 
         // Synchronous validation
-        let validated = self.validate_url_format()
-            .and_then(|req| req.validate_not_recursive(shortener_domain))?;
+        let validated = self.validate_local(shortener_domain)?;
 
         // Async validation (slower)
-        validated.validate_safe_browsing(secrets_client, secret_arn, http_client).await
+        validated.validate_reputation(reputation).await
+    }
+
+    /// Validates a whole batch, returning one result per request in input order.
+    ///
+    /// The local checks run per item exactly as in [`Self::validate`]. The reputation
+    /// check then covers every URL that passed them in one go -- one round trip per
+    /// remote provider instead of one per link -- and fails the same way the single
+    /// check does.
+    ///
+    /// Custom slugs are refused here: the batch write cannot be conditional, so it has
+    /// no way to stop a chosen slug overwriting an existing link (see
+    /// [`UrlShortener::shorten_urls`]).
+    pub async fn validate_batch(
+        reqs: Vec<Self>,
+        shortener_domain: &str,
+        reputation: &ReputationPolicy,
+    ) -> Vec<Result<Self, AppError>> {
+        let locally_valid: Vec<Result<Self, AppError>> = reqs
+            .into_iter()
+            .map(|req| {
+                req.validate_local(shortener_domain).and_then(|req| match req.custom_slug {
+                    Some(_) => Err(AppError::Validation(
+                        "Custom slugs cannot be used in a batch; create the link on its own".to_string(),
+                    )),
+                    None => Ok(req),
+                })
+            })
+            .collect();
+
+        let urls: Vec<String> = locally_valid
+            .iter()
+            .flatten()
+            .flat_map(|req| req.destinations())
+            .collect();
+        if urls.is_empty() {
+            return locally_valid;
+        }
+
+        let refused = reputation.refused(&urls).await;
+
+        locally_valid
+            .into_iter()
+            .map(|result| {
+                result.and_then(|req| match req.destinations().iter().find_map(|url| refused.get(url)) {
+                    Some(refusal) => Err(refusal.error()),
+                    None => Ok(req),
+                })
+            })
+            .collect()
+    }
+
+    /// Every check that needs no network call.
+    fn validate_local(self, shortener_domain: &str) -> Result<Self, AppError> {
+        self.validate_url_format()
+            .and_then(|req| req.validate_utm())
+            .and_then(|req| req.validate_not_recursive(shortener_domain))
+            .and_then(|req| req.validate_custom_slug())
+            .and_then(|req| req.validate_expiry(Utc::now().timestamp()))
+            .and_then(|req| req.validate_password())
+            .and_then(|req| req.validate_tags())
+            .and_then(|req| req.validate_rules(shortener_domain))
+    }
+
+    /// Every URL the link can send a visitor to: its own, then its rules'.
+    fn destinations(&self) -> Vec<String> {
+        let rules = self.rules.iter().flatten();
+        std::iter::once(self.url_to_shorten.clone())
+            .chain(rules.map(|rule| rule.destination.clone()))
+            .collect()
     }
     fn validate_url_format(self) -> Result<Self, AppError> {
         if !is_valid_url(&self.url_to_shorten) {
@@ -55,6 +278,14 @@ impl ShortenUrlRequest {
         }
         Ok(self)
     }
+    /// Merges the campaign parameters into the URL, so the checks after this one, and
+    /// the reputation check in particular, see the destination visitors will get.
+    fn validate_utm(mut self) -> Result<Self, AppError> {
+        if let Some(utm) = self.utm.take() {
+            self.url_to_shorten = utm.normalized()?.apply(&normalize_url(&self.url_to_shorten))?;
+        }
+        Ok(self)
+    }
     fn validate_not_recursive(self, shortener_domain: &str) -> Result<Self, AppError> {
         if is_recursive_url(&self.url_to_shorten, shortener_domain) {
             return Err(AppError::Validation(format!("Cannot shorten links, already shortened links of {shortener_domain}")));
@@ -62,12 +293,80 @@ impl ShortenUrlRequest {
         Ok(self)
     }
 
-    async fn validate_safe_browsing(self, secrets_client: &SecretsClient, secret_arn: &str, http_client: &reqwest::Client) -> Result<Self, AppError> {
-        match is_url_safe(&self.url_to_shorten, secrets_client, secret_arn, http_client).await {
-            Ok(true) => Ok(self),
-            Ok(false) => Err(AppError::SafeBrowsing("URL flagged as unsafe by Google Safe Browsing".to_string())),
-            Err(_) => Ok(self), // Fail open - do not block if the API is down
+    fn validate_custom_slug(mut self) -> Result<Self, AppError> {
+        self.custom_slug = self
+            .custom_slug
+            .map(|slug| slug.trim().to_string())
+            .filter(|slug| !slug.is_empty());
+
+        if let Some(ref slug) = self.custom_slug {
+            check_custom_slug(slug)?;
         }
+        Ok(self)
+    }
+
+    fn validate_expiry(self, now: i64) -> Result<Self, AppError> {
+        // A link that is dead on arrival reads as "the link I just made is broken".
+        if let Some(expires_at) = self.expires_at
+            && expires_at <= now
+        {
+            return Err(AppError::Validation("expires_at must be in the future".to_string()));
+        }
+        if self.max_clicks == Some(0) {
+            return Err(AppError::Validation("max_clicks must be a positive number".to_string()));
+        }
+        Ok(self)
+    }
+
+    fn validate_password(mut self) -> Result<Self, AppError> {
+        // Not trimmed: leading or trailing spaces are a legitimate part of a passphrase,
+        // and silently dropping them would lock out whoever typed them.
+        self.password = self.password.filter(|password| !password.is_empty());
+
+        if let Some(ref password) = self.password {
+            check_password(password)?;
+        }
+        Ok(self)
+    }
+
+    fn validate_tags(mut self) -> Result<Self, AppError> {
+        self.tags = self.tags.map(|tags| normalize_tags(&tags)).transpose()?;
+        Ok(self)
+    }
+
+    /// Each rule destination gets the checks the link's own destination gets, and is
+    /// stored normalized like it: a rule is as good a way to smuggle a URL in.
+    fn validate_rules(mut self, shortener_domain: &str) -> Result<Self, AppError> {
+        let Some(rules) = self.rules.take() else {
+            return Ok(self);
+        };
+        let mut rules = normalize_rules(rules)?;
+        for rule in &mut rules {
+            if !is_valid_url(&rule.destination) {
+                return Err(AppError::Validation(format!(
+                    "Invalid redirect rule destination: {}",
+                    rule.destination
+                )));
+            }
+            if is_recursive_url(&rule.destination, shortener_domain) {
+                return Err(AppError::Validation(format!(
+                    "A redirect rule cannot point at a link of {shortener_domain}"
+                )));
+            }
+            rule.destination = normalize_url(&rule.destination);
+        }
+        self.rules = Some(rules);
+        Ok(self)
+    }
+
+    async fn validate_reputation(self, reputation: &ReputationPolicy) -> Result<Self, AppError> {
+        let destinations = self.destinations();
+        // One round trip for the link and all its rules.
+        let refused = reputation.refused(&destinations).await;
+        if let Some(refusal) = destinations.iter().find_map(|url| refused.get(url)) {
+            return Err(refusal.error());
+        }
+        Ok(self)
     }
 }
 
@@ -76,16 +375,67 @@ pub struct ShortenUrlResponse {
     shortened_url: String,
 }
 
+/// Body of `POST /api/links/batch`: each entry is what `POST /api/links` accepts.
+#[derive(Deserialize)]
+pub struct BatchShortenRequest {
+    pub links: Vec<ShortenUrlRequest>,
+}
+
+/// One entry per requested link, in request order, so a caller can zip it back onto
+/// what it sent. Exactly one of `link` and `error` is present; `status` is what the
+/// single-link endpoint would have answered for that entry.
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<ShortUrl>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<Result<ShortUrl, AppError>> for BatchItemResult {
+    fn from(result: Result<ShortUrl, AppError>) -> Self {
+        match result {
+            Ok(link) => Self { status: 200, link: Some(link), error: None },
+            Err(e) => Self {
+                status: e.status_code().as_u16(),
+                link: None,
+                error: Some(e.public_message()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchShortenResponse {
+    pub results: Vec<BatchItemResult>,
+}
+
 // Response for when we need all the urls
 #[derive(Debug, Serialize)]
 pub struct ListShortUrlResponse {
     short_urls: Vec<ShortUrl>,
-    last_evaluated_id: Option<String>,
-    last_evaluated_timestamp: Option<String>,
+    /// Passed back as `cursor` for the next page; `None` on the last one. Opaque and
+    /// signed (see [`PageCursor`]).
+    pub cursor: Option<String>,
     // TODO: Does this one need to be public? 
     pub has_more: bool,
 }
 
+impl ListShortUrlResponse {
+    pub fn link_ids(&self) -> Vec<String> {
+        self.short_urls.iter().map(|url| url.link_id.clone()).collect()
+    }
+
+    /// Attaches click history fetched separately, by link id. Links missing from
+    /// `history` are left without one.
+    pub fn attach_click_history(&mut self, mut history: HashMap<String, ClickSeries>) {
+        for url in &mut self.short_urls {
+            url.click_history = history.remove(&url.link_id);
+        }
+    }
+}
+
 // A struct that will contain info about our Short links.
 //
 // These field names ARE the public API contract: they appear verbatim in the
@@ -98,11 +448,36 @@ pub struct ShortUrl {
     pub link_id: String,
     original_link: String,
     clicks: u32,
+    /// Hits from crawlers and link unfurlers. Kept apart from `clicks`, which counts
+    /// people, so a link pasted into a busy Slack channel does not look popular.
+    bot_clicks: u32,
     title: Option<String>,
     description: Option<String>,
     content_type: Option<String>,
     image: Option<String>,
     timestamp: i64,
+    expires_at: Option<i64>,
+    max_clicks: Option<u32>,
+    /// Derived at read time from the two limits above, so a client does not have to
+    /// re-implement the rule (and get the `>=` wrong) to grey out a dead link.
+    expired: bool,
+    /// Whether visitors are asked for a passphrase. The passphrase itself, hashed or
+    /// not, never leaves the table.
+    password_protected: bool,
+    /// Set when a rescan found the destination flagged; visitors get a warning page
+    /// instead of the redirect until the owner points the link somewhere else.
+    disabled: bool,
+    /// Whether visitors see a preview page before being sent on.
+    preview: bool,
+    /// Sorted; empty for a link with none.
+    tags: Vec<String>,
+    /// In the order they are tried; empty for a link that sends everyone to
+    /// `original_link`.
+    rules: Vec<RedirectRule>,
+    /// Clicks over the requested window. Only `get_links` fills this in, from the click
+    /// history table, so it is absent rather than empty everywhere else.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    click_history: Option<ClickSeries>,
 }
 
 // Persistence shape: mirrors the DynamoDB attribute names exactly, for
@@ -117,6 +492,9 @@ struct ShortUrlRow {
     original_link: String,
     #[serde(rename = "Clicks")]
     clicks: u32,
+    // Absent until a link's first bot hit, and on every item older than the counter.
+    #[serde(rename = "BotClicks", default)]
+    bot_clicks: u32,
     #[serde(rename = "Title")]
     title: Option<String>,
     #[serde(rename = "Description")]
@@ -127,6 +505,21 @@ struct ShortUrlRow {
     image: Option<String>,
     #[serde(rename = "TimeStamp")]
     timestamp: i64,
+    #[serde(rename = "ExpiresAt")]
+    expires_at: Option<i64>,
+    #[serde(rename = "MaxClicks")]
+    max_clicks: Option<u32>,
+    #[serde(rename = "PasswordHash", default)]
+    password_hash: Option<String>,
+    #[serde(rename = "DisabledAt", default)]
+    disabled_at: Option<i64>,
+    #[serde(rename = "Preview", default)]
+    preview: bool,
+    // A string set, which DynamoDB hands back in no particular order.
+    #[serde(rename = "Tags", default)]
+    tags: Vec<String>,
+    #[serde(rename = "Rules", default)]
+    rules: Vec<RedirectRule>,
     /// Cognito `sub` of the owner.
     ///
     /// `Option` because rows written before authentication existed have no `OwnerId`,
@@ -143,35 +536,342 @@ struct ShortUrlRow {
     owner_id: Option<String>,
 }
 
+impl ShortUrl {
+    /// The response for a link that was just written.
+    fn created(link_id: String, req: &ShortenUrlRequest, details: UrlDetails, timestamp: i64) -> Self {
+        Self {
+            link_id,
+            original_link: req.url_to_shorten.clone(),
+            clicks: 0,
+            bot_clicks: 0,
+            title: details.title,
+            description: details.description,
+            content_type: details.content_type,
+            image: details.image,
+            timestamp,
+            expires_at: req.expires_at,
+            max_clicks: req.max_clicks,
+            expired: false,
+            password_protected: req.password.is_some(),
+            disabled: false,
+            preview: req.preview,
+            tags: req.tags.clone().unwrap_or_default(),
+            rules: req.rules.clone().unwrap_or_default(),
+            click_history: None,
+        }
+    }
+}
+
 impl From<ShortUrlRow> for ShortUrl {
     fn from(row: ShortUrlRow) -> Self {
+        let expired = link_expired(
+            row.expires_at,
+            row.max_clicks,
+            row.clicks,
+            Utc::now().timestamp(),
+        );
         Self {
             link_id: row.link_id,
             original_link: row.original_link,
             clicks: row.clicks,
+            bot_clicks: row.bot_clicks,
             title: row.title,
             description: row.description,
             content_type: row.content_type,
             image: row.image,
             timestamp: row.timestamp,
+            expires_at: row.expires_at,
+            max_clicks: row.max_clicks,
+            expired,
+            password_protected: row.password_hash.is_some(),
+            disabled: row.disabled_at.is_some(),
+            preview: row.preview,
+            tags: {
+                let mut tags = row.tags;
+                tags.sort();
+                tags
+            },
+            rules: row.rules,
+            click_history: None,
         }
     }
 }
-// We are passing the DDB client as well as the table name in the UrlShortener struct.
-// As this makes sense, this is the only thing in our app that will use the client.
+
+/// The part of a link `visit_link` needs to decide what to answer.
+///
+/// Separate from `ShortUrlRow` because resolution must keep working for every item ever
+/// written, including ones far older than the listing shape, so it asks for as little as
+/// possible and defaults the rest.
+#[derive(Debug, Deserialize)]
+pub struct LinkTarget {
+    #[serde(rename = "OriginalLink")]
+    pub original_link: String,
+    #[serde(rename = "Clicks", default)]
+    pub clicks: u32,
+    #[serde(rename = "ExpiresAt", default)]
+    pub expires_at: Option<i64>,
+    #[serde(rename = "MaxClicks", default)]
+    pub max_clicks: Option<u32>,
+    /// Present only on passphrase-protected links, together with `password_salt`.
+    #[serde(rename = "PasswordHash", default)]
+    pub password_hash: Option<String>,
+    #[serde(rename = "PasswordSalt", default)]
+    pub password_salt: Option<String>,
+    /// When a rescan found the destination flagged (see `rescan_links`).
+    #[serde(rename = "DisabledAt", default)]
+    pub disabled_at: Option<i64>,
+    /// Which reputation provider flagged it.
+    #[serde(rename = "DisabledReason", default)]
+    pub disabled_reason: Option<String>,
+    /// Set on links made in preview mode; absent, and so `false`, on every other.
+    #[serde(rename = "Preview", default)]
+    pub preview: bool,
+    // Scraped when the link was made, for the preview page.
+    #[serde(rename = "Title", default)]
+    pub title: Option<String>,
+    #[serde(rename = "Description", default)]
+    pub description: Option<String>,
+    #[serde(rename = "Image", default)]
+    pub image: Option<String>,
+    /// Absent on every link made without rules.
+    #[serde(rename = "Rules", default)]
+    pub rules: Vec<RedirectRule>,
+}
+
+impl LinkTarget {
+    pub fn is_expired(&self, now: i64) -> bool {
+        link_expired(self.expires_at, self.max_clicks, self.clicks, now)
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled_at.is_some()
+    }
+
+    /// Where `visitor` is sent: the first of the link's rules that matches them, or
+    /// `original_link`.
+    pub fn destination_for(&self, visitor: &Visitor) -> &str {
+        destination_for(&self.rules, &self.original_link, visitor)
+    }
+}
+
+/// A destination's host, which the preview page puts first: it is the part of a URL
+/// people can judge at a glance, and the part a lookalike path tries to hide.
+pub fn destination_host(destination: &str) -> Option<String> {
+    url::Url::parse(destination)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+}
+
+/// A link's destination, as a rescan sees it. A link with redirect rules has one for
+/// its own destination and one for each rule's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub link_id: String,
+    pub url: String,
+    /// `None` for links from before ownership existed.
+    pub owner_id: Option<String>,
+}
+
 #[derive(Debug)]
-pub struct UrlShortener {
-    dynamodb_urls_table: String,
+pub struct DestinationPage {
+    pub destinations: Vec<Destination>,
+    /// The `LinkId` to continue the segment after; `None` once it is done.
+    pub next: Option<String>,
+}
+
+/// Whether a link has passed either of its limits.
+///
+/// The click limit is enforced against `Clicks`, which `process_analytics` updates from
+/// the CloudFront log stream a few seconds behind the redirect. A burst can therefore
+/// overshoot `max_clicks` by however many visits land inside that lag; it is a cap on
+/// sustained use, not an exact quota.
+///
+/// Checked on every read rather than left to DynamoDB TTL, for the same reason the API
+/// key authorizer does: TTL deletion is asynchronous and can lag by days.
+pub fn link_expired(
+    expires_at: Option<i64>,
+    max_clicks: Option<u32>,
+    clicks: u32,
+    now: i64,
+) -> bool {
+    expires_at.is_some_and(|at| at <= now) || max_clicks.is_some_and(|max| clicks >= max)
+}
+/// How many links a page of `list_urls` or `search_urls` holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageSize(u32);
+
+impl PageSize {
+    const DEFAULT: u32 = 20;
+    /// A page is one `Query` call, and its links each get a click history lookup.
+    const MAX: u32 = 100;
+
+    /// From `get_links`' `limit` parameter; absent or blank means the default.
+    pub fn from_param(limit: Option<&str>) -> Result<Self, AppError> {
+        let Some(limit) = limit.map(str::trim).filter(|limit| !limit.is_empty()) else {
+            return Ok(Self::default());
+        };
+        match limit.parse() {
+            Ok(n) if (1..=Self::MAX).contains(&n) => Ok(Self(n)),
+            _ => Err(AppError::Validation(format!("limit must be between 1 and {}", Self::MAX))),
+        }
+    }
+
+    /// The number of links, as the `limit` parameter that asked for it.
+    pub fn links(self) -> u32 {
+        self.0
+    }
+
+    fn get(self) -> i32 {
+        self.0 as i32
+    }
+}
+
+impl Default for PageSize {
+    fn default() -> Self {
+        Self(Self::DEFAULT)
+    }
+}
+
+fn invalid_cursor() -> AppError {
+    AppError::Validation("Invalid pagination cursor".to_string())
+}
+
+/// The order a search returns links in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkSort {
+    #[default]
+    Newest,
+    MostClicked,
+}
+
+/// What narrows an owner's link list, from `get_links`' query parameters.
+///
+/// Every parameter is optional and a blank one counts as absent: the dashboard's filter
+/// form submits all of its fields whether or not they were filled in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkFilter {
+    /// Lowercased; matched against the destination, title, description and slug.
+    query: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    min_clicks: u32,
+    /// Normalized like a stored tag, so `Launch` finds links tagged `launch`.
+    tag: Option<String>,
+    sort: LinkSort,
+}
+
+impl LinkFilter {
+    /// Reads `q`, `from`, `to`, `min_clicks`, `tag` and `sort` through `param`.
+    ///
+    /// `from` and `to` take either a Unix timestamp or a `YYYY-MM-DD` date, which is what
+    /// a date input submits; a `to` date includes the whole of that day (UTC).
+    pub fn from_params<'a>(param: impl Fn(&str) -> Option<&'a str>) -> Result<Self, AppError> {
+        let param = |name| param(name).map(str::trim).filter(|value| !value.is_empty());
+
+        let min_clicks = match param("min_clicks") {
+            Some(n) => n
+                .parse()
+                .map_err(|_| AppError::Validation("min_clicks must be a whole number".to_string()))?,
+            None => 0,
+        };
+        let sort = match param("sort") {
+            None | Some("newest") => LinkSort::Newest,
+            Some("clicks") => LinkSort::MostClicked,
+            Some(_) => return Err(AppError::Validation("sort must be newest or clicks".to_string())),
+        };
+
+        Ok(Self {
+            query: param("q").map(str::to_lowercase),
+            since: param("from").map(|from| parse_bound(from, 0)).transpose()?,
+            until: param("to").map(|to| parse_bound(to, DAY_SECS - 1)).transpose()?,
+            min_clicks,
+            tag: param("tag").map(crate::tags::normalize_tag).transpose()?,
+            sort,
+        })
+    }
+
+    /// Whether this asks for nothing beyond the plain newest-first listing, which
+    /// `list_urls` serves a page at a time without reading the rest.
+    pub fn is_unfiltered(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The filter as query parameters, each prefixed with `&`, for a next-page request
+    /// to repeat.
+    pub fn query_string(&self) -> String {
+        let mut params = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(ref query) = self.query {
+            params.append_pair("q", query);
+        }
+        if let Some(since) = self.since {
+            params.append_pair("from", &since.to_string());
+        }
+        if let Some(until) = self.until {
+            params.append_pair("to", &until.to_string());
+        }
+        if self.min_clicks > 0 {
+            params.append_pair("min_clicks", &self.min_clicks.to_string());
+        }
+        if let Some(ref tag) = self.tag {
+            params.append_pair("tag", tag);
+        }
+        if self.sort == LinkSort::MostClicked {
+            params.append_pair("sort", "clicks");
+        }
+        let params = params.finish();
+        if params.is_empty() { params } else { format!("&{params}") }
+    }
+
+    fn matches(&self, row: &ShortUrlRow) -> bool {
+        let Some(ref query) = self.query else {
+            return true;
+        };
+        [Some(&row.original_link), row.title.as_ref(), row.description.as_ref(), Some(&row.link_id)]
+            .into_iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(query.as_str()))
+    }
+
+    /// Where `row` falls in this filter's order, as a key that sorts descending: the
+    /// clicks part is fixed at zero when sorting by age, and the id breaks ties the way
+    /// the `TimeStampIndex` listing does.
+    fn position(&self, clicks: u32, timestamp: i64, link_id: &str) -> (u32, i64, String) {
+        let clicks = if self.sort == LinkSort::MostClicked { clicks } else { 0 };
+        (clicks, timestamp, link_id.to_string())
+    }
+}
+
+const DAY_SECS: i64 = 86_400;
+
+/// A `from`/`to` bound: a Unix timestamp as is, or a date's midnight plus `into_day`.
+fn parse_bound(value: &str, into_day: i64) -> Result<i64, AppError> {
+    if let Ok(timestamp) = value.parse() {
+        return Ok(timestamp);
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp() + into_day)
+        .map_err(|_| AppError::Validation("from and to must be dates (YYYY-MM-DD) or Unix timestamps".to_string()))
+}
+
+/// The link operations, over whichever [`LinkStore`] holds the links. Every Lambda
+/// uses DynamoDB, which is what [`UrlShortener::new`] builds.
+#[derive(Debug)]
+pub struct UrlShortener<S = DynamoLinkStore> {
+    store: S,
     pub shortener_domain: String,
-    dynamodb_client: Client,
 }
 
 impl UrlShortener {
     pub fn new(dynamodb_urls_table: &str, shortener_domain: &str, dynamodb_client: Client) -> Self {
+        Self::with_store(DynamoLinkStore::new(dynamodb_urls_table, dynamodb_client), shortener_domain)
+    }
+}
+
+impl<S: LinkStore> UrlShortener<S> {
+    pub fn with_store(store: S, shortener_domain: &str) -> Self {
         Self {
-            dynamodb_urls_table: dynamodb_urls_table.to_string(),
+            store,
             shortener_domain: shortener_domain.to_string(),
-            dynamodb_client,
         }
     }
 
@@ -191,148 +891,324 @@ impl UrlShortener {
         // Normalize the URL before:
         let normalized_url = normalize_url(&req.url_to_shorten);
 
-        let short_url = self.generate_short_url();
+        // A vanity slug was already checked by `validate`; without one, mint an id.
+        let short_url = match req.custom_slug {
+            Some(ref slug) => slug.clone(),
+            None => self.generate_short_url(),
+        };
 
         let url_details = url_info
             .fetch_details(&normalized_url)
             .await
             .unwrap_or_default();
 
-        // Using the DDB Client from the Struct
-        //self.dynamodb_client
-        let mut put_item = self
-            .dynamodb_client
-            .put_item() // Put single item
-            .table_name(&self.dynamodb_urls_table) // Table name is from the Struct
-            // GSI partition key -- per-owner, see `owner_key`.
-            .item("SortKey".to_string(), AttributeValue::S(owner_key(owner_sub)))
-            // Authoritative ownership field. Stored alongside `SortKey` so answering
-            // "who owns this" never requires parsing a composite key.
-            .item("OwnerId", AttributeValue::S(owner_sub.to_string()))
-            .item("LinkId", AttributeValue::S(short_url.clone())) // Putting item "LinkId" as
-            // String
-            .item(
-                "OriginalLink",
-                AttributeValue::S(normalized_url.clone()),
-            ) // Putting item
-            // "OriginalLink"
-            // as String
-            .item("Clicks", AttributeValue::N("0".to_string())); // Putting item "Clicks" a Number,
-                                                                 // specifically 0 as this is a new
-                                                                 // item.
+        // NOTE:for future Darko - you deal with the local time vs UTC
+        let current_time = Utc::now().timestamp();
+        let item = new_link_item(owner_sub, &short_url, &normalized_url, &url_details, &req, current_time);
 
-        // Check if we have some URL details to post also
-        if let Some(ref title) = url_details.title {
-            put_item = put_item.item("Title", AttributeValue::S(title.to_string()));
-        }
-        if let Some(ref description) = url_details.description {
-            put_item = put_item.item("Description", AttributeValue::S(description.to_string()));
+        // The write is conditional on the "LinkId" not already existing.
+        if self.store.insert(item).await? {
+            return Ok(ShortUrl::created(short_url, &req, url_details, current_time));
         }
-        if let Some(ref content_type) = url_details.content_type {
-            put_item = put_item.item("ContentType", AttributeValue::S(content_type.to_string()));
+
+        if req.custom_slug.is_some() {
+            // A taken vanity slug is the caller's to resolve by picking another name, so it
+            // is a 409 rather than the retry message below -- retrying the same slug can
+            // never succeed.
+            tracing::info!("Custom slug '{}' is already taken", short_url);
+            Err(AppError::Conflict(format!("The short link '{short_url}' is already taken")))
+        } else {
+            tracing::error!("Generated link id {} already exists", short_url);
+            Err(AppError::Validation("The Link ID we tried to create, already exists. Please try again.".to_string()))
         }
-        if let Some(ref image) = url_details.image {
-            put_item = put_item.item("Image", AttributeValue::S(image.to_string()));
+    }
+
+    /// Creates many links owned by `owner_sub` in as few DynamoDB calls as possible.
+    ///
+    /// Takes the output of [`ShortenUrlRequest::validate_batch`] and returns one result
+    /// per input, in the same order: an item that failed validation passes its error
+    /// straight through, so the caller can report every position without re-aligning.
+    ///
+    /// `BatchWriteItem` cannot carry a condition expression, so the
+    /// `attribute_not_exists(LinkId)` guard `shorten_url` relies on is not available
+    /// here. Generated ids are therefore checked against the table with `BatchGetItem`
+    /// first and re-minted if taken, which is also why `validate_batch` refuses custom
+    /// slugs: an unconditional write of a caller-chosen id could overwrite someone
+    /// else's link.
+    pub async fn shorten_urls(
+        &self,
+        reqs: Vec<Result<ShortenUrlRequest, AppError>>,
+        url_info: &UrlInfo,
+        owner_sub: &str,
+    ) -> Vec<Result<ShortUrl, AppError>> {
+        let mut results: Vec<Option<Result<ShortUrl, AppError>>> = Vec::with_capacity(reqs.len());
+        let mut pending = vec![];
+        for (index, req) in reqs.into_iter().enumerate() {
+            match req {
+                Ok(req) => {
+                    results.push(None);
+                    pending.push((index, req));
+                }
+                Err(e) => results.push(Some(Err(e))),
+            }
         }
 
-        // Add the current timestamp
-        // NOTE:for future Darko - you deal with the local time vs UTC
+        let ids = match self.free_link_ids(pending.len()).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                tracing::error!("Could not reserve ids for batch: {:?}", e);
+                return results
+                    .into_iter()
+                    .map(|r| r.unwrap_or_else(|| Err(AppError::Internal("Could not allocate a link id".to_string()))))
+                    .collect();
+            }
+        };
+
+        // Scraping is the slow part and every URL is a different host, so fetch them
+        // side by side -- but bounded, so a batch of a hundred does not open a hundred
+        // sockets from one small Lambda.
+        let urls: Vec<String> = pending
+            .iter()
+            .map(|(_, req)| normalize_url(&req.url_to_shorten))
+            .collect();
+        let details: Vec<UrlDetails> = stream::iter(urls)
+            .map(|url| async move { url_info.fetch_details(&url).await.unwrap_or_default() })
+            .buffered(BATCH_DETAILS_CONCURRENCY)
+            .collect()
+            .await;
+
         let current_time = Utc::now().timestamp();
-        put_item = put_item.item("TimeStamp", AttributeValue::N(current_time.to_string()));
-
-        // Once we are ready, let's send the call
-        put_item
-            .condition_expression("attribute_not_exists(LinkId)") // We are making a condition to
-            // this put_item to be that the
-            // "LinkId" cannot already exist
-            .send()
-            .await
-            .map(|_| ShortUrl {
-                // Just mapping the oputput to a new struct ShortenUrlResponse
-                link_id: short_url,
-                original_link: req.url_to_shorten.clone(),
-                clicks: 0,
-                title: url_details.title,
-                description: url_details.description,
-                content_type: url_details.content_type,
-                image: url_details.image,
-                timestamp: current_time, //TODO: Clean this up
-            })
-            .map_err(|e| match e {
-                SdkError::ServiceError(err) => {
-                    match err.err() {
-                        PutItemError::ConditionalCheckFailedException(e) => {
-                            tracing::error!("Error creating link {:?}", e);
-                            AppError::Validation("The Link ID we tried to create, already exists. Please try again.".to_string())
-                        },
-                        other_error => {
-                            tracing::error!("Error creating link {:?}", &other_error);
-                            AppError::database(SdkError::ServiceError(err))
-                        }
-                    }
-                },
-                other_sdk_error => {
-                    tracing::error!("Error creating link {:?}", &other_sdk_error);
-                    AppError::database(other_sdk_error)
+        let mut created = HashMap::new();
+        let mut items = vec![];
+        for (((index, req), link_id), details) in pending.into_iter().zip(ids).zip(details) {
+            let normalized_url = normalize_url(&req.url_to_shorten);
+            items.push(new_link_item(owner_sub, &link_id, &normalized_url, &details, &req, current_time));
+            created.insert(link_id.clone(), (index, ShortUrl::created(link_id, &req, details, current_time)));
+        }
+
+        for (link_id, outcome) in self.store.insert_all(items).await {
+            if let Some((index, short_url)) = created.remove(&link_id) {
+                results[index] = Some(outcome.map(|_| short_url));
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|r| r.unwrap_or_else(|| Err(AppError::Internal("Link was not written".to_string()))))
+            .collect()
+    }
+
+    /// Mints `count` ids that are distinct from each other and not already in the table.
+    ///
+    /// Collisions in a 7-character CUID2 space are rare, so this almost always costs one
+    /// `BatchGetItem`.
+    async fn free_link_ids(&self, count: usize) -> Result<Vec<String>, AppError> {
+        let mut ids: Vec<String> = vec![];
+        for _ in 0..BATCH_ID_ATTEMPTS {
+            let mut candidates = HashSet::new();
+            while ids.len() + candidates.len() < count {
+                let id = self.generate_short_url();
+                if !ids.contains(&id) {
+                    candidates.insert(id);
                 }
-            })
+            }
+            if candidates.is_empty() {
+                break;
+            }
+
+            let taken = self.store.taken_ids(&candidates).await?;
+            ids.extend(candidates.into_iter().filter(|id| !taken.contains(id)));
+        }
+
+        if ids.len() < count {
+            return Err(AppError::Internal("Could not find enough free link ids".to_string()));
+        }
+        Ok(ids)
+    }
+
+    /// Points an existing link owned by `owner_sub` at a new destination.
+    ///
+    /// `req` must already have been through [`ShortenUrlRequest::validate`], exactly as
+    /// for creation: an edit is the cheapest way to smuggle a bad URL past checks that
+    /// only ran once, so it gets the same format, recursion and Safe Browsing gates. The
+    /// scraped metadata is refreshed too, because the old title describes a page the link
+    /// no longer leads to.
+    ///
+    /// Tags and rules in `req` replace the link's own; without any, they are left as
+    /// they were. Everything goes in one write, so an edit is never half-applied.
+    ///
+    /// A rescan's disabled mark is lifted only when every destination the link will
+    /// have has just been checked: the mark may be about a rule destination, and an
+    /// edit that keeps the rules has not looked at those again.
+    ///
+    /// Ownership is a condition on the write itself rather than a read-then-write, so
+    /// there is no window in which the item can change hands between check and update.
+    /// A failed condition is [`AppError::Forbidden`] whether the link belongs to someone
+    /// else or does not exist at all. The read below only decides about the mark.
+    pub async fn update_destination(
+        &self,
+        link_id: &str,
+        req: ShortenUrlRequest,
+        url_info: &UrlInfo,
+        owner_sub: &str,
+    ) -> Result<ShortUrl, AppError> {
+        // The id is the link's identity -- everything already printed points at it.
+        if req.custom_slug.is_some() {
+            return Err(AppError::Validation("A link's slug cannot be changed".to_string()));
+        }
+        // Limits are set at creation; an edit that silently dropped them would be worse
+        // than one that refuses.
+        if req.expires_at.is_some() || req.max_clicks.is_some() {
+            return Err(AppError::Validation(
+                "A link's expiry cannot be changed after creation".to_string(),
+            ));
+        }
+        if req.password.is_some() {
+            return Err(AppError::Validation(
+                "A link's password cannot be changed after creation".to_string(),
+            ));
+        }
+        if req.preview {
+            return Err(AppError::Validation(
+                "A link's preview mode cannot be changed after creation".to_string(),
+            ));
+        }
+
+        let normalized_url = normalize_url(&req.url_to_shorten);
+
+        let url_details = url_info
+            .fetch_details(&normalized_url)
+            .await
+            .unwrap_or_default();
+
+        let keeps_stored_rules = req.rules.is_none()
+            && self.store.get(link_id).await?.is_some_and(|item| item.contains_key("Rules"));
+        let edit = LinkEdit {
+            url: &normalized_url,
+            details: &url_details,
+            tags: req.tags.as_deref(),
+            rules: req.rules.as_deref(),
+            enable: !keeps_stored_rules,
+        };
+        let attributes = self.store.edit(link_id, owner_sub, &edit).await?;
+        let row: ShortUrlRow = serde_dynamo::from_item(attributes)?;
+        Ok(ShortUrl::from(row))
+    }
+
+    /// Renames tag `from` to `to` on those of `link_ids` that carry it, or takes it off
+    /// them when `to` is `None`, and returns how many links changed.
+    ///
+    /// `link_ids` comes from the tag index, which trails the links by a stream delivery,
+    /// so [`LinkStore::retag`] only changes a link that still has the tag and that
+    /// `owner_sub` still owns. A link deleted in the meantime is skipped rather than
+    /// failing the rest.
+    pub async fn retag(
+        &self,
+        owner_sub: &str,
+        link_ids: &[String],
+        from: &str,
+        to: Option<&str>,
+    ) -> Result<usize, AppError> {
+        // The futures are made up front: a stream closure over borrowed ids would leave
+        // the handler's future not provably `Send`.
+        let retags: Vec<_> = link_ids
+            .iter()
+            .map(|link_id| self.store.retag(link_id, owner_sub, from, to))
+            .collect();
+        stream::iter(retags)
+            .buffer_unordered(RETAG_CONCURRENCY)
+            .try_fold(0, |changed, retagged| async move { Ok(changed + usize::from(retagged)) })
+            .await
     }
-    // Get the url from DynamoDB AND increment the count
+
+    /// Deletes a link owned by `owner_sub`.
+    ///
+    /// Like [`Self::update_destination`], ownership is a condition on the delete itself, so
+    /// "not yours" and "does not exist" both surface as [`AppError::Forbidden`] and a
+    /// caller cannot use this to probe for other people's link ids.
+    pub async fn delete_url(&self, link_id: &str, owner_sub: &str) -> Result<(), AppError> {
+        self.store.delete(link_id, owner_sub).await
+    }
+
+    /// Looks up what a short link resolves to, for `visit_link`.
+    ///
+    /// Returns the target rather than a bare URL so the caller can tell an expired link
+    /// (a friendly 410) from an unknown one (a 404).
     pub async fn retrieve_url(
         &self,
         short_url: &str,
-    ) -> Result<Option<String>, AppError> {
-        let result = self
-            .dynamodb_client
-            .get_item()
-            .table_name(&self.dynamodb_urls_table)
-            .key("LinkId", AttributeValue::S(short_url.to_string()))
-            .send()
-            .await
-            .map(|record| {
-                // Succesfull retrieve from DynamoDB
-                record.item.and_then(|attributes| {
-                    // If there is (Some)thing in the Item
-                    attributes.get("OriginalLink").and_then(
-                        |v| // If there is Some with an attr "OriginalLink"
-                                v.as_s()  // Try to convert it to String
-                                .cloned() // Try to Clone the Result so we own it
-                                .ok(),
-                    ) // Return an Option from Result if all works
-                })
-            });
-        match result {
-            Err(e) => {
-                tracing::error!("Error retrieving URL: {:?}", e);
-                Err(AppError::database(e))
-            }
-            Ok(result) => Ok(result),
+    ) -> Result<Option<LinkTarget>, AppError> {
+        match self.store.get(short_url).await? {
+            Some(item) => Ok(Some(serde_dynamo::from_item(item)?)),
+            None => Ok(None),
         }
     }
-    // Increment Click Count
-    pub async fn increment_click_count(
+    /// Adds `clicks` human visits and `bot_clicks` crawler hits to a link's all-time
+    /// counters in one write.
+    ///
+    /// Only `Clicks` counts towards `max_clicks`: a link limited to ten visitors must not
+    /// be used up by the unfurlers of the chat it was pasted into.
+    ///
+    /// `process_analytics` tallies a whole batch of log records first, so a viral link
+    /// costs one `UpdateItem` per batch rather than one per visit. A hit on an id that is
+    /// not a link (anything that 404'd) fails the existence condition and comes back as
+    /// [`AppError::NotFound`], which the caller skips rather than retries.
+    pub async fn add_clicks(
         &self,
         short_url: &str,
+        clicks: u32,
+        bot_clicks: u32,
     ) -> Result<(), AppError> {
-        let result = self
-            .dynamodb_client
-            .update_item()
-            .table_name(&self.dynamodb_urls_table)
-            .key("LinkId", AttributeValue::S(short_url.to_string()))
-            .update_expression("SET Clicks = Clicks + :val")
-            .expression_attribute_values(":val", AttributeValue::N("1".to_string()))
-            .condition_expression("attribute_exists(LinkId)")
-            .return_values(ReturnValue::AllNew)
-            .send()
-            .await;
+        self.store.add_clicks(short_url, clicks, bot_clicks).await
+    }
 
-        match result {
-            Err(e) => {
-                tracing::error!("Error incrementing clicks: {:?}", e);
-                Err(AppError::database(e))
-            }
-            Ok(_) => Ok(()),
-        }
+    /// One page of one segment of a scan over every link that is not already disabled,
+    /// for `rescan_links`.
+    ///
+    /// `total_segments` splits the table into disjoint parts that can be walked side by
+    /// side; `start` is the previous page's `next`. A page may hold more destinations
+    /// than `limit`, since rule destinations come with their link.
+    pub async fn scan_destinations(
+        &self,
+        segment: i32,
+        total_segments: i32,
+        start: Option<String>,
+        limit: i32,
+    ) -> Result<DestinationPage, AppError> {
+        let page = self.store.scan(segment, total_segments, start, limit).await?;
+        let destinations = page
+            .items
+            .into_iter()
+            .flat_map(|item| {
+                let text = |attribute: &str| item.get(attribute).and_then(|v| v.as_s().ok()).cloned();
+                let (Some(link_id), Some(url)) = (text("LinkId"), text("OriginalLink")) else {
+                    return vec![];
+                };
+                let rules: Vec<RedirectRule> = item
+                    .get("Rules")
+                    .and_then(|rules| serde_dynamo::from_attribute_value(rules.clone()).ok())
+                    .unwrap_or_default();
+                let owner_id = text("OwnerId");
+                std::iter::once(url)
+                    .chain(rules.into_iter().map(|rule| rule.destination))
+                    .map(|url| Destination {
+                        link_id: link_id.clone(),
+                        url,
+                        owner_id: owner_id.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(DestinationPage {
+            destinations,
+            next: page.last_key,
+        })
+    }
+
+    /// Stops a link redirecting because `reason` (a reputation provider) flagged its
+    /// destination. Pointing the link somewhere else lifts it again.
+    pub async fn disable_link(&self, link_id: &str, reason: &str) -> Result<(), AppError> {
+        self.store.disable(link_id, reason, Utc::now().timestamp()).await
     }
 
     /// Lists the links owned by `owner_sub`, newest first.
@@ -345,76 +1221,103 @@ impl UrlShortener {
     pub async fn list_urls(
         &self,
         owner_sub: &str,
-        last_evaluated_id: Option<&str>,
-        last_evaluated_timestamp: Option<&str>,
+        cursor: Option<&str>,
+        page_size: PageSize,
+        cursor_key: &[u8],
     ) -> Result<ListShortUrlResponse, AppError> {
-        let partition = owner_key(owner_sub);
-
-        // Run a scan on 25 items, but make it mutable as we may do something in a bit.
-        let mut query = self
-            .dynamodb_client
-            .query()
-            .index_name("TimeStampIndex")
-            .key_condition_expression("#pk = :pk")
-            .expression_attribute_names("#pk", "SortKey")
-            .expression_attribute_values(
-                ":pk",
-                AttributeValue::S(partition.clone())
-            )
-            .table_name(&self.dynamodb_urls_table)
-            .scan_index_forward(false)
-            .limit(5);
-
-        // If we have a last_evaluated_id as Some() modify the scan to include the
-        // exclusive_start_key() with a value of the last_evaluated_id
-        if let (Some(lei), Some(letime)) = (last_evaluated_id, last_evaluated_timestamp) {
-            let mut exclusive_start_key = HashMap::new();
-            // Must match the queried partition exactly, or DynamoDB rejects the key.
-            exclusive_start_key.insert("SortKey".to_string(), AttributeValue::S(partition.clone()));
-            exclusive_start_key.insert("LinkId".to_string(), AttributeValue::S(lei.to_string()));
-            exclusive_start_key.insert("TimeStamp".to_string(), AttributeValue::N(letime.to_string()));
-            query = query.set_exclusive_start_key(Some(exclusive_start_key));
-        }
-
-        // Run the scan
-        let result = query
-            .send()
-            .await
-            .map_err(AppError::database)?;
-
-        // An empty vector to store all teh short_urls
-        let mut short_urls = vec![];
-
-        // If we get somethign back lets do the try_from() for them into the ShortUrl struct
-        if let Some(items) = result.items {
-            let rows: Vec<ShortUrlRow> = serde_dynamo::from_items(items)
-                .map_err(AppError::Serialization)?;
-            short_urls = rows.into_iter().map(ShortUrl::from).collect();
-        }
-
-        // Set the last_evaluated_id from the result
-        // If the key is Empty that means the last page of results has been processed.
-                // Extract pagination tokens
-        let (last_evaluated_id, last_evaluated_timestamp) = 
-            if let Some(last_key) = result.last_evaluated_key {
-                (
-                    last_key.get("LinkId")
-                        .and_then(|v| v.as_s().ok())
-                        .map(|s| s.to_string()),
-                    last_key.get("TimeStamp")
-                        .and_then(|v| v.as_n().ok())
-                        .map(|s| s.to_string())
-                )
-            } else {
-                (None, None)
-            };
-        let has_more = last_evaluated_id.is_some() && last_evaluated_timestamp.is_some();
+        let start = match cursor {
+            Some(token) => {
+                let cursor = PageCursor::verify(token, cursor_key, owner_sub)?;
+                // A cursor from a search by clicks is a position in a different order.
+                if cursor.clicks.is_some() {
+                    return Err(invalid_cursor());
+                }
+                Some(PageKey {
+                    link_id: cursor.link_id,
+                    timestamp: cursor.timestamp.to_string(),
+                })
+            }
+            None => None,
+        };
+
+        let page = self.store.list(owner_sub, start, page_size.get()).await?;
+
+        let rows: Vec<ShortUrlRow> = serde_dynamo::from_items(page.items)
+            .map_err(AppError::Serialization)?;
+        let short_urls = rows.into_iter().map(ShortUrl::from).collect();
+
+        // No key means the last page of results has been processed. The key is re-read
+        // from what the store returned, never from the request, before it is signed.
+        let cursor = page.last_key.and_then(|key| {
+            let timestamp = key.timestamp.parse().ok()?;
+            Some(PageCursor::new(owner_sub, &key.link_id, timestamp, None).sign(cursor_key))
+        });
 
         // Return the ListShortUrlResponse Struct with all the urls
         Ok(ListShortUrlResponse {
             short_urls,
-            last_evaluated_id,
-            last_evaluated_timestamp,
+            has_more: cursor.is_some(),
+            cursor,
+        })
+    }
+
+    /// Lists the links owned by `owner_sub` that pass `filter`, in its order.
+    ///
+    /// Scoped exactly like [`Self::list_urls`]: the store reads only the owner's
+    /// `TimeStampIndex` partition. DynamoDB can narrow that by date and click count but
+    /// cannot match text case-insensitively or order by clicks, so every link in range
+    /// is read on each page and the rest is done here -- a cost that grows with one
+    /// owner's link count, never with anyone else's.
+    ///
+    /// Pages resume after the position of the previous page's last link in the sort
+    /// order, rather than an offset, so a link created or deleted in between neither
+    /// repeats nor skips one.
+    pub async fn search_urls(
+        &self,
+        owner_sub: &str,
+        filter: &LinkFilter,
+        cursor: Option<&str>,
+        page_size: PageSize,
+        cursor_key: &[u8],
+    ) -> Result<ListShortUrlResponse, AppError> {
+        let by_clicks = filter.sort == LinkSort::MostClicked;
+        let start = match cursor {
+            Some(token) => {
+                let cursor = PageCursor::verify(token, cursor_key, owner_sub)?;
+                // Clicks are in the cursor exactly when the order needs them.
+                if cursor.clicks.is_some() != by_clicks {
+                    return Err(invalid_cursor());
+                }
+                Some(filter.position(cursor.clicks.unwrap_or(0), cursor.timestamp, &cursor.link_id))
+            }
+            None => None,
+        };
+
+        let items = self
+            .store
+            .search(owner_sub, filter.since, filter.until, filter.min_clicks, filter.tag.as_deref())
+            .await?;
+        let rows: Vec<ShortUrlRow> = serde_dynamo::from_items(items).map_err(AppError::Serialization)?;
+
+        let mut rows: Vec<((u32, i64, String), ShortUrlRow)> = rows
+            .into_iter()
+            .filter(|row| filter.matches(row))
+            .map(|row| (filter.position(row.clicks, row.timestamp, &row.link_id), row))
+            .filter(|(position, _)| start.as_ref().is_none_or(|start| position < start))
+            .collect();
+        rows.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let page_size = page_size.get() as usize;
+        let has_more = rows.len() > page_size;
+        rows.truncate(page_size);
+        let cursor = rows.last().filter(|_| has_more).map(|(_, row)| {
+            let clicks = by_clicks.then_some(row.clicks);
+            PageCursor::new(owner_sub, &row.link_id, row.timestamp, clicks).sign(cursor_key)
+        });
+
+        Ok(ListShortUrlResponse {
+            short_urls: rows.into_iter().map(|(_, row)| ShortUrl::from(row)).collect(),
+            cursor,
             has_more,
         })
     }
@@ -422,7 +1325,77 @@ impl UrlShortener {
         let idgen = CuidConstructor::new().with_length(URL_LENGTH);
         idgen.create_id()
     }
-}
+}
+    /// The DynamoDB item for a brand-new link, shared by the single and batch create paths
+    /// so the two cannot drift in what they store.
+    fn new_link_item(
+        owner_sub: &str,
+        link_id: &str,
+        normalized_url: &str,
+        details: &UrlDetails,
+        req: &ShortenUrlRequest,
+        current_time: i64,
+    ) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::from([
+            // GSI partition key -- per-owner, see `owner_key`.
+            ("SortKey".to_string(), AttributeValue::S(owner_key(owner_sub))),
+            // Authoritative ownership field. Stored alongside `SortKey` so answering
+            // "who owns this" never requires parsing a composite key.
+            ("OwnerId".to_string(), AttributeValue::S(owner_sub.to_string())),
+            ("LinkId".to_string(), AttributeValue::S(link_id.to_string())),
+            ("OriginalLink".to_string(), AttributeValue::S(normalized_url.to_string())),
+            // A new link has no clicks yet.
+            ("Clicks".to_string(), AttributeValue::N("0".to_string())),
+            ("TimeStamp".to_string(), AttributeValue::N(current_time.to_string())),
+        ]);
+
+        // Check if we have some URL details to post also
+        let scraped = [
+            ("Title", &details.title),
+            ("Description", &details.description),
+            ("ContentType", &details.content_type),
+            ("Image", &details.image),
+        ];
+        for (attribute, value) in scraped {
+            if let Some(value) = value {
+                item.insert(attribute.to_string(), AttributeValue::S(value.to_string()));
+            }
+        }
+
+        // Expiry. `PurgeAt` is the table's TTL attribute and trails `ExpiresAt` by the
+        // grace period, so the expired page has an item to render from for a while.
+        if let Some(expires_at) = req.expires_at {
+            item.insert("ExpiresAt".to_string(), AttributeValue::N(expires_at.to_string()));
+            item.insert(
+                "PurgeAt".to_string(),
+                AttributeValue::N((expires_at + LINK_EXPIRY_GRACE_SECS).to_string()),
+            );
+        }
+        if let Some(max_clicks) = req.max_clicks {
+            item.insert("MaxClicks".to_string(), AttributeValue::N(max_clicks.to_string()));
+        }
+        // Written only when set, like the limits, so existing items need no migration.
+        if req.preview {
+            item.insert("Preview".to_string(), AttributeValue::Bool(true));
+        }
+        // DynamoDB refuses an empty set, so no tags means no attribute.
+        if let Some(tags) = req.tags.as_ref().filter(|tags| !tags.is_empty()) {
+            item.insert("Tags".to_string(), AttributeValue::Ss(tags.clone()));
+        }
+        if let Some(rules) = req.rules.as_ref().filter(|rules| !rules.is_empty()) {
+            item.insert("Rules".to_string(), rules_attribute(rules));
+        }
+
+        // Fresh salt per link, stored beside the hash: it only has to be unique, not secret.
+        if let Some(ref password) = req.password {
+            let salt = generate_salt();
+            item.insert("PasswordHash".to_string(), AttributeValue::S(hash_password(password, &salt)));
+            item.insert("PasswordSalt".to_string(), AttributeValue::S(salt));
+        }
+
+        item
+    }
+
     // Normalize the URL
     fn normalize_url(url: &str) -> String {
         if url.starts_with("http://") || url.starts_with("https://") {
@@ -443,6 +1416,30 @@ impl UrlShortener {
         }
     }
 
+    /// Checks a caller-chosen slug against the charset, the length bounds and the reserved
+    /// list.
+    ///
+    /// The charset is the URL-unreserved set minus `.` and `~`: a dot would let a slug look
+    /// like a file CloudFront serves from S3 (`index.html`), and neither is worth the
+    /// confusion in a link someone has to read aloud.
+    fn check_custom_slug(slug: &str) -> Result<(), AppError> {
+        let len = slug.chars().count();
+        if !(CUSTOM_SLUG_MIN_LEN..=CUSTOM_SLUG_MAX_LEN).contains(&len) {
+            return Err(AppError::Validation(format!(
+                "Custom slug must be between {CUSTOM_SLUG_MIN_LEN} and {CUSTOM_SLUG_MAX_LEN} characters"
+            )));
+        }
+        if !slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(AppError::Validation(
+                "Custom slug may only contain letters, digits, '-' and '_'".to_string(),
+            ));
+        }
+        if RESERVED_SLUGS.contains(&slug.to_ascii_lowercase().as_str()) {
+            return Err(AppError::Validation(format!("The slug '{slug}' is reserved")));
+        }
+        Ok(())
+    }
+
     // Check if the url is not the short URL itself
     fn is_recursive_url(url: &str, shortener_domain: &str) -> bool {
         if let Ok(parsed) = url::Url::parse(&normalize_url(url)) {
@@ -457,6 +1454,7 @@ impl UrlShortener {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::{InMemoryLinkStore, Item, ItemPage, ScanPage};
 
     const TEST_SUB: &str = "cognito-sub-123";
 
@@ -596,12 +1594,21 @@ mod tests {
         assert_eq!(
             keys,
             [
+                "bot_clicks",
                 "clicks",
                 "content_type",
                 "description",
+                "disabled",
+                "expired",
+                "expires_at",
                 "image",
                 "link_id",
+                "max_clicks",
                 "original_link",
+                "password_protected",
+                "preview",
+                "rules",
+                "tags",
                 "timestamp",
                 "title",
             ],
@@ -610,6 +1617,7 @@ mod tests {
 
         assert_eq!(obj["link_id"], "abc1234");
         assert_eq!(obj["clicks"], 42);
+        assert_eq!(obj["bot_clicks"], 0, "an item with no BotClicks has had no bot hits");
         assert_eq!(obj["timestamp"], 1_739_035_776i64);
     }
 
@@ -623,6 +1631,271 @@ mod tests {
         assert!(link.is_ok(), "templates::Link must deserialize the API shape: {link:?}");
     }
 
+    #[test]
+    fn a_link_with_no_limits_never_expires() {
+        assert!(!link_expired(None, None, u32::MAX, i64::MAX));
+    }
+
+    #[test]
+    fn a_link_expires_at_its_timestamp_not_after_it() {
+        assert!(!link_expired(Some(1_000), None, 0, 999));
+        assert!(link_expired(Some(1_000), None, 0, 1_000));
+        assert!(link_expired(Some(1_000), None, 0, 1_001));
+    }
+
+    /// `max_clicks: 3` means three visits redirect and the fourth does not.
+    #[test]
+    fn a_link_expires_once_it_has_used_its_clicks() {
+        assert!(!link_expired(None, Some(3), 2, 0));
+        assert!(link_expired(None, Some(3), 3, 0));
+        assert!(link_expired(None, Some(3), 4, 0));
+    }
+
+    #[test]
+    fn either_limit_is_enough_to_expire_a_link() {
+        assert!(link_expired(Some(1_000), Some(100), 0, 2_000));
+        assert!(link_expired(Some(1_000), Some(100), 100, 0));
+    }
+
+    #[test]
+    fn expiry_in_the_past_or_zero_clicks_is_rejected() {
+        let mut req = request(None);
+        req.expires_at = Some(1_000);
+        assert!(matches!(req.validate_expiry(1_000), Err(AppError::Validation(_))));
+
+        let mut req = request(None);
+        req.max_clicks = Some(0);
+        assert!(matches!(req.validate_expiry(1_000), Err(AppError::Validation(_))));
+
+        let mut req = request(None);
+        req.expires_at = Some(1_001);
+        req.max_clicks = Some(1);
+        assert!(req.validate_expiry(1_000).is_ok());
+    }
+
+    #[test]
+    fn batch_results_carry_the_status_and_a_public_error() {
+        let ok: BatchItemResult = Ok(ShortUrl::created(
+            "abc1234".to_string(),
+            &request(None),
+            UrlDetails::default(),
+            0,
+        ))
+        .into();
+        let json = serde_json::to_value(&ok).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["link"]["link_id"], "abc1234");
+        assert!(json.get("error").is_none());
+
+        let failed: BatchItemResult = Err(AppError::Internal("linkTable-prod timed out".into())).into();
+        let json = serde_json::to_value(&failed).unwrap();
+        assert_eq!(json["status"], 500);
+        assert_eq!(json["error"], "Something went wrong");
+        assert!(json.get("link").is_none());
+    }
+
+    #[test]
+    fn new_link_items_store_what_was_asked_for() {
+        let mut req = request(None);
+        req.expires_at = Some(2_000);
+        req.max_clicks = Some(3);
+        req.password = Some("correct horse".to_string());
+        let details = UrlDetails { title: Some("Example".into()), ..Default::default() };
+
+        let item = new_link_item(TEST_SUB, "abc1234", "https://example.com/", &details, &req, 1_000);
+        assert_eq!(item["SortKey"], AttributeValue::S(owner_key(TEST_SUB)));
+        assert_eq!(item["OwnerId"], AttributeValue::S(TEST_SUB.into()));
+        assert_eq!(item["Clicks"], AttributeValue::N("0".into()));
+        assert_eq!(item["Title"], AttributeValue::S("Example".into()));
+        assert!(!item.contains_key("Description"));
+        assert_eq!(
+            item["PurgeAt"],
+            AttributeValue::N((2_000 + LINK_EXPIRY_GRACE_SECS).to_string())
+        );
+        assert_eq!(item["MaxClicks"], AttributeValue::N("3".into()));
+        // Only ever the salted hash.
+        let (AttributeValue::S(hash), AttributeValue::S(salt)) = (&item["PasswordHash"], &item["PasswordSalt"]) else {
+            panic!("password attributes should be strings");
+        };
+        assert!(crate::password::verify_password("correct horse", salt, hash));
+        assert!(!item.values().any(|v| *v == AttributeValue::S("correct horse".into())));
+    }
+
+    #[test]
+    fn click_history_appears_only_once_attached() {
+        let row: ShortUrlRow = serde_dynamo::from_item(stored_item(false)).unwrap();
+        let mut list = ListShortUrlResponse {
+            short_urls: vec![ShortUrl::from(row)],
+            cursor: None,
+            has_more: false,
+        };
+        let json = serde_json::to_value(&list).unwrap();
+        assert!(json["short_urls"][0].get("click_history").is_none());
+
+        let series = ClickSeries { days: 7, start: 0, bucket_secs: 3600, counts: vec![1, 2] };
+        list.attach_click_history(HashMap::from([("abc1234".to_string(), series)]));
+        let json = serde_json::to_value(&list).unwrap();
+        assert_eq!(json["short_urls"][0]["click_history"]["counts"], serde_json::json!([1, 2]));
+    }
+
+    #[test]
+    fn blank_password_means_no_password() {
+        let mut req = request(None);
+        req.password = Some(String::new());
+        assert!(req.validate_password().unwrap().password.is_none());
+
+        let mut req = request(None);
+        req.password = Some("abc".to_string());
+        assert!(matches!(req.validate_password(), Err(AppError::Validation(_))));
+    }
+
+    /// The listing says a link is protected without ever carrying the hash.
+    #[test]
+    fn listing_flags_protected_links_without_exposing_the_hash() {
+        let mut item = stored_item(false);
+        item.insert("PasswordHash".into(), AttributeValue::S("deadbeef".into()));
+        item.insert("PasswordSalt".into(), AttributeValue::S("cafe".into()));
+        let row: ShortUrlRow = serde_dynamo::from_item(item).unwrap();
+        let json = serde_json::to_string(&ShortUrl::from(row)).unwrap();
+        assert!(json.contains(r#""password_protected":true"#), "got {json}");
+        assert!(!json.contains("deadbeef") && !json.contains("cafe"), "got {json}");
+    }
+
+    #[test]
+    fn link_target_carries_the_password_hash_and_salt() {
+        let mut item = stored_item(false);
+        item.insert("PasswordHash".into(), AttributeValue::S("deadbeef".into()));
+        item.insert("PasswordSalt".into(), AttributeValue::S("cafe".into()));
+        let target: LinkTarget = serde_dynamo::from_item(item).unwrap();
+        assert_eq!(target.password_hash.as_deref(), Some("deadbeef"));
+        assert_eq!(target.password_salt.as_deref(), Some("cafe"));
+
+        let target: LinkTarget = serde_dynamo::from_item(stored_item(false)).unwrap();
+        assert!(target.password_hash.is_none());
+    }
+
+    #[test]
+    fn preview_mode_is_stored_only_when_asked_for() {
+        let mut req = request(None);
+        let item = new_link_item(TEST_SUB, "abc1234", "https://example.com/", &UrlDetails::default(), &req, 1_000);
+        assert!(!item.contains_key("Preview"));
+
+        req.preview = true;
+        let item = new_link_item(TEST_SUB, "abc1234", "https://example.com/", &UrlDetails::default(), &req, 1_000);
+        assert_eq!(item["Preview"], AttributeValue::Bool(true));
+        let target: LinkTarget = serde_dynamo::from_item(item).unwrap();
+        assert!(target.preview);
+        assert_eq!(destination_host(&target.original_link).as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn preview_accepts_a_checkbox_or_a_boolean() {
+        use serde::de::IntoDeserializer;
+        let from_form = |value: &str| {
+            deserialize_checkbox::<serde::de::value::StrDeserializer<serde::de::value::Error>>(value.into_deserializer())
+        };
+        assert_eq!(from_form("on"), Ok(true));
+        assert_eq!(from_form(""), Ok(false));
+        assert!(from_form("maybe").is_err());
+
+        let req: ShortenUrlRequest =
+            serde_json::from_value(serde_json::json!({ "url_to_shorten": "example.com", "preview": true })).unwrap();
+        assert!(req.preview);
+        let req: ShortenUrlRequest =
+            serde_json::from_value(serde_json::json!({ "url_to_shorten": "example.com" })).unwrap();
+        assert!(!req.preview);
+    }
+
+    #[test]
+    fn tags_accept_a_list_or_comma_separated_text() {
+        use serde::de::IntoDeserializer;
+        let from_json = |body| serde_json::from_value::<ShortenUrlRequest>(body).unwrap().tags;
+        let from_form = |value: &str| {
+            deserialize_tags::<serde::de::value::StrDeserializer<serde::de::value::Error>>(value.into_deserializer())
+                .unwrap()
+        };
+
+        let tags = Some(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(from_json(serde_json::json!({ "url_to_shorten": "example.com", "tags": ["a", "b"] })), tags);
+        assert_eq!(from_form("a, b,"), tags);
+        // Absent leaves an edited link's tags alone; an empty field or list clears them.
+        assert_eq!(from_json(serde_json::json!({ "url_to_shorten": "example.com" })), None);
+        assert_eq!(from_json(serde_json::json!({ "url_to_shorten": "example.com", "tags": [] })), Some(vec![]));
+        assert_eq!(from_form(""), Some(vec![]));
+    }
+
+    #[test]
+    fn tags_are_validated_stored_as_a_set_and_listed_sorted() {
+        let mut req = request(None);
+        req.tags = Some(vec!["Q3".to_string(), "launch".to_string(), "q3".to_string()]);
+        let req = req.validate_local("krtk.rs").unwrap();
+        assert_eq!(req.tags.as_deref(), Some(&["launch".to_string(), "q3".to_string()][..]));
+
+        let item = new_link_item(TEST_SUB, "abc1234", "https://example.com/", &UrlDetails::default(), &req, 1);
+        assert_eq!(item["Tags"], AttributeValue::Ss(vec!["launch".into(), "q3".into()]));
+        let untagged = new_link_item(TEST_SUB, "abc1234", "https://example.com/", &UrlDetails::default(), &request(None), 1);
+        assert!(!untagged.contains_key("Tags"));
+
+        let mut stored = stored_item(false);
+        stored.insert("Tags".into(), AttributeValue::Ss(vec!["q3".into(), "launch".into()]));
+        let row: ShortUrlRow = serde_dynamo::from_item(stored).unwrap();
+        assert_eq!(ShortUrl::from(row).tags, ["launch", "q3"]);
+
+        let mut bad = request(None);
+        bad.tags = Some(vec!["no spaces".to_string()]);
+        assert!(matches!(bad.validate_local("krtk.rs"), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn utm_parameters_become_part_of_the_destination_before_it_is_checked() {
+        let req: ShortenUrlRequest = serde_json::from_value(serde_json::json!({
+            "url_to_shorten": "example.com/spring?ref=home#top",
+            "utm": { "source": "newsletter", "medium": "email", "campaign": "spring sale" },
+        }))
+        .unwrap();
+        let req = req.validate_local("krtk.rs").unwrap();
+        assert_eq!(
+            req.url_to_shorten,
+            "https://example.com/spring?ref=home&utm_source=newsletter&utm_medium=email&utm_campaign=spring+sale#top"
+        );
+        assert_eq!(normalize_url(&req.url_to_shorten), req.url_to_shorten);
+
+        // Merged first, so a campaign link to ourselves is refused like any other.
+        let mut recursive = request(None);
+        recursive.url_to_shorten = "krtk.rs/abc1234".to_string();
+        recursive.utm = Some(Utm { source: Some("x".to_string()), ..Default::default() });
+        assert!(matches!(recursive.validate_local("krtk.rs"), Err(AppError::Validation(_))));
+
+        let unknown = serde_json::json!({ "url_to_shorten": "example.com", "utm": { "sauce": "x" } });
+        assert!(serde_json::from_value::<ShortenUrlRequest>(unknown).is_err());
+    }
+
+    #[test]
+    fn listing_reports_expiry_state_from_the_stored_limits() {
+        let mut item = stored_item(false);
+        item.insert("MaxClicks".into(), AttributeValue::N("42".into()));
+        let row: ShortUrlRow = serde_dynamo::from_item(item).unwrap();
+        let url = ShortUrl::from(row);
+        assert_eq!(url.max_clicks, Some(42));
+        assert!(url.expired, "42 clicks of a 42-click link is used up");
+
+        let row: ShortUrlRow = serde_dynamo::from_item(stored_item(false)).unwrap();
+        assert!(!ShortUrl::from(row).expired);
+    }
+
+    /// Resolution must keep working for every item ever written, so the target asks for
+    /// nothing beyond `OriginalLink`.
+    #[test]
+    fn link_target_deserializes_an_item_with_only_a_destination() {
+        let mut item = HashMap::new();
+        item.insert("LinkId".to_string(), AttributeValue::S("abc1234".into()));
+        item.insert("OriginalLink".to_string(), AttributeValue::S("https://example.com/".into()));
+
+        let target: LinkTarget = serde_dynamo::from_item(item).unwrap();
+        assert_eq!(target.original_link, "https://example.com/");
+        assert!(!target.is_expired(i64::MAX));
+    }
+
     #[test]
     fn normalize_url_defaults_to_https_and_preserves_explicit_schemes() {
         assert_eq!(normalize_url("example.com"), "https://example.com");
@@ -640,6 +1913,599 @@ mod tests {
         assert!(!is_valid_url(""));
     }
 
+    fn request(custom_slug: Option<&str>) -> ShortenUrlRequest {
+        ShortenUrlRequest {
+            url_to_shorten: "https://example.com/".to_string(),
+            custom_slug: custom_slug.map(str::to_string),
+            expires_at: None,
+            max_clicks: None,
+            password: None,
+            preview: false,
+            tags: None,
+            utm: None,
+            rules: None,
+        }
+    }
+
+    #[test]
+    fn custom_slug_accepts_the_allowed_charset() {
+        assert!(check_custom_slug("launch-2026").is_ok());
+        assert!(check_custom_slug("Q3_Report").is_ok());
+        assert!(check_custom_slug("abc").is_ok());
+        assert!(check_custom_slug(&"a".repeat(CUSTOM_SLUG_MAX_LEN)).is_ok());
+    }
+
+    #[test]
+    fn custom_slug_is_length_bounded() {
+        assert!(matches!(check_custom_slug("ab"), Err(AppError::Validation(_))));
+        assert!(matches!(
+            check_custom_slug(&"a".repeat(CUSTOM_SLUG_MAX_LEN + 1)),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn custom_slug_rejects_characters_outside_the_charset() {
+        for slug in ["has space", "a/b/c", "index.html", "caf\u{e9}", "q?x=1", "50%off"] {
+            assert!(
+                matches!(check_custom_slug(slug), Err(AppError::Validation(_))),
+                "{slug:?} should have been rejected"
+            );
+        }
+    }
+
+    /// A reserved slug would be written successfully and then never resolve, because
+    /// CloudFront sends its path somewhere other than visit_link.
+    #[test]
+    fn custom_slug_rejects_reserved_words_in_any_case() {
+        for slug in ["api", "auth", "assets", "API", "Assets", "terms", "privacy"] {
+            assert!(
+                matches!(check_custom_slug(slug), Err(AppError::Validation(_))),
+                "{slug:?} should be reserved"
+            );
+        }
+    }
+
+    /// The form submits `custom_slug=` when the box is left empty, which is the common
+    /// case, so blank must mean "generate one" rather than "invalid slug".
+    #[test]
+    fn blank_custom_slug_is_treated_as_absent() {
+        let req = request(Some("   ")).validate_custom_slug().unwrap();
+        assert!(req.custom_slug.is_none());
+
+        let req = request(None).validate_custom_slug().unwrap();
+        assert!(req.custom_slug.is_none());
+    }
+
+    #[test]
+    fn custom_slug_is_trimmed_before_it_is_checked() {
+        let req = request(Some(" launch ")).validate_custom_slug().unwrap();
+        assert_eq!(req.custom_slug.as_deref(), Some("launch"));
+    }
+
+    #[test]
+    fn custom_slug_is_optional_on_the_wire() {
+        let req: ShortenUrlRequest =
+            serde_json::from_str(r#"{"url_to_shorten":"https://example.com/"}"#).unwrap();
+        assert!(req.custom_slug.is_none());
+
+        let req: ShortenUrlRequest = serde_json::from_str(
+            r#"{"url_to_shorten":"https://example.com/","custom_slug":"launch"}"#,
+        )
+        .unwrap();
+        assert_eq!(req.custom_slug.as_deref(), Some("launch"));
+    }
+
+    // --- UrlShortener over the in-memory store ---
+
+    /// Scrapes go to a closed loopback port, so they fail at once and no test here leaves
+    /// the machine. A failed scrape is just a link without metadata.
+    const OFFLINE_URL: &str = "http://127.0.0.1:9/page";
+    const OTHER_SUB: &str = "cognito-sub-456";
+
+    fn shortener() -> UrlShortener<InMemoryLinkStore> {
+        UrlShortener::with_store(InMemoryLinkStore::default(), "krtk.rs")
+    }
+
+    fn offline_url_info() -> UrlInfo {
+        UrlInfo::new(reqwest::Client::builder().no_proxy().build().unwrap())
+    }
+
+    fn offline_request(custom_slug: Option<&str>) -> ShortenUrlRequest {
+        ShortenUrlRequest {
+            url_to_shorten: OFFLINE_URL.to_string(),
+            ..request(custom_slug)
+        }
+    }
+
+    /// The old fixed page size, which the paging tests were written around.
+    const PAGE: PageSize = PageSize(5);
+    const CURSOR_KEY: &[u8] = b"cursor-test-key";
+
+    async fn seed<S: LinkStore>(shortener: &UrlShortener<S>, owner_sub: &str, link_id: &str, timestamp: i64) {
+        let details = UrlDetails { title: Some("Seeded".into()), ..Default::default() };
+        let item = new_link_item(owner_sub, link_id, "https://example.com/", &details, &request(None), timestamp);
+        assert!(shortener.store.insert(item).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn a_new_link_resolves_and_is_listed_for_its_owner_only() {
+        let shortener = shortener();
+        let link = shortener
+            .shorten_url(offline_request(Some("launch")), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert_eq!(link.link_id, "launch");
+
+        let target = shortener.retrieve_url("launch").await.unwrap().unwrap();
+        assert_eq!(target.original_link, OFFLINE_URL);
+        let mine = shortener.list_urls(TEST_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(mine.link_ids(), ["launch"]);
+        let theirs = shortener.list_urls(OTHER_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert!(theirs.short_urls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_taken_custom_slug_is_a_conflict_and_keeps_the_original() {
+        let shortener = shortener();
+        seed(&shortener, OTHER_SUB, "launch", 1).await;
+
+        let result = shortener
+            .shorten_url(offline_request(Some("launch")), &offline_url_info(), TEST_SUB)
+            .await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+        let target = shortener.retrieve_url("launch").await.unwrap().unwrap();
+        assert_eq!(target.original_link, "https://example.com/");
+    }
+
+    #[tokio::test]
+    async fn listing_pages_newest_first_and_resumes_after_the_last_key() {
+        let shortener = shortener();
+        for n in 1..=7 {
+            seed(&shortener, TEST_SUB, &format!("link{n}"), n).await;
+        }
+        // Newer than all of the above, and in another partition.
+        seed(&shortener, OTHER_SUB, "theirs", 100).await;
+
+        let first = shortener.list_urls(TEST_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(first.link_ids(), ["link7", "link6", "link5", "link4", "link3"]);
+        assert!(first.has_more);
+
+        let second = shortener
+            .list_urls(TEST_SUB, first.cursor.as_deref(), PAGE, CURSOR_KEY)
+            .await
+            .unwrap();
+        assert_eq!(second.link_ids(), ["link2", "link1"]);
+        assert!(!second.has_more);
+        assert!(second.cursor.is_none());
+    }
+
+    /// DynamoDB cannot know a full page was the last one, so the table's "load more"
+    /// row has to cope with an empty page after it.
+    #[tokio::test]
+    async fn a_full_last_page_is_followed_by_an_empty_one() {
+        let shortener = shortener();
+        for n in 1..=PAGE.get() {
+            seed(&shortener, TEST_SUB, &format!("link{n}"), i64::from(n)).await;
+        }
+
+        let first = shortener.list_urls(TEST_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert!(first.has_more);
+        let second = shortener
+            .list_urls(TEST_SUB, first.cursor.as_deref(), PAGE, CURSOR_KEY)
+            .await
+            .unwrap();
+        assert!(second.short_urls.is_empty());
+        assert!(!second.has_more);
+    }
+
+    fn filter(params: &[(&str, &str)]) -> LinkFilter {
+        LinkFilter::from_params(|name| params.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)).unwrap()
+    }
+
+    #[test]
+    fn a_blank_filter_form_is_the_plain_listing() {
+        let blank = filter(&[("q", " "), ("from", ""), ("to", ""), ("min_clicks", ""), ("tag", ""), ("sort", "newest")]);
+        assert!(blank.is_unfiltered());
+        assert_eq!(blank.query_string(), "");
+    }
+
+    #[test]
+    fn filter_dates_cover_whole_days_and_round_trip_as_timestamps() {
+        let f = filter(&[("q", "Spring Sale"), ("from", "2025-03-01"), ("to", "2025-03-01"), ("tag", "Clients/Acme"), ("sort", "clicks")]);
+        assert_eq!(f.since, Some(1_740_787_200));
+        assert_eq!(f.until, Some(1_740_787_200 + DAY_SECS - 1));
+        assert_eq!(
+            f.query_string(),
+            "&q=spring+sale&from=1740787200&to=1740873599&tag=clients%2Facme&sort=clicks"
+        );
+
+        let query = f.query_string();
+        let pairs: Vec<(String, String)> = url::form_urlencoded::parse(&query.as_bytes()[1..]).into_owned().collect();
+        let again = LinkFilter::from_params(|name| pairs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())).unwrap();
+        assert_eq!(again, f);
+
+        let bad = LinkFilter::from_params(|name| (name == "to").then_some("last spring"));
+        assert!(matches!(bad, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn search_matches_text_case_insensitively_and_stays_with_the_owner() {
+        let shortener = shortener();
+        seed(&shortener, TEST_SUB, "mine", 1).await;
+        seed(&shortener, OTHER_SUB, "theirs", 2).await;
+
+        // `seed` titles every link "Seeded".
+        let found = shortener.search_urls(TEST_SUB, &filter(&[("q", "SEEDED")]), None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(found.link_ids(), ["mine"]);
+        let by_slug = shortener.search_urls(TEST_SUB, &filter(&[("q", "min")]), None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(by_slug.link_ids(), ["mine"]);
+        let none = shortener.search_urls(TEST_SUB, &filter(&[("q", "nothing")]), None, PAGE, CURSOR_KEY).await.unwrap();
+        assert!(none.short_urls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_sorted_by_clicks_pages_without_repeats() {
+        let shortener = shortener();
+        for n in 1..=7 {
+            let link_id = format!("link{n}");
+            seed(&shortener, TEST_SUB, &link_id, i64::from(n)).await;
+            // Two links per click count, so ties have to be broken consistently.
+            shortener.store.add_clicks(&link_id, n / 2, 0).await.unwrap();
+        }
+        let by_clicks = filter(&[("sort", "clicks")]);
+
+        let first = shortener.search_urls(TEST_SUB, &by_clicks, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(first.link_ids(), ["link7", "link6", "link5", "link4", "link3"]);
+        assert!(first.has_more);
+
+        let second = shortener
+            .search_urls(TEST_SUB, &by_clicks, first.cursor.as_deref(), PAGE, CURSOR_KEY)
+            .await
+            .unwrap();
+        assert_eq!(second.link_ids(), ["link2", "link1"]);
+        assert!(!second.has_more);
+        assert!(second.cursor.is_none());
+
+        // The position is in clicks order, which means nothing to the newest-first listing.
+        let mixed = shortener.list_urls(TEST_SUB, first.cursor.as_deref(), PAGE, CURSOR_KEY).await;
+        assert!(matches!(mixed, Err(AppError::Validation(_))));
+    }
+
+    /// The page a cursor resumes is the one it was minted for, or none at all.
+    #[tokio::test]
+    async fn listing_refuses_a_cursor_minted_for_another_owner() {
+        let shortener = shortener();
+        for n in 1..=7 {
+            seed(&shortener, OTHER_SUB, &format!("link{n}"), n).await;
+        }
+        let theirs = shortener.list_urls(OTHER_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+
+        let page = shortener.list_urls(TEST_SUB, theirs.cursor.as_deref(), PAGE, CURSOR_KEY).await;
+        assert!(matches!(page, Err(AppError::Validation(_))));
+        let page = shortener.list_urls(OTHER_SUB, theirs.cursor.as_deref(), PAGE, b"another key").await;
+        assert!(matches!(page, Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn page_size_is_bounded() {
+        assert_eq!(PageSize::from_param(None).unwrap(), PageSize::default());
+        assert_eq!(PageSize::from_param(Some("")).unwrap(), PageSize::default());
+        assert_eq!(PageSize::from_param(Some("100")).unwrap().get(), 100);
+        for bad in ["0", "101", "-1", "ten"] {
+            assert!(matches!(PageSize::from_param(Some(bad)), Err(AppError::Validation(_))), "{bad}");
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_delete_or_repoint_a_link() {
+        let shortener = shortener();
+        seed(&shortener, TEST_SUB, "abc1234", 1).await;
+
+        assert!(matches!(shortener.delete_url("abc1234", OTHER_SUB).await, Err(AppError::Forbidden)));
+        let repoint = shortener
+            .update_destination("abc1234", offline_request(None), &offline_url_info(), OTHER_SUB)
+            .await;
+        assert!(matches!(repoint, Err(AppError::Forbidden)));
+        // Indistinguishable from someone else's link, so ids cannot be probed.
+        assert!(matches!(shortener.delete_url("missing", TEST_SUB).await, Err(AppError::Forbidden)));
+
+        let target = shortener.retrieve_url("abc1234").await.unwrap().unwrap();
+        assert_eq!(target.original_link, "https://example.com/");
+        shortener.delete_url("abc1234", TEST_SUB).await.unwrap();
+        assert!(shortener.retrieve_url("abc1234").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn repointing_a_link_drops_the_old_pages_metadata() {
+        let shortener = shortener();
+        seed(&shortener, TEST_SUB, "abc1234", 1).await;
+
+        let link = shortener
+            .update_destination("abc1234", offline_request(None), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert_eq!(link.original_link, OFFLINE_URL);
+        assert!(link.title.is_none());
+    }
+
+    #[tokio::test]
+    async fn an_edit_replaces_tags_only_when_it_carries_them() {
+        let shortener = shortener();
+        seed(&shortener, TEST_SUB, "abc1234", 1).await;
+        let edit = |tags: Option<&[&str]>| ShortenUrlRequest {
+            tags: tags.map(|tags| tags.iter().map(|tag| tag.to_string()).collect()),
+            ..offline_request(None)
+        };
+
+        let link = shortener
+            .update_destination("abc1234", edit(Some(&["launch"])), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert_eq!(link.tags, ["launch"]);
+        let link = shortener
+            .update_destination("abc1234", edit(None), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert_eq!(link.tags, ["launch"]);
+        let link = shortener
+            .update_destination("abc1234", edit(Some(&[])), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert!(link.tags.is_empty());
+    }
+
+    fn with_rules(rules: serde_json::Value) -> ShortenUrlRequest {
+        serde_json::from_value(serde_json::json!({ "url_to_shorten": "example.com", "rules": rules })).unwrap()
+    }
+
+    #[test]
+    fn redirect_rules_are_validated_like_the_destination_and_kept_in_order() {
+        let req = with_rules(serde_json::json!([
+            { "device": "ios", "destination": "apps.apple.com/app/id1" },
+            { "country": "de", "destination": "https://example.com/de" },
+        ]))
+        .validate_local("krtk.rs")
+        .unwrap();
+        let item = new_link_item(TEST_SUB, "abc1234", "https://example.com/", &UrlDetails::default(), &req, 1);
+        let target: LinkTarget = serde_dynamo::from_item(item).unwrap();
+        let destinations: Vec<(&str, Option<&str>)> = target
+            .rules
+            .iter()
+            .map(|rule| (rule.destination.as_str(), rule.country.as_deref()))
+            .collect();
+        assert_eq!(destinations, [("https://apps.apple.com/app/id1", None), ("https://example.com/de", Some("DE"))]);
+        assert_eq!(
+            target.destination_for(&Visitor::from_headers(None, Some("DE"))),
+            "https://example.com/de"
+        );
+        assert_eq!(target.destination_for(&Visitor::default()), "https://example.com/");
+
+        for bad in [
+            serde_json::json!([{ "device": "ios", "destination": "not a url" }]),
+            serde_json::json!([{ "device": "ios", "destination": "krtk.rs/abc1234" }]),
+            serde_json::json!([{ "destination": "https://example.com/" }]),
+        ] {
+            assert!(matches!(with_rules(bad).validate_local("krtk.rs"), Err(AppError::Validation(_))));
+        }
+        let untouched = new_link_item(TEST_SUB, "abc1234", "https://example.com/", &UrlDetails::default(), &request(None), 1);
+        assert!(!untouched.contains_key("Rules"));
+    }
+
+    #[tokio::test]
+    async fn a_flagged_rule_destination_fails_the_reputation_check() {
+        let blocking = ReputationPolicy::new().with_provider(
+            crate::reputation::DomainList::new(["evil.example".to_string()], []),
+            crate::reputation::FailureMode::Open,
+        );
+        let req = with_rules(serde_json::json!([{ "device": "android", "destination": "https://evil.example/app" }]));
+        assert!(req.validate("krtk.rs", &blocking).await.is_err());
+
+        let batch = ShortenUrlRequest::validate_batch(
+            vec![request(None), with_rules(serde_json::json!([{ "country": "DE", "destination": "evil.example" }]))],
+            "krtk.rs",
+            &blocking,
+        )
+        .await;
+        assert!(batch[0].is_ok());
+        assert!(batch[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn an_edit_replaces_rules_only_when_it_carries_them() {
+        let shortener = shortener();
+        seed(&shortener, TEST_SUB, "abc1234", 1).await;
+        let edit = |rules: Option<serde_json::Value>| ShortenUrlRequest {
+            rules: rules.map(|rules| serde_json::from_value(rules).unwrap()),
+            ..offline_request(None)
+        };
+
+        let ios = serde_json::json!([{ "device": "ios", "destination": "https://apps.apple.com/app/id1" }]);
+        let link = shortener
+            .update_destination("abc1234", edit(Some(ios)), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert_eq!(link.rules.len(), 1);
+        let link = shortener
+            .update_destination("abc1234", edit(None), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert_eq!(link.rules.len(), 1);
+        let link = shortener
+            .update_destination("abc1234", edit(Some(serde_json::json!([]))), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert!(link.rules.is_empty());
+
+        let edit = edit(Some(serde_json::json!([{ "country": "DE", "destination": "https://example.com/de" }])));
+        assert!(matches!(
+            shortener.update_destination("abc1234", edit, &offline_url_info(), OTHER_SUB).await,
+            Err(AppError::Forbidden)
+        ));
+    }
+
+    /// The rescan may have disabled the link for one of its rules; a new main URL says
+    /// nothing about those.
+    #[tokio::test]
+    async fn an_edit_that_keeps_the_rules_keeps_the_link_disabled() {
+        let shortener = shortener();
+        seed(&shortener, TEST_SUB, "abc1234", 1).await;
+        let edit = |rules: Option<serde_json::Value>| ShortenUrlRequest {
+            rules: rules.map(|rules| serde_json::from_value(rules).unwrap()),
+            ..offline_request(None)
+        };
+        let flagged = serde_json::json!([{ "device": "ios", "destination": "https://evil.example/app" }]);
+        shortener
+            .update_destination("abc1234", edit(Some(flagged)), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        shortener.disable_link("abc1234", "the domain blocklist").await.unwrap();
+
+        shortener
+            .update_destination("abc1234", edit(None), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert!(shortener.retrieve_url("abc1234").await.unwrap().unwrap().is_disabled());
+
+        shortener
+            .update_destination("abc1234", edit(Some(serde_json::json!([]))), &offline_url_info(), TEST_SUB)
+            .await
+            .unwrap();
+        assert!(!shortener.retrieve_url("abc1234").await.unwrap().unwrap().is_disabled());
+    }
+
+    #[tokio::test]
+    async fn retagging_renames_or_removes_a_tag_on_the_owners_links() {
+        let shortener = shortener();
+        for link_id in ["one", "two", "theirs"] {
+            let owner = if link_id == "theirs" { OTHER_SUB } else { TEST_SUB };
+            let tagged = ShortenUrlRequest { tags: Some(vec!["draft".to_string(), "q3".to_string()]), ..request(None) };
+            let item = new_link_item(owner, link_id, "https://example.com/", &UrlDetails::default(), &tagged, 1);
+            assert!(shortener.store.insert(item).await.unwrap());
+        }
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        // A stale index entry for someone else's link, or for one that is gone, is skipped.
+        let renamed = shortener
+            .retag(TEST_SUB, &ids(&["one", "theirs", "gone"]), "draft", Some("q3"))
+            .await
+            .unwrap();
+        assert_eq!(renamed, 1);
+        let tagged = shortener.search_urls(TEST_SUB, &filter(&[("tag", "q3")]), None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(tagged.link_ids(), ["two", "one"]);
+        assert_eq!(tagged.short_urls[1].tags, ["q3"], "renaming onto an existing tag merges the two");
+
+        let removed = shortener.retag(TEST_SUB, &ids(&["one", "two"]), "q3", None).await.unwrap();
+        assert_eq!(removed, 2);
+        let tagged = shortener.search_urls(TEST_SUB, &filter(&[("tag", "Q3")]), None, PAGE, CURSOR_KEY).await.unwrap();
+        assert!(tagged.short_urls.is_empty());
+        let theirs: ShortUrlRow = serde_dynamo::from_item(shortener.store.get("theirs").await.unwrap().unwrap()).unwrap();
+        assert_eq!(theirs.tags.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn clicks_accumulate_and_unknown_links_are_not_found() {
+        let shortener = shortener();
+        seed(&shortener, TEST_SUB, "abc1234", 1).await;
+
+        shortener.add_clicks("abc1234", 2, 1).await.unwrap();
+        shortener.add_clicks("abc1234", 3, 0).await.unwrap();
+        let page = shortener.list_urls(TEST_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(page.short_urls[0].clicks, 5);
+        assert_eq!(page.short_urls[0].bot_clicks, 1);
+
+        assert!(matches!(
+            shortener.add_clicks("missing", 1, 0).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(shortener.retrieve_url("missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn a_batch_reports_each_entry_in_place() {
+        let shortener = shortener();
+        let reqs = vec![
+            Ok(offline_request(None)),
+            Err(AppError::Validation("Invalid URL Provided".to_string())),
+            Ok(offline_request(None)),
+        ];
+
+        let results = shortener.shorten_urls(reqs, &offline_url_info(), TEST_SUB).await;
+        assert!(matches!(results[1], Err(AppError::Validation(_))));
+        let (Ok(first), Ok(third)) = (&results[0], &results[2]) else {
+            panic!("both valid entries should have been created: {results:?}");
+        };
+        assert_ne!(first.link_id, third.link_id);
+        let page = shortener.list_urls(TEST_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert_eq!(page.short_urls.len(), 2);
+    }
+
+    /// A store in which every id is already taken: the collision paths a real table
+    /// almost never reaches.
+    #[derive(Default)]
+    struct FullStore(InMemoryLinkStore);
+
+    impl LinkStore for FullStore {
+        async fn insert(&self, _item: Item) -> Result<bool, AppError> {
+            Ok(false)
+        }
+        async fn taken_ids(&self, ids: &HashSet<String>) -> Result<HashSet<String>, AppError> {
+            Ok(ids.clone())
+        }
+        async fn insert_all(&self, items: Vec<Item>) -> Vec<(String, Result<(), AppError>)> {
+            self.0.insert_all(items).await
+        }
+        async fn get(&self, link_id: &str) -> Result<Option<Item>, AppError> {
+            self.0.get(link_id).await
+        }
+        async fn edit(&self, link_id: &str, owner_sub: &str, edit: &LinkEdit<'_>) -> Result<Item, AppError> {
+            self.0.edit(link_id, owner_sub, edit).await
+        }
+        async fn retag(&self, link_id: &str, owner_sub: &str, from: &str, to: Option<&str>) -> Result<bool, AppError> {
+            self.0.retag(link_id, owner_sub, from, to).await
+        }
+        async fn delete(&self, link_id: &str, owner_sub: &str) -> Result<(), AppError> {
+            self.0.delete(link_id, owner_sub).await
+        }
+        async fn add_clicks(&self, link_id: &str, clicks: u32, bot_clicks: u32) -> Result<(), AppError> {
+            self.0.add_clicks(link_id, clicks, bot_clicks).await
+        }
+        async fn list(&self, owner_sub: &str, start: Option<PageKey>, limit: i32) -> Result<ItemPage, AppError> {
+            self.0.list(owner_sub, start, limit).await
+        }
+        async fn search(&self, owner_sub: &str, since: Option<i64>, until: Option<i64>, min_clicks: u32, tag: Option<&str>) -> Result<Vec<Item>, AppError> {
+            self.0.search(owner_sub, since, until, min_clicks, tag).await
+        }
+        async fn scan(&self, segment: i32, total_segments: i32, start: Option<String>, limit: i32) -> Result<ScanPage, AppError> {
+            self.0.scan(segment, total_segments, start, limit).await
+        }
+        async fn disable(&self, link_id: &str, reason: &str, at: i64) -> Result<(), AppError> {
+            self.0.disable(link_id, reason, at).await
+        }
+    }
+
+    /// Unlike a taken custom slug, a generated id colliding is not the caller's doing:
+    /// the same request will very likely succeed if sent again.
+    #[tokio::test]
+    async fn a_generated_id_collision_asks_for_a_retry() {
+        let shortener = UrlShortener::with_store(FullStore::default(), "krtk.rs");
+        let result = shortener
+            .shorten_url(offline_request(None), &offline_url_info(), TEST_SUB)
+            .await;
+        assert!(matches!(result, Err(AppError::Validation(message)) if message.contains("try again")));
+    }
+
+    #[tokio::test]
+    async fn a_batch_that_cannot_find_free_ids_writes_nothing() {
+        let shortener = UrlShortener::with_store(FullStore::default(), "krtk.rs");
+        let results = shortener
+            .shorten_urls(vec![Ok(offline_request(None))], &offline_url_info(), TEST_SUB)
+            .await;
+        assert!(matches!(results[0], Err(AppError::Internal(_))));
+        let page = shortener.list_urls(TEST_SUB, None, PAGE, CURSOR_KEY).await.unwrap();
+        assert!(page.short_urls.is_empty());
+    }
+
     #[test]
     fn is_recursive_url_catches_our_own_domain() {
         assert!(is_recursive_url("https://krtk.rs/abc1234", "krtk.rs"));
@@ -0,0 +1,119 @@
+//! Opaque, signed pagination cursors for the link list.
+//!
+//! A page used to resume from `last_evaluated_id`/`last_evaluated_timestamp` query
+//! parameters, which went straight into DynamoDB's `ExclusiveStartKey`: the client
+//! could hand back any key it liked. A cursor is the same position, signed, and it
+//! names the owner partition it was minted for, so one that was edited or minted for
+//! somebody else is refused before it reaches the store.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::core::owner_key;
+use crate::error::AppError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Where a listing page stopped.
+///
+/// Field names are kept to a letter: the cursor travels in every "load more" URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageCursor {
+    /// The owner partition (see [`owner_key`]) the cursor was minted for.
+    #[serde(rename = "p")]
+    partition: String,
+    #[serde(rename = "i")]
+    pub link_id: String,
+    #[serde(rename = "t")]
+    pub timestamp: i64,
+    /// Only in a cursor from a search sorted by clicks, where the timestamp alone does
+    /// not say where the page stopped.
+    #[serde(rename = "c", default, skip_serializing_if = "Option::is_none")]
+    pub clicks: Option<u32>,
+}
+
+impl PageCursor {
+    pub fn new(owner_sub: &str, link_id: &str, timestamp: i64, clicks: Option<u32>) -> Self {
+        Self {
+            partition: owner_key(owner_sub),
+            link_id: link_id.to_string(),
+            timestamp,
+            clicks,
+        }
+    }
+
+    /// `payload.signature`, both base64url, safe in a query string as is.
+    pub fn sign(&self, key: &[u8]) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(self).expect("a cursor always serializes"),
+        );
+        let signature = URL_SAFE_NO_PAD.encode(mac(key, &payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// The cursor in `token`, if `key` signed it and it was minted for `owner_sub`.
+    ///
+    /// Every way of failing gives the same [`AppError::Validation`], so a caller learns
+    /// nothing from probing with cursors of its own making.
+    pub fn verify(token: &str, key: &[u8], owner_sub: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::Validation("Invalid pagination cursor".to_string());
+
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        // Constant-time, via `verify_slice`.
+        mac(key, payload).verify_slice(&signature).map_err(|_| invalid())?;
+
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let cursor: Self = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+        if cursor.partition != owner_key(owner_sub) {
+            return Err(invalid());
+        }
+        Ok(cursor)
+    }
+}
+
+fn mac(key: &[u8], payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"cursor-test-key";
+
+    #[test]
+    fn a_signed_cursor_round_trips_for_its_owner() {
+        let cursor = PageCursor::new("owner-a", "abc1234", 1_739_035_776, Some(3));
+        let token = cursor.sign(KEY);
+        assert!(!token.contains(['+', '/', '=', '&']), "must not need escaping: {token}");
+        assert_eq!(PageCursor::verify(&token, KEY, "owner-a").unwrap(), cursor);
+    }
+
+    #[test]
+    fn a_cursor_minted_for_another_owner_is_refused() {
+        let token = PageCursor::new("owner-a", "abc1234", 1, None).sign(KEY);
+        assert!(matches!(PageCursor::verify(&token, KEY, "owner-b"), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn an_edited_or_foreign_cursor_is_refused() {
+        let token = PageCursor::new("owner-a", "abc1234", 1, None).sign(KEY);
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = URL_SAFE_NO_PAD.encode(br#"{"p":"USER#owner-a","i":"zzz","t":9}"#);
+
+        for bad in [
+            format!("{forged}.{signature}"),
+            token.replace('.', ""),
+            "not-a-cursor".to_string(),
+        ] {
+            assert!(PageCursor::verify(&bad, KEY, "owner-a").is_err(), "accepted {bad}");
+        }
+        assert!(PageCursor::verify(&token, b"another key", "owner-a").is_err());
+    }
+}
@@ -40,6 +40,19 @@ pub enum AppError {
     /// would let a caller probe for other users' link IDs.
     #[error("Not permitted")]
     Forbidden,
+
+    /// The write was well-formed but collides with something that already exists.
+    ///
+    /// Distinct from `Validation` because the caller cannot fix it by correcting the
+    /// input's shape -- a taken vanity slug is a perfectly valid slug -- and a 409 lets
+    /// a script tell "pick another name" apart from "your request is malformed".
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// A dependency we refuse to go without is down, so the request cannot be served
+    /// right now. A 503 tells a script to retry later rather than fix its input.
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
 }
 
 impl AppError {
@@ -49,10 +62,26 @@ impl AppError {
             Self::NotFound(_) => StatusCode::NOT_FOUND,
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
+    /// The message it is safe to show a caller.
+    ///
+    /// Client errors (4xx) keep their own message, because the caller can act on it.
+    /// Server errors get a fixed generic one: variants like `Internal(String)`
+    /// interpolate their argument, so echoing them would eventually leak a table name,
+    /// an ARN, or an SDK error chain. The real error belongs in CloudWatch.
+    pub fn public_message(&self) -> String {
+        if self.status_code().is_server_error() {
+            "Something went wrong".to_string()
+        } else {
+            self.to_string()
+        }
+    }
+
     pub fn database<E: std::error::Error + Send + Sync + 'static>(err: E) -> Self {
         Self::Database(Box::new(err))
     }
@@ -72,6 +101,22 @@ mod tests {
         assert_eq!(AppError::Forbidden.status_code(), StatusCode::FORBIDDEN);
     }
 
+    #[test]
+    fn conflict_maps_to_409() {
+        assert_eq!(
+            AppError::Conflict("taken".into()).status_code(),
+            StatusCode::CONFLICT
+        );
+    }
+
+    #[test]
+    fn unavailable_maps_to_503() {
+        assert_eq!(
+            AppError::Unavailable("down".into()).status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
     #[test]
     fn existing_mappings_unchanged() {
         assert_eq!(
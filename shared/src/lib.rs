@@ -5,5 +5,16 @@ pub mod response;
 pub mod url_info;
 pub mod templates;
 pub mod safe_browsing;
+pub mod reputation;
+pub mod secrets;
+pub mod password;
+pub mod clicks;
+pub mod audit;
+pub mod store;
+pub mod cursor;
+pub mod tags;
+pub mod utm;
+pub mod rules;
+pub mod collection;
 
 pub use reqwest::Client;
@@ -0,0 +1,221 @@
+//! Passphrases on short links, and the cookie that remembers a correct one.
+//!
+//! A passphrase is stored the way `manage_keys` stores API keys -- only as a SHA-256
+//! hash -- with one difference: it is salted. An API key is 32 random bytes, so an
+//! unsalted hash of it cannot be reversed; a passphrase is chosen by a person, and
+//! without a per-link salt one precomputed table would open every link that shares it.
+//!
+//! Once a visitor has typed the right passphrase, `visit_link` hands back a cookie
+//! carrying an expiry and an HMAC over (link id, expiry, stored hash). The cookie holds
+//! nothing secret and needs no server-side session: it is trusted only because only the
+//! holder of the signing key could have produced the signature.
+
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::TryRngCore;
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SALT_BYTES: usize = 16;
+
+// Bounds on a link passphrase. The floor stops a one-character "password" that only
+// looks like protection; the ceiling keeps the hashed input bounded.
+pub const PASSWORD_MIN_LEN: usize = 6;
+pub const PASSWORD_MAX_LEN: usize = 128;
+
+/// How long a correct passphrase is remembered for. Short on purpose: the cookie is
+/// per-browser and cannot be revoked, so it should outlive the visit and not much more.
+pub const ACCESS_COOKIE_TTL_SECS: i64 = 60 * 60;
+
+pub fn check_password(password: &str) -> Result<(), AppError> {
+    let len = password.chars().count();
+    if !(PASSWORD_MIN_LEN..=PASSWORD_MAX_LEN).contains(&len) {
+        return Err(AppError::Validation(format!(
+            "Password must be between {PASSWORD_MIN_LEN} and {PASSWORD_MAX_LEN} characters"
+        )));
+    }
+    Ok(())
+}
+
+/// A fresh random salt, hex-encoded so it stores as a plain DynamoDB string.
+pub fn generate_salt() -> String {
+    let mut buf = [0u8; SALT_BYTES];
+    OsRng.try_fill_bytes(&mut buf).expect("OS RNG failed");
+    hex::encode(buf)
+}
+
+/// Hex SHA-256 of the salt followed by the passphrase.
+pub fn hash_password(password: &str, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(password.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Whether `candidate` is the passphrase behind `stored_hash`.
+///
+/// The comparison runs over every byte regardless of where the first difference is, so
+/// response timing does not tell a guesser how much of the hash they matched.
+pub fn verify_password(candidate: &str, salt: &str, stored_hash: &str) -> bool {
+    let computed = hash_password(candidate, salt);
+    computed.len() == stored_hash.len()
+        && computed
+            .bytes()
+            .zip(stored_hash.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// One cookie per link, so unlocking one link does not unlock another.
+pub fn access_cookie_name(link_id: &str) -> String {
+    format!("krtk_unlock_{link_id}")
+}
+
+/// The full `Set-Cookie` value that remembers a correct passphrase until `now` plus
+/// [`ACCESS_COOKIE_TTL_SECS`].
+///
+/// The stored hash is part of what is signed, so a cookie stops working if the link's
+/// passphrase is ever replaced, without anyone having to track which cookies were issued.
+pub fn access_cookie(key: &[u8], link_id: &str, password_hash: &str, now: i64) -> String {
+    let expires = now + ACCESS_COOKIE_TTL_SECS;
+    let signature = sign(key, link_id, password_hash, expires);
+    format!(
+        "{}={expires}.{signature}; Path=/{link_id}; Max-Age={ACCESS_COOKIE_TTL_SECS}; HttpOnly; Secure; SameSite=Lax",
+        access_cookie_name(link_id),
+    )
+}
+
+/// Whether the request's `Cookie` header carries an unexpired, correctly signed unlock
+/// cookie for this link.
+///
+/// The expiry is checked here rather than trusted to the browser's `Max-Age`: a copied
+/// cookie value is replayable from anywhere, and the signed expiry is what bounds it.
+pub fn has_access(
+    cookie_header: Option<&str>,
+    key: &[u8],
+    link_id: &str,
+    password_hash: &str,
+    now: i64,
+) -> bool {
+    let Some(header) = cookie_header else {
+        return false;
+    };
+    let name = access_cookie_name(link_id);
+
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .filter(|(k, _)| *k == name)
+        .any(|(_, value)| {
+            let Some((expires, signature)) = value.split_once('.') else {
+                return false;
+            };
+            let Ok(expires) = expires.parse::<i64>() else {
+                return false;
+            };
+            let Ok(signature) = hex::decode(signature) else {
+                return false;
+            };
+            expires > now && mac(key, link_id, password_hash, expires).verify_slice(&signature).is_ok()
+        })
+}
+
+fn mac(key: &[u8], link_id: &str, password_hash: &str, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    // Newline-separated: none of the fields can contain one, so no two distinct inputs
+    // produce the same message.
+    mac.update(format!("{link_id}\n{expires}\n{password_hash}").as_bytes());
+    mac
+}
+
+fn sign(key: &[u8], link_id: &str, password_hash: &str, expires: i64) -> String {
+    hex::encode(mac(key, link_id, password_hash, expires).finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"test-signing-key";
+    const NOW: i64 = 1_800_000_000;
+
+    /// The cookie value as a browser would send it back: just `name=value`.
+    fn sent_back(set_cookie: &str) -> String {
+        set_cookie.split(';').next().unwrap().to_string()
+    }
+
+    #[test]
+    fn password_bounds_are_enforced() {
+        assert!(check_password("hunter2").is_ok());
+        assert!(matches!(check_password("short"), Err(AppError::Validation(_))));
+        assert!(matches!(
+            check_password(&"x".repeat(PASSWORD_MAX_LEN + 1)),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn salts_are_unique_and_change_the_hash() {
+        let (a, b) = (generate_salt(), generate_salt());
+        assert_ne!(a, b);
+        assert_eq!(a.len(), SALT_BYTES * 2);
+        assert_ne!(hash_password("correct horse", &a), hash_password("correct horse", &b));
+    }
+
+    #[test]
+    fn verify_accepts_only_the_right_password() {
+        let salt = generate_salt();
+        let stored = hash_password("correct horse", &salt);
+        assert!(verify_password("correct horse", &salt, &stored));
+        assert!(!verify_password("correct horsf", &salt, &stored));
+        assert!(!verify_password("", &salt, &stored));
+    }
+
+    #[test]
+    fn issued_cookie_grants_access_to_its_own_link() {
+        let cookie = access_cookie(KEY, "docs123", "hash", NOW);
+        assert!(cookie.contains("Path=/docs123"));
+        assert!(cookie.contains("HttpOnly"));
+
+        let header = format!("other=1; {}", sent_back(&cookie));
+        assert!(has_access(Some(&header), KEY, "docs123", "hash", NOW + 10));
+    }
+
+    #[test]
+    fn cookie_does_not_transfer_between_links_or_keys() {
+        let header = sent_back(&access_cookie(KEY, "docs123", "hash", NOW));
+        assert!(!has_access(Some(&header), KEY, "other12", "hash", NOW));
+        assert!(!has_access(Some(&header), b"another-key", "docs123", "hash", NOW));
+    }
+
+    #[test]
+    fn cookie_stops_working_when_the_password_changes() {
+        let header = sent_back(&access_cookie(KEY, "docs123", "old-hash", NOW));
+        assert!(!has_access(Some(&header), KEY, "docs123", "new-hash", NOW));
+    }
+
+    #[test]
+    fn cookie_expires_server_side() {
+        let header = sent_back(&access_cookie(KEY, "docs123", "hash", NOW));
+        assert!(!has_access(Some(&header), KEY, "docs123", "hash", NOW + ACCESS_COOKIE_TTL_SECS));
+    }
+
+    #[test]
+    fn a_forged_expiry_breaks_the_signature() {
+        let header = sent_back(&access_cookie(KEY, "docs123", "hash", NOW));
+        let (name, value) = header.split_once('=').unwrap();
+        let (_, signature) = value.split_once('.').unwrap();
+        let forged = format!("{name}={}.{signature}", NOW + 10 * ACCESS_COOKIE_TTL_SECS);
+        assert!(!has_access(Some(&forged), KEY, "docs123", "hash", NOW + ACCESS_COOKIE_TTL_SECS));
+    }
+
+    #[test]
+    fn missing_or_garbled_cookies_are_refused() {
+        assert!(!has_access(None, KEY, "docs123", "hash", NOW));
+        assert!(!has_access(Some("krtk_unlock_docs123=garbage"), KEY, "docs123", "hash", NOW));
+        assert!(!has_access(Some("krtk_unlock_docs123=1.zz"), KEY, "docs123", "hash", NOW));
+    }
+}
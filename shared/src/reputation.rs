@@ -0,0 +1,503 @@
+//! Deciding whether a URL is safe enough to shorten.
+//!
+//! Each source of opinion is a [`UrlReputationProvider`]; a [`ReputationPolicy`] asks all
+//! of them and combines the answers. Providers only speak up about URLs they have a view
+//! on, so the combination rule is simple:
+//!
+//! - a URL any provider marks [`Verdict::Unsafe`] is refused, unless another marks it
+//!   [`Verdict::Trusted`] and the flagging provider can be overridden -- an operator's
+//!   allowlist is how a false positive from their own blocklist or feed gets waved
+//!   through, but never one from Safe Browsing (see
+//!   [`UrlReputationProvider::overridable`]);
+//! - a provider that fails is skipped if it fails open, and refuses every URL it could
+//!   not be overridden on if it fails closed.
+//!
+//! Keeping Google Safe Browsing as one provider among several means losing it (quota,
+//! an expired key, an outage) no longer means losing every check at once.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use futures::future::{join_all, BoxFuture};
+use lambda_http::tracing;
+use url::Url;
+
+use crate::error::AppError;
+use crate::safe_browsing::SafeBrowsing;
+
+/// What a provider has to say about one URL. Silence means no opinion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Unsafe,
+    Trusted,
+}
+
+/// A source of opinions about URLs.
+///
+/// Boxed futures rather than `async fn`: the policy holds a list of different providers
+/// chosen at start-up, so the trait has to be object safe.
+pub trait UrlReputationProvider: Send + Sync {
+    /// Stable identifier, used to name the provider in configuration.
+    fn id(&self) -> &'static str;
+
+    /// Shown to the caller when this provider refuses a URL.
+    fn name(&self) -> &'static str;
+
+    /// Verdicts for whichever of `urls` this provider has a view on, keyed by the URL
+    /// exactly as given.
+    fn check<'a>(&'a self, urls: &'a [String]) -> BoxFuture<'a, Result<HashMap<String, Verdict>, AppError>>;
+
+    /// Whether another provider's [`Verdict::Trusted`] overrides this one's
+    /// [`Verdict::Unsafe`], and its failing closed.
+    ///
+    /// Lists the operator maintains can be, since the allowlist is theirs too. An
+    /// independent service should not be: one stale allowlist entry would otherwise let
+    /// through a domain it has since seen serve malware.
+    fn overridable(&self) -> bool {
+        true
+    }
+}
+
+/// What an error from a provider means for the URLs it was asked about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Carry on as if the provider had no opinion.
+    Open,
+    /// Refuse every URL no other provider trusts.
+    Closed,
+}
+
+/// Why the policy refused a URL. Copyable, unlike the error it becomes, because a batch
+/// can carry the same URL more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    /// A provider, named here, marked the URL unsafe.
+    Flagged(&'static str),
+    /// A fail-closed provider could not be asked.
+    Unavailable,
+}
+
+impl Refusal {
+    pub fn error(self) -> AppError {
+        match self {
+            Self::Flagged(name) => AppError::SafeBrowsing(format!("URL flagged as unsafe by {name}")),
+            Self::Unavailable => AppError::Unavailable("URL safety check is unavailable".to_string()),
+        }
+    }
+}
+
+struct Checker {
+    provider: Box<dyn UrlReputationProvider>,
+    failure: FailureMode,
+}
+
+/// The configured providers, and how to combine them.
+#[derive(Default)]
+pub struct ReputationPolicy {
+    checkers: Vec<Checker>,
+}
+
+impl ReputationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a provider. When several refuse the same URL, the first added is named.
+    pub fn with_provider(mut self, provider: impl UrlReputationProvider + 'static, failure: FailureMode) -> Self {
+        self.checkers.push(Checker {
+            provider: Box::new(provider),
+            failure,
+        });
+        self
+    }
+
+    /// Safe Browsing plus whatever the environment configures:
+    ///
+    /// - `BLOCKED_DOMAINS` / `ALLOWED_DOMAINS`: comma-separated domains, subdomains
+    ///   included, for a [`DomainList`];
+    /// - `URLHAUS_FEED_PATH`: a URLhaus-style feed file for a [`UrlFeed`];
+    /// - `REPUTATION_FAIL_CLOSED`: comma-separated provider ids that fail closed. Every
+    ///   other provider fails open, which is how Safe Browsing has always behaved.
+    pub fn from_env(safe_browsing: SafeBrowsing) -> Result<Self, AppError> {
+        let fail_closed = list_var("REPUTATION_FAIL_CLOSED");
+        let failure = |provider: &dyn UrlReputationProvider| {
+            if fail_closed.iter().any(|id| id == provider.id()) {
+                FailureMode::Closed
+            } else {
+                FailureMode::Open
+            }
+        };
+
+        let domains = DomainList::new(list_var("BLOCKED_DOMAINS"), list_var("ALLOWED_DOMAINS"));
+        let mut policy = Self::new();
+        if !domains.is_empty() {
+            let mode = failure(&domains);
+            policy = policy.with_provider(domains, mode);
+        }
+        if let Ok(path) = std::env::var("URLHAUS_FEED_PATH") {
+            let feed = UrlFeed::load(Path::new(&path))?;
+            let mode = failure(&feed);
+            policy = policy.with_provider(feed, mode);
+        }
+        let mode = failure(&safe_browsing);
+        Ok(policy.with_provider(safe_browsing, mode))
+    }
+
+    /// The URLs the policy refuses, each with the reason.
+    ///
+    /// Every provider is asked about the whole set at once and they are asked side by
+    /// side, so a batch costs one round trip per remote provider, not one per link.
+    pub async fn refused(&self, urls: &[String]) -> HashMap<String, Refusal> {
+        if urls.is_empty() {
+            return HashMap::new();
+        }
+
+        let results = join_all(self.checkers.iter().map(|c| c.provider.check(urls))).await;
+
+        let mut trusted = HashSet::new();
+        // Each URL's flags in provider order, with whether they can be overridden.
+        let mut flagged_by: HashMap<String, Vec<(&'static str, bool)>> = HashMap::new();
+        let mut unavailable = vec![];
+        for (checker, result) in self.checkers.iter().zip(results) {
+            let name = checker.provider.name();
+            let overridable = checker.provider.overridable();
+            match result {
+                Ok(verdicts) => {
+                    for (url, verdict) in verdicts {
+                        match verdict {
+                            Verdict::Trusted => {
+                                trusted.insert(url);
+                            }
+                            Verdict::Unsafe => flagged_by.entry(url).or_default().push((name, overridable)),
+                        }
+                    }
+                }
+                Err(e) if checker.failure == FailureMode::Open => {
+                    tracing::warn!("{name} check failed, failing open: {e}");
+                }
+                Err(e) => {
+                    tracing::error!("{name} check failed, failing closed: {e}");
+                    unavailable.push(overridable);
+                }
+            }
+        }
+
+        urls.iter()
+            .filter_map(|url| {
+                let stands = |overridable: bool| !(overridable && trusted.contains(url));
+                let flag = flagged_by.get(url).into_iter().flatten().find(|(_, overridable)| stands(*overridable));
+                let refusal = match flag {
+                    Some((name, _)) => Refusal::Flagged(name),
+                    None if unavailable.iter().any(|overridable| stands(*overridable)) => Refusal::Unavailable,
+                    None => return None,
+                };
+                Some((url.clone(), refusal))
+            })
+            .collect()
+    }
+
+    /// [`Self::refused`] for a single URL.
+    pub async fn check(&self, url: &str) -> Result<(), AppError> {
+        match self.refused(&[url.to_string()]).await.into_values().next() {
+            Some(refusal) => Err(refusal.error()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn list_var(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses a URL as the link validation does: a bare `example.com/page` is a web URL.
+fn parse_lenient(url: &str) -> Option<Url> {
+    Url::parse(url)
+        .ok()
+        .filter(|parsed| parsed.has_host())
+        .or_else(|| Url::parse(&format!("https://{url}")).ok())
+}
+
+fn host_of(url: &str) -> Option<String> {
+    parse_lenient(url)?
+        .host_str()
+        .map(|host| host.trim_end_matches('.').to_ascii_lowercase())
+}
+
+/// Operator-maintained domains: always refused, or always let through.
+///
+/// A domain covers its subdomains (`example.com` covers `www.example.com`) but not
+/// lookalikes (`badexample.com`).
+pub struct DomainList {
+    blocked: HashSet<String>,
+    allowed: HashSet<String>,
+}
+
+impl DomainList {
+    pub fn new(
+        blocked: impl IntoIterator<Item = String>,
+        allowed: impl IntoIterator<Item = String>,
+    ) -> Self {
+        let normalize = |domain: String| domain.trim().trim_end_matches('.').to_ascii_lowercase();
+        Self {
+            blocked: blocked.into_iter().map(normalize).collect(),
+            allowed: allowed.into_iter().map(normalize).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocked.is_empty() && self.allowed.is_empty()
+    }
+
+    fn verdict(&self, url: &str) -> Option<Verdict> {
+        let host = host_of(url)?;
+        // The host itself, then each parent domain in turn.
+        let mut candidates = std::iter::successors(Some(host.as_str()), |h| h.split_once('.').map(|(_, parent)| parent));
+        // Allowed first: an allowlist entry is an explicit override.
+        if candidates.clone().any(|d| self.allowed.contains(d)) {
+            Some(Verdict::Trusted)
+        } else if candidates.any(|d| self.blocked.contains(d)) {
+            Some(Verdict::Unsafe)
+        } else {
+            None
+        }
+    }
+}
+
+impl UrlReputationProvider for DomainList {
+    fn id(&self) -> &'static str {
+        "domain_list"
+    }
+
+    fn name(&self) -> &'static str {
+        "the domain blocklist"
+    }
+
+    fn check<'a>(&'a self, urls: &'a [String]) -> BoxFuture<'a, Result<HashMap<String, Verdict>, AppError>> {
+        let verdicts = urls
+            .iter()
+            .filter_map(|url| self.verdict(url).map(|v| (url.clone(), v)))
+            .collect();
+        Box::pin(async move { Ok(verdicts) })
+    }
+}
+
+/// Known-bad URLs from a feed file, matched exactly (after normalization).
+///
+/// Takes the formats URLhaus publishes: the plain text list, one URL per line, and the
+/// CSV export, where the URL is the third quoted field. `#` lines are comments in both.
+/// The file is read once, at start-up; refreshing it means redeploying.
+pub struct UrlFeed {
+    urls: HashSet<String>,
+}
+
+impl UrlFeed {
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            AppError::Internal(format!("could not read URL feed {}: {e}", path.display()))
+        })?;
+        let feed = Self::parse(&contents);
+        tracing::info!("Loaded {} URLs from {}", feed.urls.len(), path.display());
+        Ok(feed)
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let urls = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                if line.starts_with('"') {
+                    line.split("\",\"").nth(2)
+                } else {
+                    Some(line)
+                }
+            })
+            .filter_map(canonical)
+            .collect();
+        Self { urls }
+    }
+}
+
+/// `HTTP://Example.com` and `http://example.com/` are the same URL to a feed.
+fn canonical(url: &str) -> Option<String> {
+    parse_lenient(url.trim_matches('"')).map(String::from)
+}
+
+impl UrlReputationProvider for UrlFeed {
+    fn id(&self) -> &'static str {
+        "urlhaus"
+    }
+
+    fn name(&self) -> &'static str {
+        "the URLhaus feed"
+    }
+
+    fn check<'a>(&'a self, urls: &'a [String]) -> BoxFuture<'a, Result<HashMap<String, Verdict>, AppError>> {
+        let verdicts = urls
+            .iter()
+            .filter(|url| canonical(url).is_some_and(|c| self.urls.contains(&c)))
+            .map(|url| (url.clone(), Verdict::Unsafe))
+            .collect();
+        Box::pin(async move { Ok(verdicts) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers the same for every URL it is asked about, or fails.
+    struct Fixed(Option<Verdict>);
+
+    impl UrlReputationProvider for Fixed {
+        fn id(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn name(&self) -> &'static str {
+            "Fixed"
+        }
+
+        fn check<'a>(&'a self, urls: &'a [String]) -> BoxFuture<'a, Result<HashMap<String, Verdict>, AppError>> {
+            let result = match self.0 {
+                Some(verdict) => Ok(urls.iter().map(|url| (url.clone(), verdict)).collect()),
+                None => Err(AppError::Internal("down".to_string())),
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    /// [`Fixed`], but not to be overridden, as Safe Browsing.
+    struct Strict(Fixed);
+
+    impl UrlReputationProvider for Strict {
+        fn id(&self) -> &'static str {
+            "strict"
+        }
+
+        fn name(&self) -> &'static str {
+            "Strict"
+        }
+
+        fn check<'a>(&'a self, urls: &'a [String]) -> BoxFuture<'a, Result<HashMap<String, Verdict>, AppError>> {
+            self.0.check(urls)
+        }
+
+        fn overridable(&self) -> bool {
+            false
+        }
+    }
+
+    fn urls(list: &[&str]) -> Vec<String> {
+        list.iter().map(|url| url.to_string()).collect()
+    }
+
+    fn domains(blocked: &[&str], allowed: &[&str]) -> DomainList {
+        DomainList::new(urls(blocked), urls(allowed))
+    }
+
+    #[test]
+    fn blocked_domains_cover_subdomains_but_not_lookalikes() {
+        let list = domains(&["example.com"], &[]);
+        assert_eq!(list.verdict("https://example.com/x"), Some(Verdict::Unsafe));
+        assert_eq!(list.verdict("https://WWW.Example.com./x"), Some(Verdict::Unsafe));
+        assert_eq!(list.verdict("sub.example.com/page"), Some(Verdict::Unsafe));
+        assert_eq!(list.verdict("https://badexample.com/"), None);
+        assert_eq!(list.verdict("https://example.com.evil.net/"), None);
+    }
+
+    #[test]
+    fn an_allowed_subdomain_beats_a_blocked_parent() {
+        let list = domains(&["example.com"], &["docs.example.com"]);
+        assert_eq!(list.verdict("https://docs.example.com/"), Some(Verdict::Trusted));
+        assert_eq!(list.verdict("https://www.example.com/"), Some(Verdict::Unsafe));
+    }
+
+    #[test]
+    fn feed_reads_plain_and_csv_lines() {
+        let feed = UrlFeed::parse(
+            "# URLhaus\n\
+             http://1.2.3.4:8080/bin.sh\n\
+             \n\
+             # id,dateadded,url,url_status\n\
+             \"3196374\",\"2024-01-01 00:00:00\",\"http://Bad.Example/payload\",\"online\"\n",
+        );
+        assert_eq!(feed.urls.len(), 2);
+        assert!(feed.urls.contains("http://1.2.3.4:8080/bin.sh"));
+        assert!(feed.urls.contains("http://bad.example/payload"));
+    }
+
+    #[tokio::test]
+    async fn feed_matches_after_normalization_only() {
+        let feed = UrlFeed::parse("http://bad.example/\n");
+        let asked = urls(&["HTTP://BAD.example", "http://bad.example/other"]);
+        let verdicts = feed.check(&asked).await.unwrap();
+        assert_eq!(verdicts.len(), 1);
+        assert_eq!(verdicts["HTTP://BAD.example"], Verdict::Unsafe);
+    }
+
+    #[tokio::test]
+    async fn any_unsafe_verdict_refuses_and_names_the_provider() {
+        let policy = ReputationPolicy::new()
+            .with_provider(domains(&["bad.example"], &[]), FailureMode::Open)
+            .with_provider(Fixed(None), FailureMode::Open);
+        let refused = policy.refused(&urls(&["https://bad.example/", "https://ok.example/"])).await;
+        assert_eq!(refused.len(), 1);
+        assert_eq!(
+            refused["https://bad.example/"].error().to_string(),
+            "URL safety check failed: URL flagged as unsafe by the domain blocklist"
+        );
+    }
+
+    #[tokio::test]
+    async fn trusted_overrides_another_providers_flag() {
+        let policy = ReputationPolicy::new()
+            .with_provider(Fixed(Some(Verdict::Unsafe)), FailureMode::Open)
+            .with_provider(domains(&[], &["good.example"]), FailureMode::Open);
+        let refused = policy.refused(&urls(&["https://good.example/", "https://other.example/"])).await;
+        assert_eq!(refused.keys().collect::<Vec<_>>(), vec!["https://other.example/"]);
+    }
+
+    /// An allowlist entry does not get past a provider that cannot be overridden.
+    #[tokio::test]
+    async fn trusted_does_not_override_a_provider_that_forbids_it() {
+        let policy = ReputationPolicy::new()
+            .with_provider(Fixed(Some(Verdict::Unsafe)), FailureMode::Open)
+            .with_provider(Strict(Fixed(None)), FailureMode::Closed)
+            .with_provider(domains(&[], &["good.example"]), FailureMode::Open);
+        let refused = policy.refused(&urls(&["https://good.example/"])).await;
+        assert_eq!(refused["https://good.example/"], Refusal::Unavailable);
+
+        let policy = ReputationPolicy::new()
+            .with_provider(domains(&[], &["good.example"]), FailureMode::Open)
+            .with_provider(Fixed(Some(Verdict::Unsafe)), FailureMode::Open)
+            .with_provider(Strict(Fixed(Some(Verdict::Unsafe))), FailureMode::Open);
+        let refused = policy.refused(&urls(&["https://good.example/"])).await;
+        assert_eq!(refused["https://good.example/"], Refusal::Flagged("Strict"));
+    }
+
+    #[tokio::test]
+    async fn a_fail_closed_error_refuses_everything_not_trusted() {
+        let policy = ReputationPolicy::new()
+            .with_provider(Fixed(None), FailureMode::Closed)
+            .with_provider(domains(&[], &["good.example"]), FailureMode::Open);
+        let refused = policy.refused(&urls(&["https://good.example/", "https://other.example/"])).await;
+        assert_eq!(refused.len(), 1);
+        assert_eq!(refused["https://other.example/"], Refusal::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn a_fail_open_error_refuses_nothing() {
+        let policy = ReputationPolicy::new().with_provider(Fixed(None), FailureMode::Open);
+        assert!(policy.check("https://ok.example/").await.is_ok());
+    }
+}
@@ -18,6 +18,19 @@ pub fn redirect_response(location: &str) -> Result<Response<Body>, Error> {
     Ok(response)
 }
 
+/// A redirect that also sets a cookie, for the step that unlocks a password-protected
+/// link: the browser stores the cookie and follows the redirect in one round trip.
+pub fn redirect_response_with_cookie(location: &str, cookie: &str) -> Result<Response<Body>, Error> {
+    let response = Response::builder()
+        .status(StatusCode::FOUND)
+        .header("Location", location)
+        .header("Set-Cookie", cookie)
+        .body(Body::Empty)
+        .map_err(Box::new)?;
+
+    Ok(response)
+}
+
 // Just return an empty response of the same status
 pub fn empty_response(status: &StatusCode) -> Result<Response<Body>, Error> {
     let response = Response::builder()
@@ -86,18 +99,12 @@ pub fn html_response_with_trigger(
 /// it — "Invalid URL Provided" is useful, and the auth variants are already worded to
 /// reveal nothing (see `error.rs`).
 ///
-/// Server errors (5xx) return a **fixed generic message** instead of the error's own.
-/// Variants like `Internal(String)` interpolate their argument, so echoing them would
-/// eventually leak a table name, an ARN, or an SDK error chain into a public response.
-/// The real error still goes to CloudWatch via the caller's `tracing::error!`.
+/// Server errors (5xx) return a **fixed generic message** instead of the error's own;
+/// see [`AppError::public_message`]. The real error still goes to CloudWatch via the
+/// caller's `tracing::error!`.
 pub fn error_response(err: &AppError) -> Result<Response<Body>, Error> {
     let status = err.status_code();
-
-    let message = if status.is_server_error() {
-        "Something went wrong".to_string()
-    } else {
-        err.to_string()
-    };
+    let message = err.public_message();
 
     let response = Response::builder()
         .status(status)
@@ -155,6 +162,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn redirect_response_with_cookie_sets_both_headers() {
+        let resp = redirect_response_with_cookie("https://example.com/", "a=b; HttpOnly").unwrap();
+        assert_eq!(resp.status(), StatusCode::FOUND);
+        assert_eq!(resp.headers()["Location"], "https://example.com/");
+        assert_eq!(resp.headers()["Set-Cookie"], "a=b; HttpOnly");
+    }
+
     #[test]
     fn html_response_with_trigger_sets_the_hx_trigger_header() {
         let resp =
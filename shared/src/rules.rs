@@ -0,0 +1,199 @@
+//! Per-link redirect rules: send some visitors somewhere other than the link's
+//! destination, by device or by country.
+//!
+//! A link's rules are an ordered list stored on its item as `Rules`. `visit_link` tries
+//! them in order and the first that matches the visitor decides where they go; when
+//! none does, or the link has none, they go to `OriginalLink` as always. Every rule
+//! destination is validated like the link's own, and rescanned like it too.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+/// Enough for per-platform and a handful of per-country pages; a longer list is a
+/// routing table, and nobody can predict where their link sends whom any more.
+pub const MAX_RULES_PER_LINK: usize = 10;
+
+/// What kind of device a rule is for, as told by the visitor's user agent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Device {
+    Ios,
+    Android,
+    /// Any phone or tablet, iOS and Android included.
+    Mobile,
+    /// Anything that is not `Mobile`, user agents that say nothing included.
+    Desktop,
+}
+
+/// One rule. Every condition it sets has to hold; it must set at least one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedirectRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<Device>,
+    /// ISO 3166-1 alpha-2 code, as CloudFront reports the viewer's country.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    pub destination: String,
+}
+
+impl RedirectRule {
+    fn matches(&self, visitor: &Visitor) -> bool {
+        self.device.is_none_or(|device| visitor.is(device))
+            && self
+                .country
+                .as_deref()
+                .is_none_or(|country| visitor.country.as_deref() == Some(country))
+    }
+}
+
+/// What a rule can know about a visitor, from their request headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Visitor {
+    ios: bool,
+    android: bool,
+    mobile: bool,
+    country: Option<String>,
+}
+
+impl Visitor {
+    /// Reads the visitor from the `User-Agent` and `CloudFront-Viewer-Country` headers.
+    ///
+    /// iPadOS Safari asks for desktop sites by default and says `Macintosh`, so such an
+    /// iPad is a desktop here. Telling it apart would take client-side detection, which
+    /// a redirect does not get to run.
+    pub fn from_headers(user_agent: Option<&str>, country: Option<&str>) -> Self {
+        let user_agent = user_agent.unwrap_or_default();
+        let ios = ["iPhone", "iPad", "iPod"].iter().any(|device| user_agent.contains(device));
+        let android = user_agent.contains("Android");
+        Self {
+            ios,
+            android,
+            mobile: ios || android || user_agent.contains("Mobi"),
+            country: country
+                .map(|country| country.trim().to_ascii_uppercase())
+                .filter(|country| !country.is_empty()),
+        }
+    }
+
+    fn is(&self, device: Device) -> bool {
+        match device {
+            Device::Ios => self.ios,
+            Device::Android => self.android,
+            Device::Mobile => self.mobile,
+            Device::Desktop => !self.mobile,
+        }
+    }
+}
+
+/// Where `visitor` goes: the destination of the first rule that matches, else `fallback`.
+pub fn destination_for<'a>(rules: &'a [RedirectRule], fallback: &'a str, visitor: &Visitor) -> &'a str {
+    rules
+        .iter()
+        .find(|rule| rule.matches(visitor))
+        .map_or(fallback, |rule| rule.destination.as_str())
+}
+
+/// A rule list as it is stored: a list of maps, in order.
+pub fn rules_attribute(rules: &[RedirectRule]) -> AttributeValue {
+    serde_dynamo::to_attribute_value(rules).expect("a rule list always serializes")
+}
+
+/// Checks a rule list's shape and normalizes each rule's country code.
+///
+/// The destinations are left to the caller, which already knows how a destination is
+/// validated and normalized (see `ShortenUrlRequest::validate`).
+pub fn normalize_rules(rules: Vec<RedirectRule>) -> Result<Vec<RedirectRule>, AppError> {
+    if rules.len() > MAX_RULES_PER_LINK {
+        return Err(AppError::Validation(format!(
+            "A link can have at most {MAX_RULES_PER_LINK} redirect rules"
+        )));
+    }
+    rules
+        .into_iter()
+        .map(|mut rule| {
+            if rule.device.is_none() && rule.country.is_none() {
+                return Err(AppError::Validation(
+                    "A redirect rule needs a device, a country or both".to_string(),
+                ));
+            }
+            if let Some(country) = rule.country.take() {
+                let country = country.trim().to_ascii_uppercase();
+                if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                    return Err(AppError::Validation(format!(
+                        "'{country}' is not a two-letter country code"
+                    )));
+                }
+                rule.country = Some(country);
+            }
+            Ok(rule)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 18_0 like Mac OS X) AppleWebKit/605.1.15 \
+                          (KHTML, like Gecko) Version/18.0 Mobile/15E148 Safari/604.1";
+    const ANDROID: &str = "Mozilla/5.0 (Linux; Android 15; Pixel 9) AppleWebKit/537.36 \
+                           (KHTML, like Gecko) Chrome/130.0.0.0 Mobile Safari/537.36";
+    const DESKTOP: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                           (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36";
+
+    fn rule(device: Option<Device>, country: Option<&str>, destination: &str) -> RedirectRule {
+        RedirectRule {
+            device,
+            country: country.map(str::to_string),
+            destination: destination.to_string(),
+        }
+    }
+
+    fn app_rules() -> Vec<RedirectRule> {
+        vec![
+            rule(Some(Device::Ios), None, "https://apps.apple.com/app/id1"),
+            rule(Some(Device::Android), None, "https://play.google.com/store/apps/details?id=x"),
+            rule(None, Some("DE"), "https://example.com/de"),
+        ]
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins_and_the_rest_fall_back() {
+        let rules = app_rules();
+        let go = |user_agent, country| {
+            destination_for(&rules, "https://example.com/", &Visitor::from_headers(Some(user_agent), country))
+        };
+        assert_eq!(go(IPHONE, Some("DE")), "https://apps.apple.com/app/id1");
+        assert_eq!(go(ANDROID, None), "https://play.google.com/store/apps/details?id=x");
+        assert_eq!(go(DESKTOP, Some("de")), "https://example.com/de");
+        assert_eq!(go(DESKTOP, Some("FR")), "https://example.com/");
+        assert_eq!(destination_for(&rules, "https://example.com/", &Visitor::default()), "https://example.com/");
+    }
+
+    #[test]
+    fn every_condition_of_a_rule_must_hold() {
+        let rules = [rule(Some(Device::Mobile), Some("AT"), "https://example.com/at-mobile")];
+        let visitor = |user_agent, country| Visitor::from_headers(Some(user_agent), Some(country));
+        assert_eq!(destination_for(&rules, "x", &visitor(ANDROID, "AT")), "https://example.com/at-mobile");
+        assert_eq!(destination_for(&rules, "x", &visitor(DESKTOP, "AT")), "x");
+        assert_eq!(destination_for(&rules, "x", &visitor(IPHONE, "DE")), "x");
+
+        let desktop = [rule(Some(Device::Desktop), None, "https://example.com/desktop")];
+        assert_eq!(destination_for(&desktop, "x", &visitor(DESKTOP, "AT")), "https://example.com/desktop");
+        assert_eq!(destination_for(&desktop, "x", &visitor(IPHONE, "AT")), "x");
+    }
+
+    #[test]
+    fn rules_are_bounded_and_need_a_condition() {
+        let normalized = normalize_rules(vec![rule(None, Some(" de "), "https://example.com/de")]).unwrap();
+        assert_eq!(normalized[0].country.as_deref(), Some("DE"));
+
+        assert!(normalize_rules(vec![rule(None, None, "https://example.com/")]).is_err());
+        assert!(normalize_rules(vec![rule(None, Some("DEU"), "https://example.com/")]).is_err());
+        let too_many = vec![rule(Some(Device::Ios), None, "https://example.com/"); MAX_RULES_PER_LINK + 1];
+        assert!(normalize_rules(too_many).is_err());
+    }
+}
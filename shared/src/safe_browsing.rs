@@ -1,8 +1,21 @@
+use std::collections::{HashMap, HashSet};
+
 use serde::{Deserialize, Serialize};
-use aws_sdk_secretsmanager::Client as SecretsClient;
+use futures::future::BoxFuture;
 use lambda_http::tracing;
+use reqwest::StatusCode;
 
 use crate::error::AppError;
+use crate::reputation::{UrlReputationProvider, Verdict};
+use crate::secrets::SecretCache;
+
+const DEFAULT_ENDPOINT: &str = "https://safebrowsing.googleapis.com/v4/threatMatches:find";
+
+/// `SAFE_BROWSING_ENDPOINT` overrides the API URL. Only `krtk-dev` sets it, to its own
+/// stub; the stack never does, so deployed functions always talk to Google.
+fn endpoint() -> String {
+    std::env::var("SAFE_BROWSING_ENDPOINT").unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string())
+}
 
 #[derive(Serialize, Debug)]
 struct SafeBrowsingRequest {
@@ -38,13 +51,103 @@ struct ThreatEntry {
 
 #[derive(Deserialize, Debug)]
 struct SafeBrowsingResponse {
-    matches: Option<Vec<serde_json::Value>>
+    matches: Option<Vec<ThreatMatch>>
 
 }
 
-pub async fn is_url_safe(url: &str, secrets_client: &SecretsClient, secret_arn: &str, http_client: &reqwest::Client) -> Result<bool, AppError> {
-    let api_key = get_api_key(secrets_client, secret_arn).await?;
+// Only the part of a match we act on. Both levels are optional so an unexpected match
+// shape still counts as a match instead of failing to parse -- which would fail open.
+#[derive(Deserialize, Debug)]
+struct ThreatMatch {
+    #[serde(default)]
+    threat: Option<MatchedThreat>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MatchedThreat {
+    #[serde(default)]
+    url: Option<String>,
+}
+
+/// Most `threatEntries` the API takes in one request.
+pub const MAX_THREAT_ENTRIES: usize = 500;
+
+/// Google Safe Browsing, as a reputation provider.
+///
+/// The URLs a check is asked about go in as few `threatMatches:find` calls as the API
+/// allows, [`MAX_THREAT_ENTRIES`] per request: a batch of links with redirect rules can
+/// carry more than that. Each match is reported with the URL it matched, exactly as it
+/// was sent; one that is not is looked into URL by URL (see [`flagged_urls`]).
+///
+/// The API key is held in a [`SecretCache`], so a warm function looks it up once rather
+/// than on every link it checks.
+pub struct SafeBrowsing {
+    api_key: SecretCache,
+    http_client: reqwest::Client,
+}
+
+impl SafeBrowsing {
+    pub fn new(api_key: SecretCache, http_client: reqwest::Client) -> Self {
+        Self { api_key, http_client }
+    }
+}
+
+impl UrlReputationProvider for SafeBrowsing {
+    fn id(&self) -> &'static str {
+        "safe_browsing"
+    }
+
+    fn name(&self) -> &'static str {
+        "Google Safe Browsing"
+    }
+
+    fn check<'a>(&'a self, urls: &'a [String]) -> BoxFuture<'a, Result<HashMap<String, Verdict>, AppError>> {
+        Box::pin(async move {
+            let mut verdicts = HashMap::new();
+            for chunk in urls.chunks(MAX_THREAT_ENTRIES) {
+                let matches = find_threats(chunk, &self.api_key, &self.http_client).await?;
+                let flagged = match flagged_urls(matches, chunk) {
+                    Some(flagged) => flagged,
+                    None => {
+                        tracing::warn!("Safe Browsing answered a match without its URL, checking each URL alone");
+                        let mut flagged = HashSet::new();
+                        for url in chunk {
+                            let single = std::slice::from_ref(url);
+                            let matches = find_threats(single, &self.api_key, &self.http_client).await?;
+                            flagged.extend(flagged_urls(matches, single).unwrap_or_default());
+                        }
+                        flagged
+                    }
+                };
+                verdicts.extend(flagged.into_iter().map(|url| (url, Verdict::Unsafe)));
+            }
+            Ok(verdicts)
+        })
+    }
+
+    /// Google's verdict stands whatever the operator's allowlist says.
+    fn overridable(&self) -> bool {
+        false
+    }
+}
+
+/// Which of `asked` the matches flag, or `None` if some match cannot be attributed.
+///
+/// A match that names none of the URLs asked about is still a threat somewhere in the
+/// request. Dropping it would fail open, and flagging the whole request would disable
+/// every link a rescan page holds, so the caller asks again one URL at a time -- where
+/// every match is about that URL, whether or not it says so.
+fn flagged_urls(matches: Vec<ThreatMatch>, asked: &[String]) -> Option<HashSet<String>> {
+    if let [only] = asked {
+        return Some(if matches.is_empty() { HashSet::new() } else { HashSet::from([only.clone()]) });
+    }
+    matches
+        .into_iter()
+        .map(|m| m.threat.and_then(|t| t.url).filter(|url| asked.contains(url)))
+        .collect()
+}
 
+async fn find_threats(urls: &[String], api_key: &SecretCache, http_client: &reqwest::Client) -> Result<Vec<ThreatMatch>, AppError> {
     let request = SafeBrowsingRequest {
         client: ClientInfo { 
             client_id: "krtkt-rs".to_string(),
@@ -54,20 +157,27 @@ pub async fn is_url_safe(url: &str, secrets_client: &SecretsClient, secret_arn:
             threat_types: vec!["MALWARE".to_string(), "SOCIAL_ENGINEERING".to_string()],
             platform_types: vec!["ANY_PLATFORM".to_string()],
             threat_entry_types: vec!["URL".to_string()],
-            threat_entries: vec![ThreatEntry { url: url.to_string()}],
+            threat_entries: urls.iter().map(|url| ThreatEntry { url: url.clone() }).collect(),
             },
     };
 
+    let mut response = post_request(&request, &api_key.get().await.map_err(not_configured)?, http_client).await?;
 
-    let response: SafeBrowsingResponse = http_client
-        .post(format!("https://safebrowsing.googleapis.com/v4/threatMatches:find?key={}", api_key))
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| {
-            tracing::warn!("Safe Browsing API request failed: {e}");
-            AppError::SafeBrowsing("URL safety check is unavailable".to_string())
-        })?
+    // The cached key was refused: most likely it has been rotated since we fetched it.
+    // Fetch it again and try once more, rather than failing every check until the
+    // cache goes stale.
+    if key_rejected(response.status()) {
+        tracing::info!("Safe Browsing refused the API key ({}), refreshing it", response.status());
+        let api_key = api_key.refresh().await.map_err(not_configured)?;
+        response = post_request(&request, &api_key, http_client).await?;
+    }
+
+    if !response.status().is_success() {
+        tracing::warn!("Safe Browsing API answered {}", response.status());
+        return Err(AppError::SafeBrowsing("URL safety check is unavailable".to_string()));
+    }
+
+    let response: SafeBrowsingResponse = response
         .json()
         .await
         .map_err(|e| {
@@ -75,25 +185,92 @@ pub async fn is_url_safe(url: &str, secrets_client: &SecretsClient, secret_arn:
             AppError::SafeBrowsing("URL safety check returned an unreadable response".to_string())
         })?;
 
-    Ok(response.matches.is_none())
+    Ok(response.matches.unwrap_or_default())
 }
 
-async fn get_api_key(secrets_client: &SecretsClient, secret_arn: &str) -> Result<String, AppError> {
-
-    let secret_value = secrets_client
-        .get_secret_value()
-        .secret_id(secret_arn)
+async fn post_request(request: &SafeBrowsingRequest, api_key: &str, http_client: &reqwest::Client) -> Result<reqwest::Response, AppError> {
+    http_client
+        .post(format!("{}?key={}", endpoint(), api_key))
+        .json(request)
         .send()
         .await
         .map_err(|e| {
-            tracing::error!("Failed to retrieve Safe Browsing API key: {e}");
-            AppError::Internal("URL safety check is not configured".to_string())
-        })?;
+            tracing::warn!("Safe Browsing API request failed: {e}");
+            AppError::SafeBrowsing("URL safety check is unavailable".to_string())
+        })
+}
+
+/// How Google answers a bad key: 400 for one it does not recognise, 401/403 for one
+/// that is not allowed to call the API. Other 4xx, like 429, are not about the key.
+fn key_rejected(status: StatusCode) -> bool {
+    matches!(status, StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+}
+
+fn not_configured(e: AppError) -> AppError {
+    tracing::error!("Failed to retrieve Safe Browsing API key: {e}");
+    AppError::Internal("URL safety check is not configured".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flagged_urls_are_taken_from_each_match() {
+        let response: SafeBrowsingResponse = serde_json::from_str(
+            r#"{"matches":[
+                {"threatType":"MALWARE","threat":{"url":"http://bad.example/"}},
+                {"threatType":"SOCIAL_ENGINEERING","threat":{"url":"http://phish.example/"}}
+            ]}"#,
+        )
+        .unwrap();
+        let asked = vec!["http://bad.example/".to_string(), "http://phish.example/".to_string(), "http://ok.example/".to_string()];
+        let flagged = flagged_urls(response.matches.unwrap(), &asked).unwrap();
+        assert_eq!(flagged.len(), 2);
+        assert!(flagged.contains("http://bad.example/"));
+    }
+
+    /// A match we cannot attribute to a URL must still parse, so the single-URL check
+    /// treats it as unsafe rather than erroring and failing open.
+    #[test]
+    fn a_match_without_a_url_still_flags_a_single_url() {
+        let response: SafeBrowsingResponse =
+            serde_json::from_str(r#"{"matches":[{"threatType":"MALWARE"}]}"#).unwrap();
+        let asked = vec!["http://bad.example/".to_string()];
+        assert_eq!(flagged_urls(response.matches.unwrap(), &asked), Some(HashSet::from([asked[0].clone()])));
+    }
+
+    /// Among several URLs it could be about any of them, so none can be cleared yet.
+    #[test]
+    fn a_match_without_a_url_among_several_is_unattributed() {
+        let asked = vec!["http://bad.example/".to_string(), "http://ok.example/".to_string()];
+        let response: SafeBrowsingResponse = serde_json::from_str(
+            r#"{"matches":[
+                {"threatType":"MALWARE","threat":{"url":"http://bad.example/"}},
+                {"threatType":"MALWARE"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(flagged_urls(response.matches.unwrap(), &asked), None);
+
+        let response: SafeBrowsingResponse = serde_json::from_str(
+            r#"{"matches":[{"threatType":"MALWARE","threat":{"url":"http://elsewhere.example/"}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(flagged_urls(response.matches.unwrap(), &asked), None);
+    }
 
-    // Return if there is something there in the secret
-    secret_value
-        .secret_string()
-        .ok_or_else(|| AppError::Internal("URL safety check is not configured".to_string()))
-        .map(|s| s.to_string())
+    #[test]
+    fn only_key_errors_trigger_a_refresh() {
+        assert!(key_rejected(StatusCode::BAD_REQUEST));
+        assert!(key_rejected(StatusCode::FORBIDDEN));
+        assert!(!key_rejected(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!key_rejected(StatusCode::SERVICE_UNAVAILABLE));
+    }
 
+    #[test]
+    fn no_matches_is_an_empty_response() {
+        let response: SafeBrowsingResponse = serde_json::from_str("{}").unwrap();
+        assert!(response.matches.is_none());
+    }
 }
@@ -0,0 +1,113 @@
+//! Secrets Manager values, cached for the life of the execution environment.
+//!
+//! A warm Lambda serves many requests; fetching the same secret for each one adds a
+//! round trip to every request and turns any Secrets Manager slowness into ours.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use aws_sdk_secretsmanager::Client as SecretsClient;
+use lambda_http::tracing;
+use tokio::sync::RwLock;
+
+use crate::error::AppError;
+
+/// Long enough that a warm function almost never refetches; a rotated secret is picked
+/// up sooner wherever the caller can tell the old one was rejected (see
+/// [`SecretCache::refresh`]).
+const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug)]
+struct CacheEntry {
+    value: String,
+    fetched_at: Instant,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        self.fetched_at.elapsed() < ttl
+    }
+}
+
+/// One secret's string value, fetched on first use and again once stale.
+#[derive(Debug, Clone)]
+pub struct SecretCache {
+    client: SecretsClient,
+    secret_id: String,
+    ttl: Duration,
+    cache: Arc<RwLock<Option<CacheEntry>>>,
+}
+
+impl SecretCache {
+    pub fn new(client: SecretsClient, secret_id: &str) -> Self {
+        Self {
+            client,
+            secret_id: secret_id.to_string(),
+            ttl: DEFAULT_TTL,
+            cache: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The cached value, fetched first if missing or stale.
+    pub async fn get(&self) -> Result<String, AppError> {
+        {
+            let cache = self.cache.read().await;
+            if let Some(entry) = cache.as_ref()
+                && entry.is_fresh(self.ttl)
+            {
+                return Ok(entry.value.clone());
+            }
+        }
+
+        self.refresh().await
+    }
+
+    /// Fetches the value regardless of the cache, for when the cached one was refused.
+    ///
+    /// On failure the old value stays cached: it may still be the right one, and a
+    /// caller that retries should not be left with nothing.
+    pub async fn refresh(&self) -> Result<String, AppError> {
+        let secret = self
+            .client
+            .get_secret_value()
+            .secret_id(&self.secret_id)
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to retrieve secret {}: {e}", self.secret_id);
+                AppError::Internal(format!("Secret {} is unavailable", self.secret_id))
+            })?;
+        let value = secret
+            .secret_string()
+            .ok_or_else(|| AppError::Internal(format!("Secret {} is empty", self.secret_id)))?
+            .to_string();
+
+        let mut cache = self.cache.write().await;
+        *cache = Some(CacheEntry {
+            value: value.clone(),
+            fetched_at: Instant::now(),
+        });
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_entry_goes_stale_after_the_ttl() {
+        let entry = CacheEntry {
+            value: "key".to_string(),
+            fetched_at: Instant::now() - Duration::from_secs(120),
+        };
+        assert!(entry.is_fresh(Duration::from_secs(300)));
+        assert!(!entry.is_fresh(Duration::from_secs(60)));
+    }
+}
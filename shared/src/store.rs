@@ -0,0 +1,1169 @@
+//! Where links live.
+//!
+//! [`UrlShortener`](crate::core::UrlShortener) decides *what* to write; a [`LinkStore`]
+//! only knows how. The unit of exchange is the raw DynamoDB item, so the attribute
+//! naming and `serde_dynamo` mapping stay in one place (`core`) and are exercised the
+//! same way whichever store is behind them.
+//!
+//! [`InMemoryLinkStore`] reproduces the DynamoDB behaviour the shortener relies on --
+//! conditional writes, ownership conditions, `TimeStampIndex` ordering and `Limit`
+//! pagination -- so the paths built on them can be tested without a network.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemError;
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes, PutRequest, ReturnValue, WriteRequest};
+use lambda_http::tracing;
+
+use crate::core::owner_key;
+use crate::error::AppError;
+use crate::rules::{rules_attribute, RedirectRule};
+use crate::url_info::UrlDetails;
+
+/// One link, as stored.
+pub type Item = HashMap<String, AttributeValue>;
+
+// `BatchWriteItem` takes at most 25 puts per call.
+const BATCH_WRITE_LIMIT: usize = 25;
+// Rounds of retrying DynamoDB's unprocessed items.
+const BATCH_WRITE_ATTEMPTS: u32 = 4;
+
+/// Where a listing page stopped: the `LastEvaluatedKey` of a `TimeStampIndex` query,
+/// minus the partition, which is always the caller's own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageKey {
+    pub link_id: String,
+    pub timestamp: String,
+}
+
+#[derive(Debug)]
+pub struct ItemPage {
+    pub items: Vec<Item>,
+    /// Set whenever the page is full, as DynamoDB does -- so a full last page is
+    /// followed by an empty one.
+    pub last_key: Option<PageKey>,
+}
+
+/// One page of a [`LinkStore::scan`].
+#[derive(Debug)]
+pub struct ScanPage {
+    pub items: Vec<Item>,
+    /// The `LinkId` to resume after, set whenever the page is full, as for [`ItemPage`].
+    pub last_key: Option<String>,
+}
+
+/// A change to an existing link, written as a single `UpdateItem` so a failure cannot
+/// leave it half-applied.
+#[derive(Debug)]
+pub struct LinkEdit<'a> {
+    pub url: &'a str,
+    /// The new page's scraped metadata, replacing the old page's.
+    pub details: &'a UrlDetails,
+    /// Replaces the link's tags when set; an empty list removes them.
+    pub tags: Option<&'a [String]>,
+    /// Replaces the link's redirect rules when set; an empty list removes them.
+    pub rules: Option<&'a [RedirectRule]>,
+    /// Whether to lift a rescan's disabled mark (see [`LinkStore::disable`]).
+    pub enable: bool,
+}
+
+/// Storage for link items.
+///
+/// Ownership lives in the store's conditions rather than in a read-then-check by the
+/// caller, so [`Self::edit`] and [`Self::delete`] answer
+/// [`AppError::Forbidden`] alike for a link someone else owns and one that does not exist.
+pub trait LinkStore: Send + Sync {
+    /// Writes a new link unless its `LinkId` is taken. `Ok(false)` means it was.
+    fn insert(&self, item: Item) -> impl Future<Output = Result<bool, AppError>> + Send;
+
+    /// Which of `ids` are already links. One that could not be checked counts as taken:
+    /// re-minting is cheap, an overwrite is not.
+    fn taken_ids(
+        &self,
+        ids: &HashSet<String>,
+    ) -> impl Future<Output = Result<HashSet<String>, AppError>> + Send;
+
+    /// Writes new links unconditionally, with an outcome per `LinkId`. Callers check
+    /// the ids with [`Self::taken_ids`] first.
+    fn insert_all(
+        &self,
+        items: Vec<Item>,
+    ) -> impl Future<Output = Vec<(String, Result<(), AppError>)>> + Send;
+
+    fn get(&self, link_id: &str) -> impl Future<Output = Result<Option<Item>, AppError>> + Send;
+
+    /// Applies `edit` to a link owned by `owner_sub` in one write, and returns the
+    /// updated item.
+    fn edit(
+        &self,
+        link_id: &str,
+        owner_sub: &str,
+        edit: &LinkEdit<'_>,
+    ) -> impl Future<Output = Result<Item, AppError>> + Send;
+
+    /// Renames tag `from` to `to` on a link owned by `owner_sub`, or takes it off when
+    /// `to` is `None`.
+    ///
+    /// `Ok(false)` if the link is not theirs, is gone, or no longer has `from`. The
+    /// write is conditioned on all three and only touches those two tags, so an edit of
+    /// the link's other tags in the meantime is neither lost nor undone.
+    fn retag(
+        &self,
+        link_id: &str,
+        owner_sub: &str,
+        from: &str,
+        to: Option<&str>,
+    ) -> impl Future<Output = Result<bool, AppError>> + Send;
+
+    fn delete(&self, link_id: &str, owner_sub: &str) -> impl Future<Output = Result<(), AppError>> + Send;
+
+    /// Adds to a link's counters; [`AppError::NotFound`] if there is no such link.
+    fn add_clicks(
+        &self,
+        link_id: &str,
+        clicks: u32,
+        bot_clicks: u32,
+    ) -> impl Future<Output = Result<(), AppError>> + Send;
+
+    /// Up to `limit` of `owner_sub`'s links, newest first, starting after `start`.
+    fn list(
+        &self,
+        owner_sub: &str,
+        start: Option<PageKey>,
+        limit: i32,
+    ) -> impl Future<Output = Result<ItemPage, AppError>> + Send;
+
+    /// Every one of `owner_sub`'s links made between `since` and `until` (inclusive)
+    /// with at least `min_clicks` clicks and, if given, tagged `tag`, in no particular
+    /// order.
+    fn search(
+        &self,
+        owner_sub: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        min_clicks: u32,
+        tag: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<Item>, AppError>> + Send;
+
+    /// Segment `segment` of `total_segments` of a scan over the links that are not
+    /// disabled, `limit` items at a time and starting after `start`. Items carry only
+    /// `LinkId`, `OriginalLink`, `OwnerId` and `Rules`.
+    fn scan(
+        &self,
+        segment: i32,
+        total_segments: i32,
+        start: Option<String>,
+        limit: i32,
+    ) -> impl Future<Output = Result<ScanPage, AppError>> + Send;
+
+    /// Marks a link disabled as of `at`, for `reason`; [`AppError::NotFound`] if there
+    /// is no such link.
+    fn disable(&self, link_id: &str, reason: &str, at: i64) -> impl Future<Output = Result<(), AppError>> + Send;
+}
+
+// We are passing the DDB client as well as the table name in the store.
+// As this makes sense, this is the only thing in our app that will use the client for links.
+#[derive(Debug)]
+pub struct DynamoLinkStore {
+    table_name: String,
+    client: Client,
+}
+
+impl DynamoLinkStore {
+    pub fn new(table_name: &str, client: Client) -> Self {
+        Self {
+            table_name: table_name.to_string(),
+            client,
+        }
+    }
+}
+
+impl LinkStore for DynamoLinkStore {
+    async fn insert(&self, item: Item) -> Result<bool, AppError> {
+        let result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(LinkId)")
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError(err))
+                if matches!(err.err(), PutItemError::ConditionalCheckFailedException(_)) =>
+            {
+                Ok(false)
+            }
+            Err(e) => {
+                tracing::error!("Error creating link {:?}", &e);
+                Err(AppError::database(e))
+            }
+        }
+    }
+
+    async fn taken_ids(&self, ids: &HashSet<String>) -> Result<HashSet<String>, AppError> {
+        let keys = ids
+            .iter()
+            .map(|id| HashMap::from([("LinkId".to_string(), AttributeValue::S(id.clone()))]))
+            .collect();
+        let lookup = KeysAndAttributes::builder()
+            .set_keys(Some(keys))
+            .projection_expression("LinkId")
+            .build()
+            .map_err(|e| AppError::Internal(format!("Invalid id lookup: {e}")))?;
+        let output = self
+            .client
+            .batch_get_item()
+            .request_items(&self.table_name, lookup)
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error checking batch link ids: {:?}", e);
+                AppError::database(e)
+            })?;
+
+        let found = output
+            .responses
+            .and_then(|mut tables| tables.remove(&self.table_name))
+            .unwrap_or_default();
+        // Unprocessed keys were not checked, so they count as taken.
+        let unprocessed = output
+            .unprocessed_keys
+            .and_then(|mut tables| tables.remove(&self.table_name))
+            .map(|keys| keys.keys)
+            .unwrap_or_default();
+        Ok(found.iter().chain(&unprocessed).map(link_id_of).collect())
+    }
+
+    /// `BatchWriteItem`, 25 at a time, retrying whatever DynamoDB hands back as
+    /// unprocessed.
+    async fn insert_all(&self, items: Vec<Item>) -> Vec<(String, Result<(), AppError>)> {
+        let mut outcomes = vec![];
+
+        for chunk in items.chunks(BATCH_WRITE_LIMIT) {
+            let mut requests = vec![];
+            for item in chunk {
+                match PutRequest::builder().set_item(Some(item.clone())).build() {
+                    Ok(put) => requests.push(WriteRequest::builder().put_request(put).build()),
+                    Err(e) => outcomes.push((
+                        link_id_of(item),
+                        Err(AppError::Internal(format!("Invalid link item: {e}"))),
+                    )),
+                }
+            }
+
+            for attempt in 0..BATCH_WRITE_ATTEMPTS {
+                if requests.is_empty() {
+                    break;
+                }
+                if attempt > 0 {
+                    // Unprocessed items mean the table is throttling; back off before asking again.
+                    tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+                }
+
+                let sent: Vec<String> = requests
+                    .iter()
+                    .filter_map(|r| r.put_request())
+                    .map(|put| link_id_of(put.item()))
+                    .collect();
+
+                match self
+                    .client
+                    .batch_write_item()
+                    .request_items(&self.table_name, std::mem::take(&mut requests))
+                    .send()
+                    .await
+                {
+                    Ok(output) => {
+                        requests = output
+                            .unprocessed_items
+                            .and_then(|mut tables| tables.remove(&self.table_name))
+                            .unwrap_or_default();
+                        let retrying: HashSet<String> = requests
+                            .iter()
+                            .filter_map(|r| r.put_request())
+                            .map(|put| link_id_of(put.item()))
+                            .collect();
+                        outcomes.extend(
+                            sent.into_iter()
+                                .filter(|id| !retrying.contains(id))
+                                .map(|id| (id, Ok(()))),
+                        );
+                    }
+                    Err(e) => {
+                        tracing::error!("Error writing link batch {:?}", e);
+                        outcomes.extend(sent.into_iter().map(|id| {
+                            (id, Err(AppError::Internal("Failed to store link".to_string())))
+                        }));
+                    }
+                }
+            }
+
+            for unwritten in requests.iter().filter_map(|r| r.put_request()) {
+                tracing::error!("Giving up on unprocessed link {}", link_id_of(unwritten.item()));
+                outcomes.push((
+                    link_id_of(unwritten.item()),
+                    Err(AppError::Internal("Failed to store link".to_string())),
+                ));
+            }
+        }
+
+        outcomes
+    }
+
+    async fn get(&self, link_id: &str) -> Result<Option<Item>, AppError> {
+        let result = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(link_id.to_string()))
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error retrieving URL: {:?}", e);
+                AppError::database(e)
+            })?;
+        Ok(result.item)
+    }
+
+    async fn edit(&self, link_id: &str, owner_sub: &str, edit: &LinkEdit<'_>) -> Result<Item, AppError> {
+        let (update_expression, values) = edit_update(edit);
+
+        let mut update = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(link_id.to_string()))
+            .update_expression(update_expression)
+            // Also fails for a missing item, since an absent OwnerId equals nothing.
+            .condition_expression("OwnerId = :owner")
+            .expression_attribute_values(":owner", AttributeValue::S(owner_sub.to_string()))
+            .return_values(ReturnValue::AllNew);
+
+        for (placeholder, value) in values {
+            update = update.expression_attribute_values(placeholder, value);
+        }
+
+        let result = update.send().await.map_err(|e| match e {
+            SdkError::ServiceError(err) => match err.err() {
+                UpdateItemError::ConditionalCheckFailedException(_) => {
+                    tracing::warn!("Refusing to update link {link_id}: not owned by caller or absent");
+                    AppError::Forbidden
+                }
+                other_error => {
+                    tracing::error!("Error updating link {:?}", &other_error);
+                    AppError::database(SdkError::ServiceError(err))
+                }
+            },
+            other_sdk_error => {
+                tracing::error!("Error updating link {:?}", &other_sdk_error);
+                AppError::database(other_sdk_error)
+            }
+        })?;
+
+        result
+            .attributes
+            .ok_or_else(|| AppError::Internal("Update returned no attributes".to_string()))
+    }
+
+    async fn retag(&self, link_id: &str, owner_sub: &str, from: &str, to: Option<&str>) -> Result<bool, AppError> {
+        // An expression cannot act on one attribute twice, so a rename is an ADD of the
+        // new tag and then a DELETE of the old, each conditioned on the old one still
+        // being there. Deleting a set's last element removes the attribute.
+        let mut actions = vec![];
+        if let Some(to) = to {
+            actions.push(("ADD Tags :tag", to));
+        }
+        if to != Some(from) {
+            actions.push(("DELETE Tags :tag", from));
+        }
+
+        for (update_expression, tag) in actions {
+            let result = self
+                .client
+                .update_item()
+                .table_name(&self.table_name)
+                .key("LinkId", AttributeValue::S(link_id.to_string()))
+                .update_expression(update_expression)
+                .condition_expression("OwnerId = :owner AND contains(Tags, :from)")
+                .expression_attribute_values(":owner", AttributeValue::S(owner_sub.to_string()))
+                .expression_attribute_values(":from", AttributeValue::S(from.to_string()))
+                .expression_attribute_values(":tag", AttributeValue::Ss(vec![tag.to_string()]))
+                .send()
+                .await;
+            match result {
+                Ok(_) => {}
+                Err(SdkError::ServiceError(err))
+                    if matches!(err.err(), UpdateItemError::ConditionalCheckFailedException(_)) =>
+                {
+                    return Ok(false);
+                }
+                Err(e) => {
+                    tracing::error!("Error retagging link {link_id}: {:?}", e);
+                    return Err(AppError::database(e));
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    async fn delete(&self, link_id: &str, owner_sub: &str) -> Result<(), AppError> {
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(link_id.to_string()))
+            .condition_expression("OwnerId = :owner")
+            .expression_attribute_values(":owner", AttributeValue::S(owner_sub.to_string()))
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| match e {
+                SdkError::ServiceError(err) => match err.err() {
+                    DeleteItemError::ConditionalCheckFailedException(_) => {
+                        tracing::warn!("Refusing to delete link {link_id}: not owned by caller or absent");
+                        AppError::Forbidden
+                    }
+                    other_error => {
+                        tracing::error!("Error deleting link {:?}", &other_error);
+                        AppError::database(SdkError::ServiceError(err))
+                    }
+                },
+                other_sdk_error => {
+                    tracing::error!("Error deleting link {:?}", &other_sdk_error);
+                    AppError::database(other_sdk_error)
+                }
+            })
+    }
+
+    async fn add_clicks(&self, link_id: &str, clicks: u32, bot_clicks: u32) -> Result<(), AppError> {
+        let result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(link_id.to_string()))
+            .update_expression("ADD Clicks :n, BotClicks :bots")
+            .expression_attribute_values(":n", AttributeValue::N(clicks.to_string()))
+            .expression_attribute_values(":bots", AttributeValue::N(bot_clicks.to_string()))
+            // ADD would otherwise create an item for every unknown id that gets a hit.
+            .condition_expression("attribute_exists(LinkId)")
+            .send()
+            .await;
+
+        match result {
+            Err(SdkError::ServiceError(err))
+                if matches!(err.err(), UpdateItemError::ConditionalCheckFailedException(_)) =>
+            {
+                Err(AppError::NotFound(link_id.to_string()))
+            }
+            Err(e) => {
+                tracing::error!("Error adding clicks: {:?}", e);
+                Err(AppError::database(e))
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+
+    async fn list(&self, owner_sub: &str, start: Option<PageKey>, limit: i32) -> Result<ItemPage, AppError> {
+        let partition = owner_key(owner_sub);
+
+        let mut query = self
+            .client
+            .query()
+            .index_name("TimeStampIndex")
+            .key_condition_expression("#pk = :pk")
+            .expression_attribute_names("#pk", "SortKey")
+            .expression_attribute_values(":pk", AttributeValue::S(partition.clone()))
+            .table_name(&self.table_name)
+            .scan_index_forward(false)
+            .limit(limit);
+
+        if let Some(start) = start {
+            let exclusive_start_key = HashMap::from([
+                // Must match the queried partition exactly, or DynamoDB rejects the key.
+                ("SortKey".to_string(), AttributeValue::S(partition)),
+                ("LinkId".to_string(), AttributeValue::S(start.link_id)),
+                ("TimeStamp".to_string(), AttributeValue::N(start.timestamp)),
+            ]);
+            query = query.set_exclusive_start_key(Some(exclusive_start_key));
+        }
+
+        let result = query.send().await.map_err(AppError::database)?;
+
+        // If the key is empty, the last page of results has been processed.
+        let last_key = result.last_evaluated_key.and_then(|last_key| {
+            Some(PageKey {
+                link_id: last_key.get("LinkId")?.as_s().ok()?.to_string(),
+                timestamp: last_key.get("TimeStamp")?.as_n().ok()?.to_string(),
+            })
+        });
+
+        Ok(ItemPage {
+            items: result.items.unwrap_or_default(),
+            last_key,
+        })
+    }
+
+    async fn search(
+        &self,
+        owner_sub: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        min_clicks: u32,
+        tag: Option<&str>,
+    ) -> Result<Vec<Item>, AppError> {
+        let partition = owner_key(owner_sub);
+        let mut items = vec![];
+        let mut start = None;
+
+        // The same partition as `list`, so the search never reads another owner's links.
+        // The date range narrows it by sort key; the click floor and the tag are filters.
+        let mut filters = vec![];
+        if min_clicks > 0 {
+            filters.push("Clicks >= :min_clicks");
+        }
+        if tag.is_some() {
+            filters.push("contains(Tags, :tag)");
+        }
+        loop {
+            let mut query = self
+                .client
+                .query()
+                .index_name("TimeStampIndex")
+                .key_condition_expression("#pk = :pk AND #ts BETWEEN :since AND :until")
+                .expression_attribute_names("#pk", "SortKey")
+                .expression_attribute_names("#ts", "TimeStamp")
+                .expression_attribute_values(":pk", AttributeValue::S(partition.clone()))
+                .expression_attribute_values(":since", AttributeValue::N(since.unwrap_or(0).to_string()))
+                .expression_attribute_values(":until", AttributeValue::N(until.unwrap_or(i64::MAX).to_string()))
+                .table_name(&self.table_name)
+                .set_exclusive_start_key(start);
+            if !filters.is_empty() {
+                query = query.filter_expression(filters.join(" AND "));
+            }
+            if min_clicks > 0 {
+                query = query.expression_attribute_values(":min_clicks", AttributeValue::N(min_clicks.to_string()));
+            }
+            if let Some(tag) = tag {
+                query = query.expression_attribute_values(":tag", AttributeValue::S(tag.to_string()));
+            }
+
+            let result = query.send().await.map_err(|e| {
+                tracing::error!("Error searching links: {:?}", e);
+                AppError::database(e)
+            })?;
+            items.extend(result.items.unwrap_or_default());
+            match result.last_evaluated_key {
+                Some(key) => start = Some(key),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    async fn scan(
+        &self,
+        segment: i32,
+        total_segments: i32,
+        start: Option<String>,
+        limit: i32,
+    ) -> Result<ScanPage, AppError> {
+        let mut scan = self
+            .client
+            .scan()
+            .table_name(&self.table_name)
+            .segment(segment)
+            .total_segments(total_segments)
+            .projection_expression("LinkId, OriginalLink, OwnerId, Rules")
+            // Applied after `Limit`, so a page can come back short but still not be the last.
+            .filter_expression("attribute_not_exists(DisabledAt)")
+            .limit(limit);
+
+        if let Some(start) = start {
+            scan = scan.exclusive_start_key("LinkId", AttributeValue::S(start));
+        }
+
+        let result = scan.send().await.map_err(|e| {
+            tracing::error!("Error scanning links: {:?}", e);
+            AppError::database(e)
+        })?;
+
+        let last_key = result
+            .last_evaluated_key
+            .and_then(|key| key.get("LinkId")?.as_s().ok().cloned());
+        Ok(ScanPage {
+            items: result.items.unwrap_or_default(),
+            last_key,
+        })
+    }
+
+    async fn disable(&self, link_id: &str, reason: &str, at: i64) -> Result<(), AppError> {
+        let result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("LinkId", AttributeValue::S(link_id.to_string()))
+            .update_expression("SET DisabledAt = :at, DisabledReason = :reason")
+            .expression_attribute_values(":at", AttributeValue::N(at.to_string()))
+            .expression_attribute_values(":reason", AttributeValue::S(reason.to_string()))
+            // The link may have been deleted since it was scanned; do not bring it back.
+            .condition_expression("attribute_exists(LinkId)")
+            .send()
+            .await;
+
+        match result {
+            Err(SdkError::ServiceError(err))
+                if matches!(err.err(), UpdateItemError::ConditionalCheckFailedException(_)) =>
+            {
+                Err(AppError::NotFound(link_id.to_string()))
+            }
+            Err(e) => {
+                tracing::error!("Error disabling link: {:?}", e);
+                Err(AppError::database(e))
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+}
+
+/// Links held in a map, for tests and local runs.
+#[derive(Debug, Default)]
+pub struct InMemoryLinkStore {
+    items: Mutex<HashMap<String, Item>>,
+}
+
+impl InMemoryLinkStore {
+    fn items(&self) -> std::sync::MutexGuard<'_, HashMap<String, Item>> {
+        // A panic while holding the lock leaves nothing half-written worth refusing.
+        self.items.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl LinkStore for InMemoryLinkStore {
+    async fn insert(&self, item: Item) -> Result<bool, AppError> {
+        let mut items = self.items();
+        let link_id = link_id_of(&item);
+        if items.contains_key(&link_id) {
+            return Ok(false);
+        }
+        items.insert(link_id, item);
+        Ok(true)
+    }
+
+    async fn taken_ids(&self, ids: &HashSet<String>) -> Result<HashSet<String>, AppError> {
+        let items = self.items();
+        Ok(ids.iter().filter(|id| items.contains_key(*id)).cloned().collect())
+    }
+
+    async fn insert_all(&self, new_items: Vec<Item>) -> Vec<(String, Result<(), AppError>)> {
+        let mut items = self.items();
+        new_items
+            .into_iter()
+            .map(|item| {
+                let link_id = link_id_of(&item);
+                items.insert(link_id.clone(), item);
+                (link_id, Ok(()))
+            })
+            .collect()
+    }
+
+    async fn get(&self, link_id: &str) -> Result<Option<Item>, AppError> {
+        Ok(self.items().get(link_id).cloned())
+    }
+
+    async fn edit(&self, link_id: &str, owner_sub: &str, edit: &LinkEdit<'_>) -> Result<Item, AppError> {
+        let mut items = self.items();
+        let item = items
+            .get_mut(link_id)
+            .filter(|item| is_owned_by(item, owner_sub))
+            .ok_or(AppError::Forbidden)?;
+
+        item.insert("OriginalLink".to_string(), AttributeValue::S(edit.url.to_string()));
+        for (attribute, value) in scraped_attributes(edit.details) {
+            match value {
+                Some(v) => item.insert(attribute.to_string(), AttributeValue::S(v.to_string())),
+                None => item.remove(attribute),
+            };
+        }
+        if let Some(tags) = edit.tags {
+            if tags.is_empty() {
+                item.remove("Tags");
+            } else {
+                item.insert("Tags".to_string(), AttributeValue::Ss(tags.to_vec()));
+            }
+        }
+        if let Some(rules) = edit.rules {
+            if rules.is_empty() {
+                item.remove("Rules");
+            } else {
+                item.insert("Rules".to_string(), rules_attribute(rules));
+            }
+        }
+        if edit.enable {
+            for attribute in DISABLED_ATTRIBUTES {
+                item.remove(attribute);
+            }
+        }
+        Ok(item.clone())
+    }
+
+    async fn retag(&self, link_id: &str, owner_sub: &str, from: &str, to: Option<&str>) -> Result<bool, AppError> {
+        let mut items = self.items();
+        let Some(item) = items.get_mut(link_id).filter(|item| is_owned_by(item, owner_sub)) else {
+            return Ok(false);
+        };
+        if !tags_of(item).iter().any(|tag| tag == from) {
+            return Ok(false);
+        }
+
+        let mut tags: Vec<String> = tags_of(item).iter().filter(|tag| *tag != from).cloned().collect();
+        tags.extend(to.map(str::to_string));
+        tags.sort();
+        tags.dedup();
+        if tags.is_empty() {
+            item.remove("Tags");
+        } else {
+            item.insert("Tags".to_string(), AttributeValue::Ss(tags));
+        }
+        Ok(true)
+    }
+
+    async fn delete(&self, link_id: &str, owner_sub: &str) -> Result<(), AppError> {
+        let mut items = self.items();
+        if !items.get(link_id).is_some_and(|item| is_owned_by(item, owner_sub)) {
+            return Err(AppError::Forbidden);
+        }
+        items.remove(link_id);
+        Ok(())
+    }
+
+    async fn add_clicks(&self, link_id: &str, clicks: u32, bot_clicks: u32) -> Result<(), AppError> {
+        let mut items = self.items();
+        let item = items
+            .get_mut(link_id)
+            .ok_or_else(|| AppError::NotFound(link_id.to_string()))?;
+        for (attribute, n) in [("Clicks", clicks), ("BotClicks", bot_clicks)] {
+            let total = number_of(item, attribute) + i64::from(n);
+            item.insert(attribute.to_string(), AttributeValue::N(total.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn list(&self, owner_sub: &str, start: Option<PageKey>, limit: i32) -> Result<ItemPage, AppError> {
+        let partition = owner_key(owner_sub);
+        let start = start.map(|key| (key.timestamp.parse::<i64>().unwrap_or(i64::MAX), key.link_id));
+
+        // `TimeStampIndex` order, descending; ties go by `LinkId` so pages are stable.
+        let mut owned: Vec<(i64, String, Item)> = self
+            .items()
+            .values()
+            .filter(|item| matches!(item.get("SortKey"), Some(AttributeValue::S(key)) if *key == partition))
+            .map(|item| (number_of(item, "TimeStamp"), link_id_of(item), item.clone()))
+            .filter(|(timestamp, link_id, _)| {
+                start
+                    .as_ref()
+                    .is_none_or(|(start_ts, start_id)| (*timestamp, link_id) < (*start_ts, start_id))
+            })
+            .collect();
+        owned.sort_by(|a, b| (b.0, &b.1).cmp(&(a.0, &a.1)));
+        owned.truncate(usize::try_from(limit).unwrap_or(0));
+
+        let last_key = match owned.last() {
+            Some((timestamp, link_id, _)) if owned.len() as i32 == limit => Some(PageKey {
+                link_id: link_id.clone(),
+                timestamp: timestamp.to_string(),
+            }),
+            _ => None,
+        };
+        Ok(ItemPage {
+            items: owned.into_iter().map(|(_, _, item)| item).collect(),
+            last_key,
+        })
+    }
+
+    async fn search(
+        &self,
+        owner_sub: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        min_clicks: u32,
+        tag: Option<&str>,
+    ) -> Result<Vec<Item>, AppError> {
+        let partition = owner_key(owner_sub);
+        let range = since.unwrap_or(0)..=until.unwrap_or(i64::MAX);
+        Ok(self
+            .items()
+            .values()
+            .filter(|item| matches!(item.get("SortKey"), Some(AttributeValue::S(key)) if *key == partition))
+            .filter(|item| range.contains(&number_of(item, "TimeStamp")))
+            .filter(|item| number_of(item, "Clicks") >= i64::from(min_clicks))
+            .filter(|item| tag.is_none_or(|tag| tags_of(item).iter().any(|t| t == tag)))
+            .cloned()
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        segment: i32,
+        total_segments: i32,
+        start: Option<String>,
+        limit: i32,
+    ) -> Result<ScanPage, AppError> {
+        let mut found: Vec<Item> = self
+            .items()
+            .values()
+            .filter(|item| !item.contains_key("DisabledAt"))
+            .filter(|item| segment_of(&link_id_of(item), total_segments) == segment)
+            .filter(|item| start.as_ref().is_none_or(|start| link_id_of(item) > *start))
+            .map(|item| {
+                ["LinkId", "OriginalLink", "OwnerId", "Rules"]
+                    .into_iter()
+                    .filter_map(|attribute| Some((attribute.to_string(), item.get(attribute)?.clone())))
+                    .collect()
+            })
+            .collect();
+        found.sort_by_key(link_id_of);
+        found.truncate(usize::try_from(limit).unwrap_or(0));
+
+        let last_key = match found.last() {
+            Some(item) if found.len() as i32 == limit => Some(link_id_of(item)),
+            _ => None,
+        };
+        Ok(ScanPage { items: found, last_key })
+    }
+
+    async fn disable(&self, link_id: &str, reason: &str, at: i64) -> Result<(), AppError> {
+        let mut items = self.items();
+        let item = items
+            .get_mut(link_id)
+            .ok_or_else(|| AppError::NotFound(link_id.to_string()))?;
+        item.insert("DisabledAt".to_string(), AttributeValue::N(at.to_string()));
+        item.insert("DisabledReason".to_string(), AttributeValue::S(reason.to_string()));
+        Ok(())
+    }
+}
+
+/// Which scan segment a link falls in. Any stable split will do; DynamoDB's own is by
+/// partition key hash.
+fn segment_of(link_id: &str, total_segments: i32) -> i32 {
+    let sum: i32 = link_id.bytes().map(i32::from).sum();
+    sum % total_segments.max(1)
+}
+
+fn is_owned_by(item: &Item, owner_sub: &str) -> bool {
+    matches!(item.get("OwnerId"), Some(AttributeValue::S(owner)) if owner == owner_sub)
+}
+
+fn number_of(item: &Item, attribute: &str) -> i64 {
+    match item.get(attribute) {
+        Some(AttributeValue::N(n)) => n.parse().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn tags_of(item: &Item) -> &[String] {
+    match item.get("Tags") {
+        Some(AttributeValue::Ss(tags)) => tags,
+        _ => &[],
+    }
+}
+
+fn link_id_of(item: &Item) -> String {
+    match item.get("LinkId") {
+        Some(AttributeValue::S(id)) => id.clone(),
+        _ => String::new(),
+    }
+}
+
+fn scraped_attributes(details: &UrlDetails) -> [(&'static str, &Option<String>); 4] {
+    [
+        ("Title", &details.title),
+        ("Description", &details.description),
+        ("ContentType", &details.content_type),
+        ("Image", &details.image),
+    ]
+}
+
+/// What [`LinkStore::disable`] sets, and an edit that re-enables the link clears.
+const DISABLED_ATTRIBUTES: [&str; 2] = ["DisabledAt", "DisabledReason"];
+
+/// Builds the update expression for an edit.
+///
+/// Scraped attributes the new page does not have are REMOVEd rather than left alone:
+/// keeping the old page's title on a link that now goes somewhere else would be
+/// actively misleading in the links table. Tags and rules likewise: DynamoDB has no
+/// empty sets, and an empty rule list is no rules.
+fn edit_update(edit: &LinkEdit<'_>) -> (String, Vec<(&'static str, AttributeValue)>) {
+    let mut set = vec!["OriginalLink = :url"];
+    let mut remove = vec![];
+    let mut values = vec![(":url", AttributeValue::S(edit.url.to_string()))];
+
+    let details = edit.details;
+    let scraped = [
+        ("Title", "Title = :title", ":title", &details.title),
+        ("Description", "Description = :description", ":description", &details.description),
+        ("ContentType", "ContentType = :content_type", ":content_type", &details.content_type),
+        ("Image", "Image = :image", ":image", &details.image),
+    ];
+    for (attribute, assignment, placeholder, value) in scraped {
+        match value {
+            Some(v) => {
+                set.push(assignment);
+                values.push((placeholder, AttributeValue::S(v.to_string())));
+            }
+            None => remove.push(attribute),
+        }
+    }
+    match edit.tags {
+        Some([]) => remove.push("Tags"),
+        Some(tags) => {
+            set.push("Tags = :tags");
+            values.push((":tags", AttributeValue::Ss(tags.to_vec())));
+        }
+        None => {}
+    }
+    match edit.rules {
+        Some([]) => remove.push("Rules"),
+        Some(rules) => {
+            set.push("Rules = :rules");
+            values.push((":rules", rules_attribute(rules)));
+        }
+        None => {}
+    }
+    if edit.enable {
+        remove.extend(DISABLED_ATTRIBUTES);
+    }
+
+    let mut expression = format!("SET {}", set.join(", "));
+    if !remove.is_empty() {
+        expression.push_str(&format!(" REMOVE {}", remove.join(", ")));
+    }
+    (expression, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(link_id: &str, owner_sub: &str, timestamp: i64) -> Item {
+        HashMap::from([
+            ("LinkId".to_string(), AttributeValue::S(link_id.to_string())),
+            ("SortKey".to_string(), AttributeValue::S(owner_key(owner_sub))),
+            ("OwnerId".to_string(), AttributeValue::S(owner_sub.to_string())),
+            ("TimeStamp".to_string(), AttributeValue::N(timestamp.to_string())),
+        ])
+    }
+
+    #[tokio::test]
+    async fn insert_refuses_a_link_id_that_exists() {
+        let store = InMemoryLinkStore::default();
+        assert!(store.insert(link("abc1234", "owner-a", 1)).await.unwrap());
+        assert!(!store.insert(link("abc1234", "owner-b", 2)).await.unwrap());
+
+        let kept = store.get("abc1234").await.unwrap().unwrap();
+        assert_eq!(kept["OwnerId"], AttributeValue::S("owner-a".into()));
+    }
+
+    #[tokio::test]
+    async fn taken_ids_are_the_ones_already_stored() {
+        let store = InMemoryLinkStore::default();
+        store.insert(link("taken", "owner-a", 1)).await.unwrap();
+
+        let ids = HashSet::from(["taken".to_string(), "free".to_string()]);
+        assert_eq!(store.taken_ids(&ids).await.unwrap(), HashSet::from(["taken".to_string()]));
+    }
+
+    /// Links created in the same second must still page without skipping or repeating.
+    #[tokio::test]
+    async fn listing_breaks_timestamp_ties_consistently() {
+        let store = InMemoryLinkStore::default();
+        for id in ["a", "b", "c"] {
+            store.insert(link(id, "owner-a", 100)).await.unwrap();
+        }
+
+        let first = store.list("owner-a", None, 2).await.unwrap();
+        let rest = store.list("owner-a", first.last_key.clone(), 2).await.unwrap();
+        let ids: Vec<String> = first.items.iter().chain(&rest.items).map(link_id_of).collect();
+        assert_eq!(ids, ["c", "b", "a"]);
+        assert_eq!(first.last_key, Some(PageKey { link_id: "b".into(), timestamp: "100".into() }));
+        assert!(rest.last_key.is_none());
+    }
+
+    #[tokio::test]
+    async fn search_reads_only_the_owners_links_within_its_bounds() {
+        let store = InMemoryLinkStore::default();
+        for (id, owner, timestamp) in [("old", "owner-a", 100), ("new", "owner-a", 300), ("theirs", "owner-b", 200)] {
+            store.insert(link(id, owner, timestamp)).await.unwrap();
+        }
+        store.add_clicks("new", 5, 0).await.unwrap();
+
+        let ids = |items: Vec<Item>| -> HashSet<String> { items.iter().map(link_id_of).collect() };
+        let all = store.search("owner-a", None, None, 0, None).await.unwrap();
+        assert_eq!(ids(all), HashSet::from(["old".to_string(), "new".to_string()]));
+        let ranged = store.search("owner-a", Some(150), Some(300), 0, None).await.unwrap();
+        assert_eq!(ids(ranged), HashSet::from(["new".to_string()]));
+        let clicked = store.search("owner-a", None, None, 1, None).await.unwrap();
+        assert_eq!(ids(clicked), HashSet::from(["new".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn tags_are_retagged_by_the_owner_only_and_searchable() {
+        let store = InMemoryLinkStore::default();
+        let mut item = link("abc1234", "owner-a", 1);
+        item.insert("Tags".to_string(), AttributeValue::Ss(vec!["draft".into(), "launch".into()]));
+        store.insert(item).await.unwrap();
+        store.insert(link("def5678", "owner-a", 2)).await.unwrap();
+
+        assert!(!store.retag("abc1234", "owner-b", "draft", Some("q3")).await.unwrap());
+        assert!(!store.retag("def5678", "owner-a", "draft", Some("q3")).await.unwrap());
+        assert!(store.retag("abc1234", "owner-a", "draft", Some("q3")).await.unwrap());
+        let tagged = store.get("abc1234").await.unwrap().unwrap();
+        assert_eq!(tagged["Tags"], AttributeValue::Ss(vec!["launch".into(), "q3".into()]));
+
+        let found = store.search("owner-a", None, None, 0, Some("q3")).await.unwrap();
+        assert_eq!(found.iter().map(link_id_of).collect::<Vec<_>>(), ["abc1234"]);
+
+        // Taking off the last tag removes the attribute rather than storing an empty set.
+        store.retag("abc1234", "owner-a", "q3", None).await.unwrap();
+        store.retag("abc1234", "owner-a", "launch", None).await.unwrap();
+        assert!(!store.get("abc1234").await.unwrap().unwrap().contains_key("Tags"));
+        assert!(store.search("owner-a", None, None, 0, Some("q3")).await.unwrap().is_empty());
+    }
+
+    /// An edit with the destination only, as most are.
+    fn retarget<'a>(url: &'a str, details: &'a UrlDetails) -> LinkEdit<'a> {
+        LinkEdit { url, details, tags: None, rules: None, enable: true }
+    }
+
+    #[tokio::test]
+    async fn an_edit_replaces_the_scraped_metadata() {
+        let store = InMemoryLinkStore::default();
+        let mut item = link("abc1234", "owner-a", 1);
+        item.insert("Title".to_string(), AttributeValue::S("Old page".into()));
+        store.insert(item).await.unwrap();
+
+        let details = UrlDetails { image: Some("https://example.com/og.png".into()), ..Default::default() };
+        let updated = store
+            .edit("abc1234", "owner-a", &retarget("https://example.com/new", &details))
+            .await
+            .unwrap();
+        assert_eq!(updated["OriginalLink"], AttributeValue::S("https://example.com/new".into()));
+        assert!(!updated.contains_key("Title"));
+        assert!(updated.contains_key("Image"));
+    }
+
+    /// Segments are walked side by side, so together they must cover every link once.
+    #[tokio::test]
+    async fn scan_segments_split_the_links_and_skip_disabled_ones() {
+        let store = InMemoryLinkStore::default();
+        for id in ["a", "b", "c", "d", "e"] {
+            store.insert(link(id, "owner-a", 1)).await.unwrap();
+        }
+        store.disable("c", "test", 1).await.unwrap();
+
+        let mut seen = vec![];
+        for segment in 0..2 {
+            let mut start = None;
+            loop {
+                let page = store.scan(segment, 2, start, 1).await.unwrap();
+                seen.extend(page.items.iter().map(link_id_of));
+                match page.last_key {
+                    Some(key) => start = Some(key),
+                    None => break,
+                }
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, ["a", "b", "d", "e"]);
+    }
+
+    #[tokio::test]
+    async fn only_an_enabling_edit_lifts_the_disabled_mark() {
+        let store = InMemoryLinkStore::default();
+        store.insert(link("abc1234", "owner-a", 1)).await.unwrap();
+        store.disable("abc1234", "Google Safe Browsing", 5).await.unwrap();
+
+        let details = UrlDetails::default();
+        let kept = LinkEdit { enable: false, ..retarget("https://example.com/new", &details) };
+        let updated = store.edit("abc1234", "owner-a", &kept).await.unwrap();
+        assert!(updated.contains_key("DisabledAt"));
+
+        let updated = store
+            .edit("abc1234", "owner-a", &retarget("https://example.com/new", &details))
+            .await
+            .unwrap();
+        assert!(!updated.contains_key("DisabledAt"));
+        assert!(!updated.contains_key("DisabledReason"));
+    }
+
+    #[tokio::test]
+    async fn disabling_a_deleted_link_does_not_recreate_it() {
+        let store = InMemoryLinkStore::default();
+        assert!(matches!(store.disable("gone", "test", 1).await, Err(AppError::NotFound(_))));
+        assert!(store.get("gone").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn edit_update_sets_every_scraped_attribute_it_has() {
+        let details = UrlDetails {
+            content_type: Some("text/html".into()),
+            title: Some("New page".into()),
+            description: Some("About it".into()),
+            image: Some("https://example.com/og.png".into()),
+        };
+        let (expression, values) = edit_update(&retarget("https://example.com/new", &details));
+
+        assert_eq!(
+            expression,
+            "SET OriginalLink = :url, Title = :title, Description = :description, \
+             ContentType = :content_type, Image = :image REMOVE DisabledAt, DisabledReason"
+        );
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[0], (":url", AttributeValue::S("https://example.com/new".into())));
+    }
+
+    /// The old page's title must not survive onto a link that now goes somewhere else.
+    #[test]
+    fn edit_update_removes_metadata_the_new_page_lacks() {
+        let details = UrlDetails {
+            title: Some("Only a title".into()),
+            ..Default::default()
+        };
+        let (expression, values) = edit_update(&retarget("https://example.com/", &details));
+
+        assert_eq!(
+            expression,
+            "SET OriginalLink = :url, Title = :title \
+             REMOVE Description, ContentType, Image, DisabledAt, DisabledReason"
+        );
+        assert_eq!(values.len(), 2);
+    }
+
+    /// Tags, rules and the destination go in one write, and a disabled link can stay so.
+    #[test]
+    fn edit_update_carries_tags_and_rules_in_the_same_expression() {
+        let details = UrlDetails { title: Some("Page".into()), ..Default::default() };
+        let tags = ["q3".to_string()];
+        let edit = LinkEdit {
+            tags: Some(&tags),
+            rules: Some(&[]),
+            enable: false,
+            ..retarget("https://example.com/", &details)
+        };
+        let (expression, values) = edit_update(&edit);
+
+        assert_eq!(
+            expression,
+            "SET OriginalLink = :url, Title = :title, Tags = :tags \
+             REMOVE Description, ContentType, Image, Rules"
+        );
+        assert_eq!(values[2], (":tags", AttributeValue::Ss(tags.to_vec())));
+    }
+}
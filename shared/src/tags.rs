@@ -0,0 +1,218 @@
+//! Tags on links, and the per-owner index that answers "which tags, and where".
+//!
+//! A link's tags live on the link item as the `Tags` string set, which is what the
+//! listing reads and filters on. The index is a table of its own with one item per
+//! (owner, tag, link): `OwnerId` is the owner key (see [`owner_key`]) and `TagLink` is
+//! `<tag>#<linkId>`, so everything about one owner's tags is a `Query` on their
+//! partition and every link under one tag is a `begins_with` on the sort key. No
+//! other owner's tags are ever read.
+//!
+//! Only `index_tags` writes the index, from the link table's stream, so every path
+//! that changes a link's tags -- create, edit, delete, a rename, TTL expiry -- keeps it
+//! in step without knowing it exists. Putting or deleting an item is idempotent, so a
+//! stream batch that is delivered twice leaves the index as it was.
+
+use std::collections::{BTreeMap, HashSet};
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use lambda_http::tracing;
+use serde::Serialize;
+
+use crate::core::owner_key;
+use crate::error::AppError;
+
+const TAG_MAX_LEN: usize = 32;
+/// Enough to file a link several ways; beyond it tags stop being a way of finding things.
+pub const MAX_TAGS_PER_LINK: usize = 10;
+
+/// Normalizes one tag: trimmed and lowercased, so `Launch` and `launch ` are one tag.
+///
+/// Letters, digits, `-` and `_`, plus `/` so a tag can read as a folder path
+/// (`clients/acme`). `#` in particular is refused: it separates the tag from the link id
+/// in the index's sort key.
+pub fn normalize_tag(tag: &str) -> Result<String, AppError> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() || tag.len() > TAG_MAX_LEN {
+        return Err(AppError::Validation(format!(
+            "A tag must be between 1 and {TAG_MAX_LEN} characters"
+        )));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '/'))
+    {
+        return Err(AppError::Validation(format!(
+            "Tag '{tag}' may only contain letters, digits, '-', '_' and '/'"
+        )));
+    }
+    Ok(tag)
+}
+
+/// Normalizes a link's tags, dropping duplicates and keeping them sorted.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<Result<HashSet<_>, _>>()?
+        .into_iter()
+        .collect();
+    if normalized.len() > MAX_TAGS_PER_LINK {
+        return Err(AppError::Validation(format!(
+            "A link can have at most {MAX_TAGS_PER_LINK} tags"
+        )));
+    }
+    normalized.sort();
+    Ok(normalized)
+}
+
+/// One of an owner's tags and how many links carry it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub links: usize,
+}
+
+#[derive(Debug)]
+pub struct TagIndex {
+    dynamodb_tag_table: String,
+    dynamodb_client: Client,
+}
+
+impl TagIndex {
+    pub fn new(dynamodb_tag_table: &str, dynamodb_client: Client) -> Self {
+        Self {
+            dynamodb_tag_table: dynamodb_tag_table.to_string(),
+            dynamodb_client,
+        }
+    }
+
+    /// Records that `link_id` carries `tag`.
+    pub async fn add(&self, owner_sub: &str, tag: &str, link_id: &str) -> Result<(), AppError> {
+        self.dynamodb_client
+            .put_item()
+            .table_name(&self.dynamodb_tag_table)
+            .item("OwnerId", AttributeValue::S(owner_key(owner_sub)))
+            .item("TagLink", AttributeValue::S(tag_link(tag, link_id)))
+            .item("Tag", AttributeValue::S(tag.to_string()))
+            .item("LinkId", AttributeValue::S(link_id.to_string()))
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error indexing tag {tag} on {link_id}: {:?}", e);
+                AppError::database(e)
+            })?;
+        Ok(())
+    }
+
+    /// Records that `link_id` no longer carries `tag`.
+    pub async fn remove(&self, owner_sub: &str, tag: &str, link_id: &str) -> Result<(), AppError> {
+        self.dynamodb_client
+            .delete_item()
+            .table_name(&self.dynamodb_tag_table)
+            .key("OwnerId", AttributeValue::S(owner_key(owner_sub)))
+            .key("TagLink", AttributeValue::S(tag_link(tag, link_id)))
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error unindexing tag {tag} on {link_id}: {:?}", e);
+                AppError::database(e)
+            })?;
+        Ok(())
+    }
+
+    /// Every tag `owner_sub` uses, alphabetically, with the number of links carrying it.
+    pub async fn counts(&self, owner_sub: &str) -> Result<Vec<TagCount>, AppError> {
+        let entries = self.query(owner_sub, None).await?;
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for (tag, _) in entries {
+            *counts.entry(tag).or_default() += 1;
+        }
+        Ok(counts
+            .into_iter()
+            .map(|(tag, links)| TagCount { tag, links })
+            .collect())
+    }
+
+    /// The ids of `owner_sub`'s links that carry `tag`.
+    pub async fn links(&self, owner_sub: &str, tag: &str) -> Result<Vec<String>, AppError> {
+        let entries = self.query(owner_sub, Some(tag)).await?;
+        Ok(entries.into_iter().map(|(_, link_id)| link_id).collect())
+    }
+
+    /// `(tag, link id)` for each of the owner's entries, or only `tag`'s.
+    async fn query(&self, owner_sub: &str, tag: Option<&str>) -> Result<Vec<(String, String)>, AppError> {
+        let mut entries = vec![];
+        let mut start = None;
+
+        loop {
+            let mut query = self
+                .dynamodb_client
+                .query()
+                .table_name(&self.dynamodb_tag_table)
+                .expression_attribute_values(":owner", AttributeValue::S(owner_key(owner_sub)))
+                .projection_expression("Tag, LinkId")
+                .set_exclusive_start_key(start);
+            query = match tag {
+                // The trailing `#` keeps `launch` from also matching `launch-2026`.
+                Some(tag) => query
+                    .key_condition_expression("OwnerId = :owner AND begins_with(TagLink, :prefix)")
+                    .expression_attribute_values(":prefix", AttributeValue::S(tag_link(tag, ""))),
+                None => query.key_condition_expression("OwnerId = :owner"),
+            };
+
+            let result = query.send().await.map_err(|e| {
+                tracing::error!("Error querying the tag index: {:?}", e);
+                AppError::database(e)
+            })?;
+            entries.extend(result.items().iter().filter_map(|item| {
+                let tag = item.get("Tag")?.as_s().ok()?;
+                let link_id = item.get("LinkId")?.as_s().ok()?;
+                Some((tag.clone(), link_id.clone()))
+            }));
+            match result.last_evaluated_key {
+                Some(key) => start = Some(key),
+                None => return Ok(entries),
+            }
+        }
+    }
+}
+
+fn tag_link(tag: &str, link_id: &str) -> String {
+    format!("{tag}#{link_id}")
+}
+
+/// How a link's tags changed between two images of it: `(added, removed)`.
+pub fn tag_changes<'a>(old: &'a [String], new: &'a [String]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let added = new.iter().filter(|tag| !old.contains(tag)).map(String::as_str).collect();
+    let removed = old.iter().filter(|tag| !new.contains(tag)).map(String::as_str).collect();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_normalized_deduplicated_and_sorted() {
+        let tags = ["Launch ".to_string(), "clients/acme".to_string(), "launch".to_string()];
+        assert_eq!(normalize_tags(&tags).unwrap(), ["clients/acme", "launch"]);
+    }
+
+    #[test]
+    fn tags_that_would_break_the_index_key_are_refused() {
+        for bad in ["", "a#b", "two words", &"x".repeat(TAG_MAX_LEN + 1)] {
+            assert!(matches!(normalize_tag(bad), Err(AppError::Validation(_))), "accepted {bad:?}");
+        }
+        let too_many: Vec<String> = (0..=MAX_TAGS_PER_LINK).map(|n| format!("t{n}")).collect();
+        assert!(normalize_tags(&too_many).is_err());
+    }
+
+    #[test]
+    fn tag_changes_are_the_two_set_differences() {
+        let old = ["a".to_string(), "b".to_string()];
+        let new = ["b".to_string(), "c".to_string()];
+        assert_eq!(tag_changes(&old, &new), (vec!["c"], vec!["a"]));
+        assert_eq!(tag_changes(&old, &old), (vec![], vec![]));
+    }
+}
@@ -3,13 +3,67 @@ use serde::Deserialize;
 use std::fmt::Display;
 use chrono::{Utc, TimeZone};
 
+use crate::audit::AuditEntry;
+use crate::clicks::ClickSeries;
+
 #[derive(Deserialize, Debug)]
 pub struct Link {
     title: Option<String>,
     #[serde(rename = "link_id")]
     link_id: String,
     clicks: u32,
+    #[serde(default)]
+    bot_clicks: u32,
     timestamp: i64,
+    // Defaulted so a response from before link expiry existed still renders.
+    #[serde(default)]
+    expires_at: Option<i64>,
+    #[serde(default)]
+    max_clicks: Option<u32>,
+    #[serde(default)]
+    expired: bool,
+    #[serde(default)]
+    password_protected: bool,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(default)]
+    preview: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    click_history: Option<ClickSeries>,
+}
+
+// Sparkline drawing box, in SVG user units; the <svg> scales it to the cell.
+const SPARKLINE_WIDTH: f64 = 100.0;
+const SPARKLINE_HEIGHT: f64 = 20.0;
+
+impl Link {
+    /// `points` for the row's sparkline `<polyline>`, or `None` without history.
+    ///
+    /// Scaled to the busiest bucket of this link alone, so the line shows the shape of
+    /// its traffic rather than its volume next to other links -- the count beside it
+    /// already says that. A link with no clicks in the window draws a flat baseline.
+    pub fn sparkline_points(&self) -> Option<String> {
+        let counts = &self.click_history.as_ref()?.counts;
+        let max = f64::from(counts.iter().copied().max()?.max(1));
+        let step = SPARKLINE_WIDTH / (counts.len().max(2) - 1) as f64;
+
+        let points: Vec<String> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                let x = i as f64 * step;
+                let y = SPARKLINE_HEIGHT - f64::from(c) / max * SPARKLINE_HEIGHT;
+                format!("{x:.1},{y:.1}")
+            })
+            .collect();
+        Some(points.join(" "))
+    }
+
+    pub fn clicks_in_window(&self) -> Option<u64> {
+        self.click_history.as_ref().map(ClickSeries::total)
+    }
 }
 
 #[derive(Template, Debug)]
@@ -18,6 +72,16 @@ pub struct LinksTable {
     pub links: Vec<Link>,
     pub domain: &'static str,
     pub has_more: bool,
+    /// Where the next page starts, when there is one.
+    pub cursor: String,
+    /// The click history window, carried into the next-page request so later pages
+    /// draw the same range as the first.
+    pub days: u32,
+    /// The page size, carried along so later pages are as long as the first.
+    pub limit: u32,
+    /// The search, as `&`-prefixed query parameters, carried into the next-page request
+    /// for the same reason (see `LinkFilter::query_string`).
+    pub filter: String,
 }
 
 mod filters {
@@ -36,6 +100,66 @@ mod filters {
     }
 }
 
+// --- Expired link page
+//
+// A whole page rather than a fragment: this is what a visitor following a dead link
+// lands on, not something htmx swaps into the dashboard.
+
+#[derive(Template, Debug)]
+#[template(path = "link_expired.html")]
+pub struct LinkExpired<'a> {
+    pub link_id: String,
+    pub domain: &'a str,
+}
+
+// --- Disabled link warning
+//
+// What a visitor gets instead of the redirect once a rescan has flagged the destination.
+
+#[derive(Template, Debug)]
+#[template(path = "link_disabled.html")]
+pub struct LinkDisabled<'a> {
+    pub link_id: String,
+    pub domain: &'a str,
+    pub destination: String,
+    /// Who flagged it, as the reputation provider names itself.
+    pub reason: String,
+}
+
+// --- Link preview
+//
+// Stands in for the redirect on a preview-mode link, or on any link asked for with a
+// trailing `+`, so a visitor can see where it goes before going.
+
+#[derive(Template, Debug)]
+#[template(path = "link_preview.html")]
+pub struct LinkPreview<'a> {
+    pub link_id: String,
+    pub domain: &'a str,
+    pub destination: String,
+    /// `None` when the destination does not parse as a URL with a host.
+    pub host: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+// --- Password prompt
+//
+// Also a whole page: it stands in for the redirect until the visitor posts the right
+// passphrase back to the same URL.
+
+#[derive(Template, Debug)]
+#[template(path = "link_password.html")]
+pub struct LinkPassword<'a> {
+    pub link_id: String,
+    pub domain: &'a str,
+    /// Posts back to the preview rather than the redirect.
+    pub preview: bool,
+    /// Set when re-rendering after a wrong passphrase.
+    pub failed: bool,
+}
+
 // --- New Link popup
 
 #[derive(Template, Debug)]
@@ -74,6 +198,12 @@ pub struct ApiKeyRow {
     pub label: String,
     pub last_used_at: Option<i64>,
     pub expires_at: Option<i64>,
+    /// Wire names, e.g. `links:read`.
+    pub scopes: Vec<String>,
+    pub rate_limit_per_minute: u32,
+    /// Prefix of the successor, once the key has been rotated; `expires_at` is then the
+    /// end of the overlap.
+    pub replaced_by: Option<String>,
 }
 
 #[derive(Template, Debug)]
@@ -89,6 +219,28 @@ pub struct NewApiKey {
     pub key: String,
     pub label: String,
     pub expires_at: Option<i64>,
+    pub scopes: Vec<String>,
+    /// Set when this key is the successor of a rotation.
+    pub replaces: Option<ReplacedKey>,
+}
+
+/// The key a rotation retired, and when it stops working.
+#[derive(Debug)]
+pub struct ReplacedKey {
+    pub prefix: String,
+    pub retires_at: i64,
+}
+
+// --- Audit log
+//
+// A page of the owner's own security events. Each page ends with the control that
+// fetches the next one and replaces itself with it, as the links table does.
+
+#[derive(Template, Debug)]
+#[template(path = "audit_log.html")]
+pub struct AuditLogPage {
+    pub events: Vec<AuditEntry>,
+    pub next_cursor: Option<String>,
 }
 
 #[cfg(test)]
@@ -115,6 +267,10 @@ mod tests {
             links: vec![link(Some("Example"), "abc1234", 42, 1_739_035_776)],
             domain: "krtk.rs/",
             has_more: false,
+            cursor: String::new(),
+            days: 7,
+            limit: 20,
+            filter: String::new(),
         };
 
         let rendered = table.render().expect("LinksTable should render");
@@ -130,6 +286,10 @@ mod tests {
             links: vec![link(Some("Example"), "abc1234", 42, 1_739_035_776)],
             domain: "krtk.rs/",
             has_more: false,
+            cursor: String::new(),
+            days: 7,
+            limit: 20,
+            filter: String::new(),
         };
 
         let rendered = table.render().expect("LinksTable should render");
@@ -147,14 +307,183 @@ mod tests {
             links: vec![link(None, "zzz9999", 0, 1_739_035_776)],
             domain: "krtk.rs/",
             has_more: true,
+            cursor: "eyJpIjoienp6OTk5OSJ9.c2ln".to_string(),
+            days: 7,
+            limit: 50,
+            filter: "&q=spring&sort=clicks".to_string(),
         };
 
         let rendered = table.render().expect("LinksTable should render");
         assert!(rendered.contains("hx-trigger=\"revealed\""));
-        assert!(rendered.contains("last_evaluated_id=zzz9999"));
+        assert!(rendered.contains("cursor=eyJpIjoienp6OTk5OSJ9.c2ln"), "got: {rendered}");
+        assert!(rendered.contains("days=7"), "the next page must keep the history window");
+        assert!(rendered.contains("limit=50"), "and the page size, got: {rendered}");
+        assert!(rendered.contains("q=spring") && rendered.contains("sort=clicks"), "nor the search, got: {rendered}");
         assert!(!rendered.contains("All items loaded"));
     }
 
+    #[test]
+    fn links_table_rows_carry_a_delete_control_that_removes_their_own_row() {
+        let table = LinksTable {
+            links: vec![link(Some("Example"), "abc1234", 42, 1_739_035_776)],
+            domain: "krtk.rs/",
+            has_more: false,
+            cursor: String::new(),
+            days: 7,
+            limit: 20,
+            filter: String::new(),
+        };
+
+        let rendered = table.render().expect("LinksTable should render");
+        assert!(rendered.contains("hx-delete=\"/api/links/abc1234\""), "got: {rendered}");
+        // The handler answers an empty fragment, which only removes the row when the
+        // target is the row itself and the swap replaces it outright.
+        assert!(rendered.contains("hx-target=\"closest tr\""));
+        assert!(rendered.contains("hx-swap=\"outerHTML\""));
+    }
+
+    #[test]
+    fn links_table_marks_expired_links_and_shows_limits() {
+        let expired: Link = serde_json::from_str(
+            r#"{"title":null,"link_id":"gone123","clicks":5,"timestamp":1739035776,
+                "expires_at":1739035776,"max_clicks":5,"expired":true}"#,
+        )
+        .unwrap();
+        let rendered = LinksTable { links: vec![expired], domain: "krtk.rs/", has_more: false, cursor: String::new(), days: 7, limit: 20, filter: String::new() }
+            .render()
+            .expect("LinksTable should render");
+        assert!(rendered.contains("expired"), "got: {rendered}");
+        assert!(rendered.contains("5 / 5"), "got: {rendered}");
+        assert!(rendered.contains("2025-02-08 17:29:36 UTC"));
+    }
+
+    #[test]
+    fn bot_hits_are_mentioned_but_not_counted() {
+        let link: Link = serde_json::from_str(
+            r#"{"title":null,"link_id":"abc1234","clicks":3,"bot_clicks":12,"timestamp":1739035776}"#,
+        )
+        .unwrap();
+        let rendered = LinksTable { links: vec![link], domain: "krtk.rs/", has_more: false, cursor: String::new(), days: 7, limit: 20, filter: String::new() }
+            .render()
+            .expect("LinksTable should render");
+        assert!(rendered.contains(">3</span>"), "got: {rendered}");
+        assert!(rendered.contains("Not counting 12 bot and link-preview hits"), "got: {rendered}");
+    }
+
+    #[test]
+    fn tags_render_as_chips_that_filter_the_list() {
+        let tagged: Link = serde_json::from_str(
+            r#"{"title":null,"link_id":"abc1234","clicks":0,"timestamp":1739035776,"tags":["clients/acme","launch"]}"#,
+        )
+        .unwrap();
+        let rendered = LinksTable { links: vec![tagged], domain: "krtk.rs/", has_more: false, cursor: String::new(), days: 7, limit: 20, filter: String::new() }
+            .render()
+            .expect("LinksTable should render");
+        assert!(rendered.contains(r#"data-tag="clients/acme""#), "got: {rendered}");
+        assert!(rendered.contains(r#"data-tag="launch" onclick="filterByTag(this.dataset.tag)""#), "got: {rendered}");
+
+        let untagged = LinksTable { links: vec![link(None, "abc1234", 0, 1_739_035_776)], domain: "krtk.rs/", has_more: false, cursor: String::new(), days: 7, limit: 20, filter: String::new() }
+            .render()
+            .unwrap();
+        assert!(!untagged.contains("filterByTag"));
+    }
+
+    #[test]
+    fn sparkline_scales_to_the_busiest_bucket() {
+        let with_history: Link = serde_json::from_str(
+            r#"{"title":null,"link_id":"abc1234","clicks":6,"timestamp":1739035776,
+                "click_history":{"days":7,"start":0,"bucket_secs":3600,"counts":[0,4,2]}}"#,
+        )
+        .unwrap();
+        assert_eq!(with_history.sparkline_points().as_deref(), Some("0.0,20.0 50.0,0.0 100.0,10.0"));
+        assert_eq!(with_history.clicks_in_window(), Some(6));
+
+        let rendered = LinksTable { links: vec![with_history], domain: "krtk.rs/", has_more: false, cursor: String::new(), days: 7, limit: 20, filter: String::new() }
+            .render()
+            .expect("LinksTable should render");
+        assert!(rendered.contains("<polyline"), "got: {rendered}");
+        assert!(rendered.contains("6 in 7d"), "got: {rendered}");
+    }
+
+    #[test]
+    fn a_link_without_history_draws_no_sparkline() {
+        let plain = link(None, "abc1234", 0, 1_739_035_776);
+        assert!(plain.sparkline_points().is_none());
+        let rendered = LinksTable { links: vec![plain], domain: "krtk.rs/", has_more: false, cursor: String::new(), days: 7, limit: 20, filter: String::new() }
+            .render()
+            .expect("LinksTable should render");
+        assert!(!rendered.contains("<polyline"));
+    }
+
+    #[test]
+    fn link_expired_page_names_the_link() {
+        let rendered = LinkExpired { link_id: "gone123".to_string(), domain: "krtk.rs/" }
+            .render()
+            .expect("LinkExpired should render");
+        assert!(rendered.contains("krtk.rs/gone123"));
+        assert!(rendered.contains("expired"));
+    }
+
+    #[test]
+    fn disabled_link_page_shows_the_destination_without_linking_to_it() {
+        let rendered = LinkDisabled {
+            link_id: "bad1234".to_string(),
+            domain: "krtk.rs/",
+            destination: "https://phish.example/login".to_string(),
+            reason: "Google Safe Browsing".to_string(),
+        }
+        .render()
+        .expect("LinkDisabled should render");
+        assert!(rendered.contains("krtk.rs/bad1234"));
+        assert!(rendered.contains("https://phish.example/login"));
+        assert!(!rendered.contains(r#"href="https://phish.example"#), "got: {rendered}");
+    }
+
+    #[test]
+    fn preview_page_shows_the_host_and_links_on() {
+        let rendered = LinkPreview {
+            link_id: "abc1234".to_string(),
+            domain: "krtk.rs/",
+            destination: "https://example.com/post?id=1&ref=x".to_string(),
+            host: Some("example.com".to_string()),
+            title: Some("An <example>".to_string()),
+            description: None,
+            image: None,
+        }
+        .render()
+        .expect("LinkPreview should render");
+        assert!(rendered.contains("krtk.rs/abc1234"));
+        assert!(rendered.contains(">example.com<"), "got: {rendered}");
+        assert!(rendered.contains("An &#60;example&#62;"), "scraped text must be escaped, got: {rendered}");
+        assert!(rendered.contains("https://example.com/post?id=1&#38;ref=x"), "got: {rendered}");
+        assert!(rendered.contains(r#"href="/abc1234?continue""#), "continue goes back through the link, got: {rendered}");
+        assert!(!rendered.contains("<img src=\"http"), "no image was scraped");
+    }
+
+    #[test]
+    fn password_prompt_posts_back_to_the_link() {
+        let rendered = LinkPassword { link_id: "docs123".to_string(), domain: "krtk.rs/", preview: false, failed: false }
+            .render()
+            .expect("LinkPassword should render");
+        assert!(rendered.contains(r#"action="/docs123""#), "got: {rendered}");
+        assert!(rendered.contains(r#"method="post""#));
+        assert!(rendered.contains(r#"name="password""#));
+        assert!(!rendered.contains("Incorrect password"));
+
+        let preview = LinkPassword { link_id: "docs123".to_string(), domain: "krtk.rs/", preview: true, failed: false }
+            .render()
+            .expect("LinkPassword should render");
+        assert!(preview.contains(r#"action="/docs123+""#), "got: {preview}");
+    }
+
+    #[test]
+    fn password_prompt_reports_a_wrong_attempt() {
+        let rendered = LinkPassword { link_id: "docs123".to_string(), domain: "krtk.rs/", preview: false, failed: true }
+            .render()
+            .expect("LinkPassword should render");
+        assert!(rendered.contains("Incorrect password"));
+    }
+
     #[test]
     fn new_short_link_renders_the_full_url() {
         let rendered = NewShortLink { link: "abc1234".to_string(), domain: "krtk.rs/" }
@@ -184,6 +513,9 @@ mod tests {
             label: label.to_string(),
             last_used_at,
             expires_at,
+            scopes: vec!["links:read".to_string(), "analytics:read".to_string()],
+            rate_limit_per_minute: 60,
+            replaced_by: None,
         }
     }
 
@@ -260,6 +592,8 @@ mod tests {
             key: "krtk_abc123".to_string(),
             label: "laptop CLI".to_string(),
             expires_at: Some(1_739_035_776),
+            scopes: vec!["links:create".to_string()],
+            replaces: None,
         }
         .render()
         .expect("NewApiKey should render");
@@ -268,6 +602,7 @@ mod tests {
         assert!(rendered.contains("expires 2025-02-08 17:29:36 UTC"));
         // The copy control reuses the page's existing helper rather than a second one.
         assert!(rendered.contains("copyToClipboard('krtk_abc123')"));
+        assert!(rendered.contains("links:create"));
     }
 
     #[test]
@@ -276,10 +611,101 @@ mod tests {
             key: "krtk_abc123".to_string(),
             label: "forever".to_string(),
             expires_at: None,
+            scopes: vec!["links:read".to_string()],
+            replaces: None,
         }
         .render()
         .expect("NewApiKey should render");
         assert!(rendered.contains("no expiry"));
         assert!(!rendered.contains("1970"));
     }
+
+    #[test]
+    fn a_rotated_key_shows_its_pending_retirement() {
+        let mut row = key_row("ci runner", None, Some(1_739_035_776));
+        row.replaced_by = Some("krtk_9Zq2mPx".to_string());
+        let rendered = ApiKeysList { keys: vec![row] }.render().expect("ApiKeysList should render");
+        assert!(rendered.contains("retires 2025-02-08 17:29:36 UTC"), "got: {rendered}");
+        assert!(rendered.contains("krtk_9Zq2mPx"));
+        assert!(rendered.contains("60 requests/min"));
+        // Rotating it again is refused server-side; the page does not offer it.
+        assert!(!rendered.contains("/rotate"));
+    }
+
+    #[test]
+    fn a_live_key_offers_rotation() {
+        let rendered = ApiKeysList { keys: vec![key_row("ci runner", None, None)] }
+            .render()
+            .expect("ApiKeysList should render");
+        assert!(rendered.contains(&format!("hx-post=\"/api/keys/{}/rotate\"", "a".repeat(64))));
+    }
+
+    #[test]
+    fn a_successor_key_names_the_key_it_replaces() {
+        let rendered = NewApiKey {
+            key: "krtk_abc123".to_string(),
+            label: "ci runner".to_string(),
+            expires_at: None,
+            scopes: vec!["links:create".to_string()],
+            replaces: Some(ReplacedKey { prefix: "krtk_oldoldo".to_string(), retires_at: 1_739_035_776 }),
+        }
+        .render()
+        .expect("NewApiKey should render");
+        assert!(rendered.contains("krtk_oldoldo"));
+        assert!(rendered.contains("2025-02-08 17:29:36 UTC"));
+    }
+
+    // -----------------------------------------------------------------------
+    // Audit log
+    // -----------------------------------------------------------------------
+
+    fn audit_entry(action: &str, forwarded_for: Option<&str>) -> AuditEntry {
+        AuditEntry {
+            event_id: "1739035776123-00c0ffee".to_string(),
+            action: action.to_string(),
+            at: 1_739_035_776,
+            auth_method: Some("apikey".to_string()),
+            target: Some("abc1234".to_string()),
+            source_ip: Some("130.176.0.1".to_string()),
+            forwarded_for: forwarded_for.map(str::to_string),
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn audit_events_render_what_when_and_where() {
+        let rendered = AuditLogPage {
+            events: vec![audit_entry("link.deleted", Some("203.0.113.7"))],
+            next_cursor: None,
+        }
+        .render()
+        .expect("AuditLogPage should render");
+        assert!(rendered.contains("Link deleted"), "got: {rendered}");
+        assert!(rendered.contains("abc1234"));
+        assert!(rendered.contains("2025-02-08 17:29:36 UTC"));
+        assert!(rendered.contains(">130.176.0.1<"), "the connection address is the one shown");
+        assert!(rendered.contains("(claims 203.0.113.7, unverified)"));
+        assert!(!rendered.contains("hx-get"), "the last page has no next-page control");
+    }
+
+    #[test]
+    fn an_audit_page_with_more_fetches_the_next_in_place() {
+        let rendered = AuditLogPage {
+            events: vec![audit_entry("key.exploded", None)],
+            next_cursor: Some("1739035776123-00c0ffee".to_string()),
+        }
+        .render()
+        .expect("AuditLogPage should render");
+        assert!(rendered.contains(r#"hx-get="/api/audit?cursor=1739035776123-00c0ffee""#), "got: {rendered}");
+        assert!(rendered.contains(r#"hx-swap="outerHTML""#));
+        assert!(rendered.contains("key.exploded"), "an unknown action shows its wire name");
+    }
+
+    #[test]
+    fn an_empty_audit_log_says_so() {
+        let rendered = AuditLogPage { events: vec![], next_cursor: None }
+            .render()
+            .expect("AuditLogPage should render");
+        assert!(rendered.contains("No activity yet."));
+    }
 }
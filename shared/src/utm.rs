@@ -0,0 +1,314 @@
+//! Campaign (UTM) parameters, and the per-owner presets they can be saved as.
+//!
+//! A create or edit request may carry a `utm` object instead of a hand-assembled query
+//! string. It is merged into the destination during validation, so everything after
+//! that -- the recursion and reputation checks, `normalize_url`, storage -- sees the
+//! final URL and nothing downstream has to know the parameters were given separately.
+//!
+//! Presets live in a table of their own: `OwnerId` is the owner key (see
+//! [`owner_key`]) and `Name` the preset's name, so one owner's presets are a `Query` on
+//! their partition and no other owner's are ever read.
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client;
+use lambda_http::tracing;
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded;
+
+use crate::core::owner_key;
+use crate::error::AppError;
+
+// Generous for a campaign name, and keeps one link from carrying a novel in its query.
+const UTM_VALUE_MAX_LEN: usize = 128;
+const PRESET_NAME_MAX_LEN: usize = 32;
+
+/// Enough for a preset per channel and campaign; beyond that the picker is a list
+/// nobody scrolls, and the table one nobody bounds.
+pub const MAX_PRESETS_PER_OWNER: usize = 50;
+
+/// The five standard UTM parameters. Each is optional; a blank one counts as absent.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Utm {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+impl Utm {
+    /// Trims every value, drops the blank ones and bounds the rest.
+    pub fn normalized(self) -> Result<Self, AppError> {
+        let normalize = |name: &str, value: Option<String>| -> Result<Option<String>, AppError> {
+            let Some(value) = value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty()) else {
+                return Ok(None);
+            };
+            if value.chars().count() > UTM_VALUE_MAX_LEN || value.chars().any(char::is_control) {
+                return Err(AppError::Validation(format!(
+                    "utm_{name} must be at most {UTM_VALUE_MAX_LEN} printable characters"
+                )));
+            }
+            Ok(Some(value))
+        };
+        Ok(Self {
+            source: normalize("source", self.source)?,
+            medium: normalize("medium", self.medium)?,
+            campaign: normalize("campaign", self.campaign)?,
+            term: normalize("term", self.term)?,
+            content: normalize("content", self.content)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parameters().next().is_none()
+    }
+
+    /// `(query parameter, value)` for each parameter that is set, in the usual order.
+    fn parameters(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("utm_source", &self.source),
+            ("utm_medium", &self.medium),
+            ("utm_campaign", &self.campaign),
+            ("utm_term", &self.term),
+            ("utm_content", &self.content),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+    }
+
+    /// `url` with these parameters in its query string.
+    ///
+    /// Every other parameter is kept byte for byte, in its place, and so is the
+    /// fragment. A parameter set here replaces one of the same name already in the URL
+    /// rather than appearing twice, which analytics tools disagree on how to read.
+    pub fn apply(&self, url: &str) -> Result<String, AppError> {
+        let mut parsed =
+            url::Url::parse(url).map_err(|_| AppError::Validation("Invalid URL Provided".to_string()))?;
+        if self.is_empty() {
+            return Ok(url.to_string());
+        }
+
+        let replaced: Vec<&str> = self.parameters().map(|(name, _)| name).collect();
+        let mut query: Vec<String> = parsed
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .filter(|pair| {
+                let name = form_urlencoded::parse(pair.as_bytes()).next().map(|(name, _)| name);
+                !name.is_some_and(|name| replaced.contains(&name.as_ref()))
+            })
+            .map(str::to_string)
+            .collect();
+        query.push(form_urlencoded::Serializer::new(String::new()).extend_pairs(self.parameters()).finish());
+
+        parsed.set_query(Some(&query.join("&")));
+        Ok(parsed.into())
+    }
+}
+
+/// Normalizes a preset name: trimmed and lowercased, letters, digits, `-` and `_`.
+///
+/// The name is a path segment of `/api/utm-presets/{name}`, so nothing in it needs
+/// escaping.
+pub fn normalize_preset_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty()
+        || name.len() > PRESET_NAME_MAX_LEN
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    {
+        return Err(AppError::Validation(format!(
+            "A preset name must be 1 to {PRESET_NAME_MAX_LEN} letters, digits, '-' or '_'"
+        )));
+    }
+    Ok(name)
+}
+
+/// Whether an owner who has `existing` can save a preset called `name`: replacing one
+/// they have always fits, a new one only below [`MAX_PRESETS_PER_OWNER`].
+///
+/// Checked against a listing rather than in the write, so two saves racing each other
+/// can end one or two over. The cap is there to bound the list, not to the exact item.
+pub fn check_preset_room(existing: &[UtmPreset], name: &str) -> Result<(), AppError> {
+    if existing.len() >= MAX_PRESETS_PER_OWNER && !existing.iter().any(|preset| preset.name == name) {
+        return Err(AppError::Validation(format!(
+            "You can have at most {MAX_PRESETS_PER_OWNER} UTM presets; delete one to save another"
+        )));
+    }
+    Ok(())
+}
+
+/// A saved set of UTM parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtmPreset {
+    pub name: String,
+    pub utm: Utm,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PresetRow {
+    #[serde(rename = "OwnerId")]
+    partition: String,
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Utm")]
+    utm: Utm,
+}
+
+#[derive(Debug)]
+pub struct UtmPresets {
+    dynamodb_preset_table: String,
+    dynamodb_client: Client,
+}
+
+impl UtmPresets {
+    pub fn new(dynamodb_preset_table: &str, dynamodb_client: Client) -> Self {
+        Self {
+            dynamodb_preset_table: dynamodb_preset_table.to_string(),
+            dynamodb_client,
+        }
+    }
+
+    /// Every preset `owner_sub` has saved, by name.
+    pub async fn list(&self, owner_sub: &str) -> Result<Vec<UtmPreset>, AppError> {
+        let mut presets = vec![];
+        let mut start = None;
+
+        loop {
+            let result = self
+                .dynamodb_client
+                .query()
+                .table_name(&self.dynamodb_preset_table)
+                .key_condition_expression("OwnerId = :owner")
+                .expression_attribute_values(":owner", AttributeValue::S(owner_key(owner_sub)))
+                .set_exclusive_start_key(start)
+                .send()
+                .await
+                .map_err(|e| {
+                    tracing::error!("Error querying UTM presets: {:?}", e);
+                    AppError::database(e)
+                })?;
+            for item in result.items() {
+                let row: PresetRow = serde_dynamo::from_item(item.clone()).map_err(AppError::database)?;
+                presets.push(UtmPreset { name: row.name, utm: row.utm });
+            }
+            match result.last_evaluated_key {
+                Some(key) => start = Some(key),
+                // The sort key is the name, so the query already returned them in order.
+                None => return Ok(presets),
+            }
+        }
+    }
+
+    /// Saves `preset` for `owner_sub`, replacing any preset of the same name.
+    pub async fn save(&self, owner_sub: &str, preset: &UtmPreset) -> Result<(), AppError> {
+        let row = PresetRow {
+            partition: owner_key(owner_sub),
+            name: preset.name.clone(),
+            utm: preset.utm.clone(),
+        };
+        self.dynamodb_client
+            .put_item()
+            .table_name(&self.dynamodb_preset_table)
+            .set_item(Some(serde_dynamo::to_item(row).map_err(AppError::database)?))
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error saving UTM preset {}: {:?}", preset.name, e);
+                AppError::database(e)
+            })?;
+        Ok(())
+    }
+
+    /// Deletes `owner_sub`'s preset `name`; `false` if they had none by that name.
+    pub async fn delete(&self, owner_sub: &str, name: &str) -> Result<bool, AppError> {
+        let result = self
+            .dynamodb_client
+            .delete_item()
+            .table_name(&self.dynamodb_preset_table)
+            .key("OwnerId", AttributeValue::S(owner_key(owner_sub)))
+            .key("Name", AttributeValue::S(name.to_string()))
+            .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error deleting UTM preset {name}: {:?}", e);
+                AppError::database(e)
+            })?;
+        Ok(result.attributes.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utm(source: &str, medium: &str, campaign: &str) -> Utm {
+        Utm {
+            source: Some(source.to_string()),
+            medium: Some(medium.to_string()),
+            campaign: Some(campaign.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parameters_are_encoded_after_the_existing_ones_and_before_the_fragment() {
+        let merged = utm("newsletter", "email", "spring sale & more")
+            .apply("https://example.com/shop?ref=a%2Fb&x#offers")
+            .unwrap();
+        assert_eq!(
+            merged,
+            "https://example.com/shop?ref=a%2Fb&x&utm_source=newsletter&utm_medium=email\
+             &utm_campaign=spring+sale+%26+more#offers"
+        );
+    }
+
+    #[test]
+    fn a_parameter_already_in_the_url_is_replaced_not_repeated() {
+        let merged = utm("newsletter", "email", "q3")
+            .apply("https://example.com/?utm_source=twitter&utm_term=shoes&page=2")
+            .unwrap();
+        assert_eq!(
+            merged,
+            "https://example.com/?utm_term=shoes&page=2&utm_source=newsletter&utm_medium=email&utm_campaign=q3"
+        );
+    }
+
+    #[test]
+    fn blank_values_are_dropped_and_long_ones_refused() {
+        let blank = Utm { source: Some("  ".to_string()), ..Default::default() }.normalized().unwrap();
+        assert!(blank.is_empty());
+        assert_eq!(blank.apply("https://example.com/a?b").unwrap(), "https://example.com/a?b");
+
+        let trimmed = Utm { campaign: Some(" q3 ".to_string()), ..Default::default() }.normalized().unwrap();
+        assert_eq!(trimmed.campaign.as_deref(), Some("q3"));
+
+        let long = Utm { term: Some("x".repeat(UTM_VALUE_MAX_LEN + 1)), ..Default::default() };
+        assert!(matches!(long.normalized(), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn preset_names_are_path_safe() {
+        assert_eq!(normalize_preset_name(" Newsletter_2026 ").unwrap(), "newsletter_2026");
+        for bad in ["", "a/b", "two words", "50%", &"x".repeat(PRESET_NAME_MAX_LEN + 1)] {
+            assert!(normalize_preset_name(bad).is_err(), "accepted {bad:?}");
+        }
+    }
+
+    #[test]
+    fn a_full_preset_list_takes_replacements_only() {
+        let full: Vec<UtmPreset> = (0..MAX_PRESETS_PER_OWNER)
+            .map(|i| UtmPreset { name: format!("preset-{i}"), utm: utm("a", "b", "c") })
+            .collect();
+        assert!(check_preset_room(&full[1..], "new").is_ok());
+        assert!(check_preset_room(&full, "preset-7").is_ok());
+        assert!(matches!(check_preset_room(&full, "new"), Err(AppError::Validation(_))));
+    }
+}
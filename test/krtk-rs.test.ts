@@ -29,6 +29,8 @@ function synthKrtkRsStack(): Template {
     certificateArn: certStack.certificate.certificateArn,
     authCertificateArn: certStack.authCertificate.certificateArn,
     googleApiKeySecret: secretsStack.googleApiSecret,
+    linkCookieSecret: secretsStack.linkCookieSecret,
+    cursorSecret: secretsStack.cursorSecret,
     crossRegionReferences: true,
   });
 
@@ -44,9 +46,9 @@ describe('KrtkRsStack', () => {
 
   describe('DynamoDB link table', () => {
     test('creates exactly one table with LinkId as the partition key', () => {
-      // Two tables now: the link table and the API key table. Pinning the count keeps
-      // an accidental third table visible rather than silently deployed.
-      template.resourceCountIs('AWS::DynamoDB::GlobalTable', 2);
+      // Pinning the count keeps an accidental extra table visible rather than silently
+      // deployed. The tag index is the sixth and the UTM presets the seventh.
+      template.resourceCountIs('AWS::DynamoDB::GlobalTable', 7);
       template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
         KeySchema: [{ AttributeName: 'LinkId', KeyType: 'HASH' }],
       });
@@ -76,18 +78,91 @@ describe('KrtkRsStack', () => {
         ]),
       });
     });
+
+    test('purges expired links via TTL on PurgeAt, not ExpiresAt', () => {
+      // PurgeAt trails ExpiresAt by a grace period so the 410 page has an item to render
+      // from. A TTL on ExpiresAt itself would race that page and usually win.
+      template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
+        KeySchema: [{ AttributeName: 'LinkId', KeyType: 'HASH' }],
+        TimeToLiveSpecification: { AttributeName: 'PurgeAt', Enabled: true },
+      });
+    });
+  });
+
+  describe('DynamoDB rate limit table', () => {
+    test('is keyed by key id, purges idle buckets, and only the authorizer gets it', () => {
+      template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
+        KeySchema: [{ AttributeName: 'KeyId', KeyType: 'HASH' }],
+        TimeToLiveSpecification: { AttributeName: 'PurgeAt', Enabled: true },
+      });
+
+      const functions = template.findResources('AWS::Lambda::Function', {
+        Properties: { Runtime: 'provided.al2023' },
+      });
+      const limited = Object.values(functions).filter(
+        (fn) => (fn as any).Properties.Environment.Variables.RATE_LIMIT_TABLE_NAME !== undefined,
+      );
+      expect(limited).toHaveLength(1);
+      expect((limited[0] as any).Properties.Environment.Variables.COGNITO_POOL_ID).toBeDefined();
+    });
+  });
+
+  describe('DynamoDB audit table', () => {
+    test('is keyed by owner and event id, and ages events out on PurgeAt', () => {
+      template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
+        KeySchema: [
+          { AttributeName: 'Owner', KeyType: 'HASH' },
+          { AttributeName: 'EventId', KeyType: 'RANGE' },
+        ],
+        TimeToLiveSpecification: { AttributeName: 'PurgeAt', Enabled: true },
+      });
+    });
+
+    test('every function that records or reads events is told where the table is', () => {
+      const functions = template.findResources('AWS::Lambda::Function', {
+        Properties: { Runtime: 'provided.al2023' },
+      });
+      const withAudit = Object.values(functions).filter(
+        (fn) => (fn as any).Properties.Environment.Variables.AUDIT_TABLE_NAME !== undefined,
+      );
+      // authorizer, manageKeys, createLink, batchCreateLink, deleteLink, getAudit and
+      // rescanLinks.
+      expect(withAudit).toHaveLength(7);
+    });
+  });
+
+  describe('DynamoDB click history table', () => {
+    test('is keyed by series and bucket start, and ages buckets out on PurgeAt', () => {
+      template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
+        KeySchema: [
+          { AttributeName: 'Series', KeyType: 'HASH' },
+          { AttributeName: 'BucketStart', KeyType: 'RANGE' },
+        ],
+        TimeToLiveSpecification: { AttributeName: 'PurgeAt', Enabled: true },
+      });
+    });
+
+    test('only processAnalytics and getLinks know about it', () => {
+      const functions = template.findResources('AWS::Lambda::Function', {
+        Properties: { Runtime: 'provided.al2023' },
+      });
+      const withClicks = Object.values(functions).filter(
+        (fn) => (fn as any).Properties.Environment.Variables.CLICK_TABLE_NAME !== undefined,
+      );
+      expect(withClicks).toHaveLength(2);
+    });
   });
 
   describe('Lambda functions', () => {
-    test('creates the six application functions on provided.al2023', () => {
+    test('creates the fourteen application functions on provided.al2023', () => {
       // The stack also synthesizes CDK-managed helper functions (bucket
       // deployment, auto-delete-objects), so assert on the custom runtime
       // rather than a bare resourceCountIs over every function.
       const functions = template.findResources('AWS::Lambda::Function', {
         Properties: { Runtime: 'provided.al2023' },
       });
-      // Six now: the four link functions plus the authorizer and manage_keys.
-      expect(Object.keys(functions)).toHaveLength(6);
+      // Fourteen now: eleven as before plus index_tags, manage_tags and manage_utm_presets.
+      expect(Object.keys(functions)).toHaveLength(14);
     });
 
     test('every LINK function receives TABLE_NAME and SHORTENER_DOMAIN', () => {
@@ -101,7 +176,8 @@ describe('KrtkRsStack', () => {
       const linkFunctions = Object.values(functions).filter(
         (fn) => (fn as any).Properties.Environment.Variables.TABLE_NAME !== undefined,
       );
-      expect(linkFunctions).toHaveLength(4);
+      // manage_tags is one: renaming a tag rewrites the links it is on.
+      expect(linkFunctions).toHaveLength(9);
 
       for (const fn of linkFunctions) {
         const env = (fn as any).Properties.Environment.Variables;
@@ -122,21 +198,104 @@ describe('KrtkRsStack', () => {
       }
     });
 
-    test('createLink is granted read access to the Google API key secret', () => {
+    test('the functions that run Safe Browsing are granted read access to the Google API key secret', () => {
       const functions = template.findResources('AWS::Lambda::Function', {
         Properties: { Runtime: 'provided.al2023' },
       });
       const withSecret = Object.values(functions).filter(
         (fn) => (fn as any).Properties.Environment.Variables.GOOGLE_API_KEY_SECRET !== undefined,
       );
-      expect(withSecret).toHaveLength(1);
+      // All three run the Safe Browsing check: an edit or a batch is validated exactly
+      // like a single create. The fourth is the scheduled rescan.
+      expect(withSecret).toHaveLength(4);
+    });
+
+    test('rescanLinks runs once a day on a schedule', () => {
+      template.resourceCountIs('AWS::Events::Rule', 1);
+      template.hasResourceProperties('AWS::Events::Rule', {
+        ScheduleExpression: 'rate(1 day)',
+      });
+    });
+
+    test('only visitLink can read the link cookie signing secret', () => {
+      const functions = template.findResources('AWS::Lambda::Function', {
+        Properties: { Runtime: 'provided.al2023' },
+      });
+      const withCookieSecret = Object.values(functions).filter(
+        (fn) => (fn as any).Properties.Environment.Variables.LINK_COOKIE_SECRET !== undefined,
+      );
+      expect(withCookieSecret).toHaveLength(1);
+    });
+
+    test('only getLinks can read the pagination cursor signing secret', () => {
+      const functions = template.findResources('AWS::Lambda::Function', {
+        Properties: { Runtime: 'provided.al2023' },
+      });
+      const withCursorSecret = Object.values(functions).filter(
+        (fn) => (fn as any).Properties.Environment.Variables.CURSOR_SECRET !== undefined,
+      );
+      expect(withCursorSecret).toHaveLength(1);
     });
 
     test('processAnalytics is wired to the Kinesis stream via an event source mapping', () => {
-      template.resourceCountIs('AWS::Lambda::EventSourceMapping', 1);
+      // The other mapping is indexTags on the link table's stream.
+      template.resourceCountIs('AWS::Lambda::EventSourceMapping', 2);
       template.hasResourceProperties('AWS::Lambda::EventSourceMapping', {
-        BatchSize: 1,
+        BatchSize: 500,
+        MaximumBatchingWindowInSeconds: 5,
         StartingPosition: 'TRIM_HORIZON',
+        FunctionResponseTypes: ['ReportBatchItemFailures'],
+        MaximumRetryAttempts: 10,
+      });
+    });
+
+    test("indexTags follows the link table's stream with both images", () => {
+      template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
+        KeySchema: [{ AttributeName: 'LinkId', KeyType: 'HASH' }],
+        StreamSpecification: { StreamViewType: 'NEW_AND_OLD_IMAGES' },
+      });
+      template.hasResourceProperties('AWS::Lambda::EventSourceMapping', {
+        BatchSize: 100,
+        StartingPosition: 'TRIM_HORIZON',
+        FunctionResponseTypes: ['ReportBatchItemFailures'],
+        MaximumRetryAttempts: 10,
+      });
+    });
+
+    test('UTM presets are keyed by owner, then name, and only their function sees them', () => {
+      template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
+        KeySchema: [
+          { AttributeName: 'OwnerId', KeyType: 'HASH' },
+          { AttributeName: 'Name', KeyType: 'RANGE' },
+        ],
+      });
+      const functions = template.findResources('AWS::Lambda::Function', {
+        Properties: { Runtime: 'provided.al2023' },
+      });
+      const withPresets = Object.values(functions).filter(
+        (fn) => (fn as any).Properties.Environment.Variables.UTM_PRESET_TABLE_NAME !== undefined,
+      );
+      expect(withPresets).toHaveLength(1);
+      expect((withPresets[0] as any).Properties.Environment.Variables.TABLE_NAME).toBeUndefined();
+    });
+
+    test('the tag index is keyed by owner, then tag and link', () => {
+      template.hasResourceProperties('AWS::DynamoDB::GlobalTable', {
+        KeySchema: [
+          { AttributeName: 'OwnerId', KeyType: 'HASH' },
+          { AttributeName: 'TagLink', KeyType: 'RANGE' },
+        ],
+      });
+    });
+
+    test('processAnalytics is told the real-time log field order', () => {
+      const config = template.findResources('AWS::CloudFront::RealtimeLogConfig');
+      const fields: string[] = (Object.values(config)[0] as any).Properties.Fields;
+      // Bot filtering classifies hits by user agent.
+      expect(fields).toContain('cs-user-agent');
+
+      template.hasResourceProperties('AWS::Lambda::Function', {
+        Environment: { Variables: Match.objectLike({ LOG_FIELDS: fields.join(',') }) },
       });
     });
   });
@@ -154,16 +313,28 @@ describe('KrtkRsStack', () => {
       });
     });
 
-    test('exposes exactly the six expected routes', () => {
+    test('exposes exactly the eighteen expected routes', () => {
       const routes = template.findResources('AWS::ApiGatewayV2::Route');
       const routeKeys = Object.values(routes).map((r) => (r as any).Properties.RouteKey).sort();
       expect(routeKeys).toEqual([
         'DELETE /api/keys/{keyId}',
+        'DELETE /api/links/{linkId}',
+        'DELETE /api/tags/{tag+}',
+        'DELETE /api/utm-presets/{name}',
+        'GET /api/audit',
         'GET /api/keys',
         'GET /api/links',
+        'GET /api/tags',
+        'GET /api/utm-presets',
         'GET /{linkId}',
+        'PATCH /api/links/{linkId}',
+        'PATCH /api/tags/{tag+}',
         'POST /api/keys',
+        'POST /api/keys/{keyId}/rotate',
         'POST /api/links',
+        'POST /api/links/batch',
+        'POST /{linkId}',
+        'PUT /api/utm-presets/{name}',
       ]);
     });
 
@@ -193,9 +364,10 @@ describe('KrtkRsStack', () => {
         const key: string = props.RouteKey;
         const refId = props.AuthorizerId?.Ref;
 
-        if (key.includes('/api/keys')) {
+        // The audit log records what API keys did, so it is JWT-only too.
+        if (key.includes('/api/keys') || key.includes('/api/audit')) {
           expect(refId).toBe(jwtId);
-        } else if (key.includes('/api/links')) {
+        } else if (['/api/links', '/api/tags', '/api/utm-presets'].some((p) => key.includes(p))) {
           expect(refId).toBe(requestId);
         } else {
           // The public redirect must carry no authorizer at all.
@@ -503,8 +675,8 @@ describe('KrtkRsStack', () => {
       const functions = template.findResources('AWS::Lambda::Function', {
         Properties: { Runtime: 'provided.al2023' },
       });
-      // Six now: the four link functions plus the authorizer and manage_keys.
-      expect(Object.keys(functions)).toHaveLength(6);
+      // Fourteen now: eleven as before plus index_tags, manage_tags and manage_utm_presets.
+      expect(Object.keys(functions)).toHaveLength(14);
       for (const fn of Object.values(functions)) {
         expect((fn as any).Properties.Architectures).toEqual(['arm64']);
       }
@@ -795,9 +967,13 @@ describe('SecretsStack', () => {
     const stack = new SecretsStack(app, 'TestSecretsStack', { env: TEST_ENV });
     const template = Template.fromStack(stack);
 
-    template.resourceCountIs('AWS::SecretsManager::Secret', 1);
+    // The Google key, plus the generated link cookie and cursor signing keys.
+    template.resourceCountIs('AWS::SecretsManager::Secret', 3);
     template.hasResourceProperties('AWS::SecretsManager::Secret', {
       Description: 'Google API Key',
     });
+    template.hasResourceProperties('AWS::SecretsManager::Secret', {
+      Description: 'Signing key for link list pagination cursors',
+    });
   });
 });
@@ -0,0 +1,150 @@
+//! Dresses a plain HTTP request up as the event API Gateway would have delivered.
+//!
+//! The handlers read identity, path parameters and the query string from request
+//! extensions, never from the raw request, so those have to be filled in by hand --
+//! including an authorizer context in exactly the shape `owner_from_request` expects
+//! for the route's authorizer.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use bytes::Bytes;
+use lambda_http::aws_lambda_events::apigw::{
+    ApiGatewayRequestAuthorizer, ApiGatewayRequestAuthorizerJwtDescription,
+    ApiGatewayV2httpRequestContext,
+};
+use lambda_http::http::request::Parts;
+use lambda_http::request::RequestContext;
+use lambda_http::{Body, Request, RequestExt};
+use shared::auth::Scope;
+
+use crate::routes::{Authorizer, Route};
+
+/// Who every request is made as. There is no sign-in locally.
+pub struct DevIdentity {
+    pub owner_id: String,
+    pub email: String,
+}
+
+pub fn lambda_request(
+    parts: Parts,
+    body: Bytes,
+    route: &Route,
+    identity: &DevIdentity,
+    source_ip: IpAddr,
+) -> Request {
+    let path = parts.uri.path().to_string();
+    let query = parts.uri.query().map(query_map).unwrap_or_default();
+
+    let mut ctx = ApiGatewayV2httpRequestContext::default();
+    ctx.stage = Some("$default".to_string());
+    ctx.http.method = parts.method.clone();
+    ctx.http.path = Some(path.clone());
+    ctx.http.source_ip = Some(source_ip.to_string());
+    ctx.authorizer = route.authorizer().map(|kind| authorizer_context(kind, identity));
+
+    let body = match String::from_utf8(body.to_vec()) {
+        Ok(text) if text.is_empty() => Body::Empty,
+        Ok(text) => Body::Text(text),
+        Err(e) => Body::Binary(e.into_bytes()),
+    };
+    let path_parameters: HashMap<String, String> = route
+        .link_id()
+        .map(|id| HashMap::from([("linkId".to_string(), id.to_string())]))
+        .unwrap_or_default();
+
+    Request::from_parts(parts, body)
+        .with_raw_http_path(path)
+        .with_query_string_parameters(query)
+        .with_path_parameters(path_parameters)
+        .with_request_context(RequestContext::ApiGatewayV2(ctx))
+}
+
+/// What the stack's authorizers put in the context after a successful sign-in.
+fn authorizer_context(kind: Authorizer, identity: &DevIdentity) -> ApiGatewayRequestAuthorizer {
+    let mut auth = ApiGatewayRequestAuthorizer::default();
+    match kind {
+        Authorizer::Lambda => {
+            // As the authorizer answers a JWT: a session holds every scope.
+            auth.fields.insert("ownerId".to_string(), serde_json::json!(identity.owner_id));
+            auth.fields.insert("authMethod".to_string(), serde_json::json!("jwt"));
+            auth.fields.insert("scopes".to_string(), serde_json::json!(Scope::join(&Scope::ALL)));
+        }
+        Authorizer::UserPool => {
+            let mut jwt = ApiGatewayRequestAuthorizerJwtDescription::default();
+            jwt.claims.insert("sub".to_string(), identity.owner_id.clone());
+            jwt.claims.insert("email".to_string(), identity.email.clone());
+            auth.jwt = Some(jwt);
+        }
+    }
+    auth
+}
+
+/// Repeated names keep every value, as API Gateway's `queryStringParameters` do.
+fn query_map(query: &str) -> HashMap<String, Vec<String>> {
+    let mut map: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+        map.entry(name.into_owned()).or_default().push(value.into_owned());
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::route;
+    use shared::auth::{auth_method, has_scope, owner_from_request};
+
+    fn identity() -> DevIdentity {
+        DevIdentity {
+            owner_id: "dev-user".to_string(),
+            email: "dev@localhost".to_string(),
+        }
+    }
+
+    fn request(method: &str, uri: &str) -> Request {
+        let (parts, _) = lambda_http::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+            .into_parts();
+        let path = parts.uri.path().to_string();
+        let route = route(method, &path, None);
+        lambda_request(parts, Bytes::new(), &route, &identity(), "127.0.0.1".parse().unwrap())
+    }
+
+    #[test]
+    fn links_routes_get_the_lambda_authorizer_shape() {
+        let event = request("POST", "/api/links");
+        assert_eq!(owner_from_request(&event).unwrap(), "dev-user");
+        assert_eq!(auth_method(&event).as_deref(), Some("jwt"));
+        assert!(Scope::ALL.into_iter().all(|scope| has_scope(&event, scope)));
+    }
+
+    #[test]
+    fn keys_routes_get_the_user_pool_shape() {
+        let event = request("GET", "/api/keys");
+        assert_eq!(owner_from_request(&event).unwrap(), "dev-user");
+        let Some(RequestContext::ApiGatewayV2(ctx)) = event.request_context_ref() else {
+            panic!("expected an HTTP API context");
+        };
+        assert!(ctx.authorizer.as_ref().unwrap().fields.is_empty());
+    }
+
+    /// The redirect path has no authorizer in the stack, so it gets no identity here.
+    #[test]
+    fn short_links_carry_no_identity() {
+        let event = request("GET", "/abc123");
+        assert!(owner_from_request(&event).is_err());
+        assert_eq!(event.path_parameters_ref().and_then(|p| p.first("linkId")), Some("abc123"));
+    }
+
+    #[test]
+    fn query_string_is_parsed_into_the_extension() {
+        let event = request("GET", "/api/links?days=7&a=1&a=2");
+        let query = event.query_string_parameters();
+        assert_eq!(query.first("days"), Some("7"));
+        assert_eq!(query.all("a"), Some(vec!["1", "2"]));
+    }
+}
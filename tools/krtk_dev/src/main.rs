@@ -0,0 +1,297 @@
+//! `krtk-dev`: the site and its API on one local port.
+//!
+//! Serves `website/` and calls the Lambda handlers in-process, each with the request
+//! dressed up as API Gateway would deliver it (see [`gateway`]). Data goes to DynamoDB
+//! Local; Secrets Manager and Safe Browsing are answered by this server itself.
+
+mod gateway;
+mod routes;
+mod stubs;
+mod tables;
+
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use aws_sdk_dynamodb::config::Credentials;
+use bytes::Bytes;
+use clap::Parser;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use lambda_http::http::{Response, StatusCode};
+use lambda_http::{Body, Error, IntoResponse};
+use manage_keys::KeyStore;
+use shared::audit::AuditLog;
+use shared::clicks::ClickHistory;
+use shared::core::UrlShortener;
+use shared::reputation::ReputationPolicy;
+use shared::safe_browsing::SafeBrowsing;
+use shared::secrets::SecretCache;
+use shared::url_info::UrlInfo;
+use tokio::net::TcpListener;
+use visit_link::CookieKey;
+
+use gateway::DevIdentity;
+use routes::Route;
+use tables::TableNames;
+
+/// Any ID will do: the Secrets Manager stub answers every secret with the same value.
+const SECRET_ID: &str = "krtk-dev";
+
+#[derive(Parser)]
+#[command(name = "krtk-dev", about = "Serve the site and its API on localhost")]
+struct Args {
+    /// Port to listen on
+    #[arg(long, default_value_t = 3000)]
+    port: u16,
+
+    /// DynamoDB Local endpoint
+    #[arg(long, default_value = "http://localhost:8000")]
+    dynamodb_endpoint: String,
+
+    /// Prefix for the table names; tables that do not exist yet are created
+    #[arg(long, default_value = "krtk-dev")]
+    table_prefix: String,
+
+    /// Owner ID every API request is made as
+    #[arg(long, default_value = "dev-user")]
+    owner: String,
+
+    /// Email the page shows as signed in
+    #[arg(long, default_value = "dev@localhost")]
+    email: String,
+
+    /// Directory holding the frontend
+    #[arg(long, default_value = "website")]
+    site_dir: PathBuf,
+}
+
+struct App {
+    site_dir: PathBuf,
+    identity: DevIdentity,
+    shortener: UrlShortener,
+    url_info: UrlInfo,
+    click_history: ClickHistory,
+    key_store: KeyStore,
+    cookie_key: CookieKey,
+    cursor_key: SecretCache,
+    audit_log: AuditLog,
+    reputation: ReputationPolicy,
+}
+
+fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt::init();
+
+    let args = Args::parse();
+    let origin = format!("http://localhost:{}", args.port);
+
+    // SAFETY: nothing else is running yet; the runtime, and with it every other thread,
+    // only starts below.
+    unsafe {
+        std::env::set_var("SAFE_BROWSING_ENDPOINT", format!("{origin}/__dev/safebrowsing"));
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(serve(args, origin))
+}
+
+async fn serve(args: Args, origin: String) -> Result<(), Error> {
+    // DynamoDB Local accepts any credentials, but the SDK still wants some to sign with.
+    let config = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12())
+        .region(aws_config::Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("krtk-dev", "krtk-dev", None, None, "krtk-dev"))
+        .load()
+        .await;
+    let dynamodb_client = aws_sdk_dynamodb::Client::from_conf(
+        aws_sdk_dynamodb::config::Builder::from(&config)
+            .endpoint_url(&args.dynamodb_endpoint)
+            .build(),
+    );
+    let secrets_client = aws_sdk_secretsmanager::Client::from_conf(
+        aws_sdk_secretsmanager::config::Builder::from(&config)
+            .endpoint_url(&origin)
+            .build(),
+    );
+
+    let names = TableNames::with_prefix(&args.table_prefix);
+    println!("Using DynamoDB Local at {}", args.dynamodb_endpoint);
+    tables::ensure_tables(&dynamodb_client, &names).await?;
+
+    let http_client = shared::Client::builder()
+        .timeout(std::time::Duration::from_secs(2))
+        .build()?;
+
+    // The same configuration as deployed: `BLOCKED_DOMAINS` and friends work here too.
+    let reputation = ReputationPolicy::from_env(SafeBrowsing::new(
+        SecretCache::new(secrets_client.clone(), SECRET_ID),
+        http_client.clone(),
+    ))?;
+
+    let app = Arc::new(App {
+        site_dir: args.site_dir,
+        identity: DevIdentity {
+            owner_id: args.owner,
+            email: args.email,
+        },
+        shortener: UrlShortener::new(&names.links, "localhost", dynamodb_client.clone()),
+        url_info: UrlInfo::new(http_client),
+        click_history: ClickHistory::new(&names.clicks, dynamodb_client.clone()),
+        key_store: KeyStore::new(&names.keys, dynamodb_client.clone()),
+        cookie_key: CookieKey::new(secrets_client.clone(), SECRET_ID),
+        cursor_key: SecretCache::new(secrets_client.clone(), SECRET_ID),
+        audit_log: AuditLog::new(&names.audit, dynamodb_client),
+        reputation,
+    });
+
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], args.port))).await?;
+    println!("krtk-dev listening on {origin}");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let app = app.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle(app.clone(), req, peer.ip()));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::warn!("connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle(
+    app: Arc<App>,
+    req: hyper::Request<Incoming>,
+    peer: IpAddr,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let (parts, body) = req.into_parts();
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            tracing::warn!("failed to read request body: {e}");
+            return Ok(plain(StatusCode::BAD_REQUEST, "unreadable body"));
+        }
+    };
+
+    let amz_target = parts.headers.get("x-amz-target").and_then(|v| v.to_str().ok());
+    let route = routes::route(parts.method.as_str(), parts.uri.path(), amz_target);
+    println!("{} {} -> {route:?}", parts.method, parts.uri);
+
+    let response = match &route {
+        Route::Site(path) => site_file(&app.site_dir, path).await,
+        Route::AuthConfig => with_type(
+            "text/javascript",
+            stubs::auth_config_js(&app.identity).into(),
+        ),
+        Route::SafeBrowsing => with_type(
+            "application/json",
+            stubs::threat_matches(&body).to_string().into(),
+        ),
+        Route::SecretsManager => with_type(
+            "application/x-amz-json-1.1",
+            stubs::get_secret_value(&body).to_string().into(),
+        ),
+        Route::NotFound => plain(StatusCode::NOT_FOUND, "not found"),
+        _ => {
+            let event = gateway::lambda_request(parts, body, &route, &app.identity, peer);
+            let response = match route {
+                Route::CreateLink => respond(
+                    create_link::function_handler(
+                        &app.shortener,
+                        &app.url_info,
+                        &app.audit_log,
+                        &app.reputation,
+                        event,
+                    )
+                    .await,
+                )
+                .await,
+                Route::GetLinks => respond(
+                    get_links::function_handler(&app.shortener, &app.click_history, &app.cursor_key, event).await,
+                )
+                .await,
+                Route::DeleteLink(_) => respond(
+                    delete_link::function_handler(&app.shortener, &app.audit_log, event).await,
+                )
+                .await,
+                Route::Keys => respond(
+                    manage_keys::function_handler(&app.key_store, &app.audit_log, event).await,
+                )
+                .await,
+                Route::Audit => respond(get_audit::function_handler(&app.audit_log, event).await).await,
+                Route::Visit(_) => respond(
+                    visit_link::function_handler(&app.shortener, &app.cookie_key, event).await,
+                )
+                .await,
+                _ => unreachable!("every other route is answered above"),
+            };
+            let (parts, body) = response.into_parts();
+            Response::from_parts(parts, Full::new(Bytes::from(body.to_vec())))
+        }
+    };
+    Ok(response)
+}
+
+/// A handler error would be a 500 from the Lambda runtime; keep the message visible here.
+async fn respond<R: IntoResponse>(result: Result<R, Error>) -> Response<Body> {
+    match result {
+        Ok(response) => response.into_response().await,
+        Err(e) => {
+            tracing::error!("handler failed: {e}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::Text(e.to_string()))
+                .expect("static response parts are valid")
+        }
+    }
+}
+
+async fn site_file(site_dir: &Path, path: &str) -> Response<Full<Bytes>> {
+    // The router only hands over paths under the site's own prefixes, but the rest of
+    // the path is still the caller's.
+    let relative = Path::new(path);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return plain(StatusCode::NOT_FOUND, "not found");
+    }
+    match tokio::fs::read(site_dir.join(relative)).await {
+        Ok(contents) => with_type(content_type(relative), contents.into()),
+        Err(_) => plain(StatusCode::NOT_FOUND, "not found"),
+    }
+}
+
+/// The bucket deployment sets these from the extension; `terms`, `privacy` and
+/// `auth/callback` have none and are pages.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("js") => "text/javascript",
+        Some("css") => "text/css",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("svg") => "image/svg+xml",
+        Some("json") => "application/json",
+        _ => "text/html",
+    }
+}
+
+fn with_type(content_type: &str, body: Bytes) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header("content-type", content_type)
+        .body(Full::new(body))
+        .expect("static response parts are valid")
+}
+
+fn plain(status: StatusCode, message: &'static str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain")
+        .body(Full::new(Bytes::from_static(message.as_bytes())))
+        .expect("static response parts are valid")
+}
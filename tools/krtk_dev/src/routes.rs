@@ -0,0 +1,152 @@
+//! Maps a request onto what would have answered it in the stack.
+//!
+//! Mirrors the CloudFront behaviours and API Gateway routes in `lib/krtk-rs-stack.ts`:
+//! the site paths go to the bucket, `/api/*` to the handlers, and any other single
+//! segment to `visit_link`. Keep the two in step, or the page works here and not there.
+
+/// Which authorizer fronts a route in the stack, and so which context shape it gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Authorizer {
+    /// The custom REQUEST authorizer on `/api/links`: `ownerId`, `authMethod`, `scopes`.
+    Lambda,
+    /// The native user pool authorizer on `/api/keys` and `/api/audit`: JWT claims.
+    UserPool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Route {
+    /// A file under the site directory, by its path relative to it.
+    Site(String),
+    /// The Cognito config, generated so the page comes up signed in as the dev owner.
+    AuthConfig,
+    CreateLink,
+    GetLinks,
+    DeleteLink(String),
+    /// Everything under `/api/keys`; `manage_keys` does its own routing.
+    Keys,
+    Audit,
+    Visit(String),
+    /// Stands in for Google's `threatMatches:find`.
+    SafeBrowsing,
+    /// Stands in for Secrets Manager, which the SDK is pointed at this server for.
+    SecretsManager,
+    NotFound,
+}
+
+impl Route {
+    pub fn authorizer(&self) -> Option<Authorizer> {
+        match self {
+            Self::CreateLink | Self::GetLinks | Self::DeleteLink(_) => Some(Authorizer::Lambda),
+            Self::Keys | Self::Audit => Some(Authorizer::UserPool),
+            _ => None,
+        }
+    }
+
+    /// The `{linkId}` path parameter API Gateway would have extracted.
+    pub fn link_id(&self) -> Option<&str> {
+        match self {
+            Self::DeleteLink(id) | Self::Visit(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/// `amz_target` is the `X-Amz-Target` header: the SDK posts every Secrets Manager call
+/// to `/` and names the operation there, so the path alone cannot tell it apart.
+pub fn route(method: &str, path: &str, amz_target: Option<&str>) -> Route {
+    if method == "POST" && amz_target.is_some_and(|t| t.starts_with("secretsmanager.")) {
+        return Route::SecretsManager;
+    }
+
+    match (method, path) {
+        ("POST", "/__dev/safebrowsing") => Route::SafeBrowsing,
+
+        ("GET", "/" | "/index.html") => Route::Site("index.html".to_string()),
+        ("GET", "/assets/auth-config.js") => Route::AuthConfig,
+        ("GET", "/terms" | "/privacy") => Route::Site(path[1..].to_string()),
+        ("GET", p) if p.starts_with("/assets/") || p.starts_with("/auth/") => {
+            Route::Site(p[1..].to_string())
+        }
+
+        ("POST", "/api/links") => Route::CreateLink,
+        ("GET", "/api/links") => Route::GetLinks,
+        ("DELETE", p) => match p.strip_prefix("/api/links/") {
+            Some(id) if !id.is_empty() && !id.contains('/') => Route::DeleteLink(id.to_string()),
+            _ if is_keys_path(p) => Route::Keys,
+            _ => Route::NotFound,
+        },
+        (_, p) if is_keys_path(p) => Route::Keys,
+        ("GET", "/api/audit") => Route::Audit,
+        (_, p) if p == "/api" || p.starts_with("/api/") => Route::NotFound,
+
+        ("GET" | "POST", p) => match p.strip_prefix('/') {
+            Some(id) if !id.is_empty() && !id.contains('/') => Route::Visit(id.to_string()),
+            _ => Route::NotFound,
+        },
+        _ => Route::NotFound,
+    }
+}
+
+fn is_keys_path(path: &str) -> bool {
+    path == "/api/keys" || path.starts_with("/api/keys/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn site_paths_go_to_the_site_directory() {
+        assert_eq!(route("GET", "/", None), Route::Site("index.html".into()));
+        assert_eq!(route("GET", "/assets/main.js", None), Route::Site("assets/main.js".into()));
+        assert_eq!(route("GET", "/auth/callback", None), Route::Site("auth/callback".into()));
+        assert_eq!(route("GET", "/terms", None), Route::Site("terms".into()));
+    }
+
+    /// The checked-in config carries placeholders; the generated one must win.
+    #[test]
+    fn auth_config_is_generated_rather_than_served() {
+        assert_eq!(route("GET", "/assets/auth-config.js", None), Route::AuthConfig);
+    }
+
+    #[test]
+    fn api_routes_pick_their_authorizer() {
+        assert_eq!(route("POST", "/api/links", None).authorizer(), Some(Authorizer::Lambda));
+        assert_eq!(route("DELETE", "/api/links/abc", None), Route::DeleteLink("abc".into()));
+        assert_eq!(route("POST", "/api/keys/k1/rotate", None).authorizer(), Some(Authorizer::UserPool));
+        assert_eq!(route("DELETE", "/api/keys/k1", None), Route::Keys);
+        assert_eq!(route("GET", "/api/audit", None).authorizer(), Some(Authorizer::UserPool));
+    }
+
+    /// As behind CloudFront, an unknown `/api/*` path is not a short link.
+    #[test]
+    fn unknown_api_paths_are_not_short_links() {
+        assert_eq!(route("GET", "/api/nope", None), Route::NotFound);
+        assert_eq!(route("GET", "/api", None), Route::NotFound);
+    }
+
+    #[test]
+    fn a_single_segment_is_a_short_link() {
+        let visit = route("GET", "/abc123", None);
+        assert_eq!(visit, Route::Visit("abc123".into()));
+        assert_eq!(visit.link_id(), Some("abc123"));
+        assert_eq!(visit.authorizer(), None);
+        assert_eq!(route("POST", "/abc123", None), Route::Visit("abc123".into()));
+        assert_eq!(route("GET", "/abc/def", None), Route::NotFound);
+    }
+
+    /// The `+` preview suffix reaches `visit_link` untouched; it strips it itself.
+    #[test]
+    fn a_preview_suffix_is_still_a_short_link() {
+        assert_eq!(route("GET", "/abc123+", None), Route::Visit("abc123+".into()));
+    }
+
+    #[test]
+    fn secrets_manager_is_recognised_by_its_target_header() {
+        assert_eq!(
+            route("POST", "/", Some("secretsmanager.GetSecretValue")),
+            Route::SecretsManager
+        );
+        assert_eq!(route("POST", "/", None), Route::NotFound);
+    }
+}
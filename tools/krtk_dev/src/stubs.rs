@@ -0,0 +1,118 @@
+//! Local stand-ins for the services the handlers call that have no local edition.
+
+use serde_json::{Value, json};
+
+use crate::gateway::DevIdentity;
+
+/// Returned for every secret: the Safe Browsing key goes unchecked by the stub, and the
+/// link cookie and cursor keys only have to be stable for as long as the server runs.
+const DEV_SECRET: &str = "krtk-dev-secret";
+
+/// Google's own test host: any URL on it is flagged, so the rejection path can be tried
+/// without a real malware link. Everything else passes.
+const FLAGGED_HOST: &str = "testsafebrowsing.appspot.com";
+
+/// Answers `GetSecretValue` for whatever `SecretId` was asked for.
+pub fn get_secret_value(request: &[u8]) -> Value {
+    let secret_id = serde_json::from_slice::<Value>(request)
+        .ok()
+        .and_then(|body| body["SecretId"].as_str().map(str::to_string))
+        .unwrap_or_default();
+    json!({
+        "ARN": secret_id,
+        "Name": secret_id,
+        "SecretString": DEV_SECRET,
+        "VersionId": "krtk-dev",
+    })
+}
+
+/// Answers `threatMatches:find`, in the shape Google does: `{}` when nothing matched.
+pub fn threat_matches(request: &[u8]) -> Value {
+    let body: Value = serde_json::from_slice(request).unwrap_or_default();
+    let matches: Vec<Value> = body["threatInfo"]["threatEntries"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry["url"].as_str())
+        .filter(|url| is_flagged(url))
+        .map(|url| {
+            json!({
+                "threatType": "SOCIAL_ENGINEERING",
+                "platformType": "ANY_PLATFORM",
+                "threat": { "url": url },
+            })
+        })
+        .collect();
+
+    if matches.is_empty() {
+        json!({})
+    } else {
+        json!({ "matches": matches })
+    }
+}
+
+fn is_flagged(url: &str) -> bool {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#']).next() == Some(FLAGGED_HOST)
+}
+
+/// Replaces `website/assets/auth-config.js`. Besides the config, it seeds the tokens
+/// `auth.js` looks for, so the page takes itself to be signed in and never heads off to
+/// a Hosted UI that does not exist. The tokens are never checked: every request already
+/// carries the dev identity.
+pub fn auth_config_js(identity: &DevIdentity) -> String {
+    let claims = json!({ "sub": identity.owner_id, "email": identity.email });
+    format!(
+        r#"// Generated by krtk-dev.
+window.KRTK_AUTH = {{
+  userPoolId: 'local_krtkdev',
+  clientId: 'krtk-dev',
+  authDomain: window.location.host,
+  region: 'local',
+  redirectUri: window.location.origin + '/auth/callback',
+  logoutUri: window.location.origin + '/',
+}};
+(function () {{
+  sessionStorage.setItem('krtk_access_token', 'krtk-dev');
+  sessionStorage.setItem('krtk_id_token', 'krtk-dev.' + btoa(JSON.stringify({claims})) + '.krtk-dev');
+  sessionStorage.setItem('krtk_refresh_token', 'krtk-dev');
+  sessionStorage.setItem('krtk_expires_at', String(Date.now() + 365 * 24 * 60 * 60 * 1000));
+}})();
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_test_host_is_flagged() {
+        let request = json!({
+            "threatInfo": { "threatEntries": [
+                { "url": "https://testsafebrowsing.appspot.com/s/phishing.html" },
+                { "url": "https://example.com/?next=testsafebrowsing.appspot.com" },
+            ]}
+        });
+        let response = threat_matches(request.to_string().as_bytes());
+        let matches = response["matches"].as_array().unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            matches[0]["threat"]["url"],
+            "https://testsafebrowsing.appspot.com/s/phishing.html"
+        );
+    }
+
+    #[test]
+    fn a_clean_batch_is_an_empty_object() {
+        let request = json!({ "threatInfo": { "threatEntries": [{ "url": "https://example.com/" }] } });
+        assert_eq!(threat_matches(request.to_string().as_bytes()), json!({}));
+    }
+
+    #[test]
+    fn every_secret_gets_the_dev_value() {
+        let response = get_secret_value(br#"{"SecretId":"arn:aws:secretsmanager:local:link-cookie"}"#);
+        assert_eq!(response["SecretString"], DEV_SECRET);
+        assert_eq!(response["ARN"], "arn:aws:secretsmanager:local:link-cookie");
+    }
+}
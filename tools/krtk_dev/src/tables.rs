@@ -0,0 +1,140 @@
+//! Creates the tables the mounted handlers use in DynamoDB Local, if missing.
+//!
+//! Keys and indexes only -- copied from `lib/krtk-rs-stack.ts`, which stays the source
+//! of truth. TTL and streams are left out: nothing served locally depends on either.
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::error::DisplayErrorContext;
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, BillingMode, GlobalSecondaryIndex, KeySchemaElement, KeyType,
+    Projection, ProjectionType, ScalarAttributeType,
+};
+use lambda_http::Error;
+
+pub struct TableNames {
+    pub links: String,
+    pub clicks: String,
+    pub keys: String,
+    pub audit: String,
+}
+
+impl TableNames {
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            links: format!("{prefix}-links"),
+            clicks: format!("{prefix}-clicks"),
+            keys: format!("{prefix}-keys"),
+            audit: format!("{prefix}-audit"),
+        }
+    }
+}
+
+struct TableSpec<'a> {
+    name: &'a str,
+    partition: (&'a str, ScalarAttributeType),
+    sort: Option<(&'a str, ScalarAttributeType)>,
+    index: Option<IndexSpec<'a>>,
+}
+
+struct IndexSpec<'a> {
+    name: &'a str,
+    partition: (&'a str, ScalarAttributeType),
+    sort: (&'a str, ScalarAttributeType),
+}
+
+pub async fn ensure_tables(client: &Client, names: &TableNames) -> Result<(), Error> {
+    use ScalarAttributeType::{N, S};
+
+    let specs = [
+        TableSpec {
+            name: &names.links,
+            partition: ("LinkId", S),
+            sort: None,
+            index: Some(IndexSpec {
+                name: "TimeStampIndex",
+                partition: ("SortKey", S),
+                sort: ("TimeStamp", N),
+            }),
+        },
+        TableSpec {
+            name: &names.clicks,
+            partition: ("Series", S),
+            sort: Some(("BucketStart", N)),
+            index: None,
+        },
+        TableSpec {
+            name: &names.keys,
+            partition: ("KeyHash", S),
+            sort: None,
+            index: Some(IndexSpec {
+                name: "OwnerIndex",
+                partition: ("OwnerId", S),
+                sort: ("CreatedAt", N),
+            }),
+        },
+        TableSpec {
+            name: &names.audit,
+            partition: ("Owner", S),
+            sort: Some(("EventId", S)),
+            index: None,
+        },
+    ];
+
+    for spec in specs {
+        create_table(client, spec).await?;
+    }
+    Ok(())
+}
+
+async fn create_table(client: &Client, spec: TableSpec<'_>) -> Result<(), Error> {
+    let mut attributes = vec![spec.partition.clone()];
+    let mut key_schema = vec![key(spec.partition.0, KeyType::Hash)?];
+    if let Some(sort) = &spec.sort {
+        attributes.push(sort.clone());
+        key_schema.push(key(sort.0, KeyType::Range)?);
+    }
+
+    let mut request = client
+        .create_table()
+        .table_name(spec.name)
+        .billing_mode(BillingMode::PayPerRequest)
+        .set_key_schema(Some(key_schema));
+
+    if let Some(index) = &spec.index {
+        attributes.push(index.partition.clone());
+        attributes.push(index.sort.clone());
+        request = request.global_secondary_indexes(
+            GlobalSecondaryIndex::builder()
+                .index_name(index.name)
+                .key_schema(key(index.partition.0, KeyType::Hash)?)
+                .key_schema(key(index.sort.0, KeyType::Range)?)
+                .projection(Projection::builder().projection_type(ProjectionType::All).build())
+                .build()?,
+        );
+    }
+
+    for (name, kind) in attributes {
+        request = request.attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name(name)
+                .attribute_type(kind)
+                .build()?,
+        );
+    }
+
+    match request.send().await {
+        Ok(_) => {
+            println!("  created {}", spec.name);
+            Ok(())
+        }
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_resource_in_use_exception()) => Ok(()),
+        Err(e) => Err(format!("could not create {}: {}", spec.name, DisplayErrorContext(&e)).into()),
+    }
+}
+
+fn key(name: &str, key_type: KeyType) -> Result<KeySchemaElement, Error> {
+    Ok(KeySchemaElement::builder()
+        .attribute_name(name)
+        .key_type(key_type)
+        .build()?)
+}
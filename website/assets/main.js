@@ -23,6 +23,15 @@ function copyToClipboard(text) {
     });
 }
 
+// Tag chips in the links table narrow the list to that tag, through the same filter
+// form as typing it in, so later pages and refreshes keep it.
+function filterByTag(tag) {
+    var input = document.getElementById('tag-filter');
+    if (!input) return;
+    input.value = tag;
+    htmx.trigger('#link-filters', 'change');
+}
+
 function showNotification(message) {
     var notification = document.createElement('div');
     notification.className = 'fixed bottom-5 right-5 bg-gray-800 dark:bg-gray-700 text-white px-6 py-3 rounded-lg shadow-lg z-50';